@@ -38,6 +38,12 @@ fn place_setup<P: AsRef<Path>>(input_path: P) -> (TempDir, BuildCommand) {
         watch: false,
         plugin: None,
         output,
+        profile: None,
+        defines: Vec::new(),
+        base: None,
+        report: None,
+        source_mapping: None,
+        on_build: None,
     };
 
     (dir, options)
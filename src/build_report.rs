@@ -0,0 +1,462 @@
+//! Summaries of built trees for `rojo build --report`, and the budgets that
+//! projects can set to catch parts of their tree growing past the limits that
+//! Roblox enforces before they're published.
+//!
+//! Sizes in reports are estimates of how many bytes instances take up when
+//! serialized, counting their names and property values. They're meant for
+//! comparing parts of the tree with each other, not for predicting exact file
+//! sizes. Budgets on size are checked against the actual serialized size
+//! instead, since they fail the build.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use memofs::Vfs;
+use rbx_dom_weak::types::{Ref, Variant};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    path_serializer::display_absolute,
+    snapshot::{InstigatingSource, RojoTree},
+    snapshot_middleware::dir_init_file,
+    syncback::inst_path,
+    Project,
+};
+
+/// How many of the largest scripts and subtrees are listed in reports.
+const MAX_LISTED: usize = 10;
+
+/// The size given to property values that aren't strings or lists of things.
+const FIXED_VALUE_SIZE: usize = 16;
+
+/// Limits on part of a project's tree that fail the build when they're
+/// exceeded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Budget {
+    /// The instance the budget applies to, given as the names of each
+    /// instance from the root separated by slashes, like
+    /// `ReplicatedStorage/Shared`. Applies to the whole tree if left out.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+
+    /// The most instances there can be in the subtree, including the instance
+    /// the budget applies to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_instances: Option<usize>,
+
+    /// The longest, in bytes, that the `Source` of any script in the subtree
+    /// can be.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_script_size: Option<usize>,
+
+    /// The largest that the subtree can be, in bytes, when it's serialized as
+    /// a binary model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    pub instance_count: usize,
+    /// The number of instances of each class.
+    pub instance_counts: BTreeMap<String, usize>,
+    pub scripts: ScriptsReport,
+    pub largest_subtrees: Vec<SubtreeReport>,
+    pub budget_violations: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptsReport {
+    pub count: usize,
+    pub total_source_size: usize,
+    pub largest: Vec<ScriptReport>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptReport {
+    pub path: String,
+    pub class_name: String,
+    pub source_size: usize,
+    /// The file the script came from, relative to the project's folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtreeReport {
+    pub path: String,
+    pub class_name: String,
+    pub instance_count: usize,
+    pub size: usize,
+    /// The file or project that the subtree came from, relative to the
+    /// project's folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SubtreeStats {
+    instance_count: usize,
+    size: usize,
+}
+
+impl BuildReport {
+    /// Summarizes the given tree. Files are shown relative to
+    /// `project_folder` when they're inside of it.
+    pub fn new(tree: &RojoTree, vfs: &Vfs, project_folder: &Path, budgets: &[Budget]) -> Self {
+        let stats = subtree_stats(tree);
+        let root_id = tree.get_root_id();
+        let file = |id: Ref| file_for_instance(tree, vfs, id, project_folder);
+
+        let mut instance_counts = BTreeMap::new();
+        let mut scripts = Vec::new();
+        for instance in tree.descendants(root_id) {
+            *instance_counts
+                .entry(instance.class_name().to_string())
+                .or_default() += 1;
+
+            if let Some(source_size) = source_size(instance.properties()) {
+                scripts.push((instance.id(), source_size));
+            }
+        }
+
+        let total_source_size = scripts.iter().map(|(_, size)| size).sum();
+        scripts.sort_by_key(|&(_, size)| Reverse(size));
+        let largest_scripts = scripts
+            .iter()
+            .take(MAX_LISTED)
+            .map(|&(id, source_size)| ScriptReport {
+                path: inst_path(tree.inner(), id),
+                class_name: tree.get_instance(id).unwrap().class_name().to_string(),
+                source_size,
+                file: file(id),
+            })
+            .collect();
+
+        // Subtrees of the same size are sorted by path so that reports don't
+        // change from build to build.
+        let mut subtrees: Vec<_> = stats
+            .iter()
+            .filter(|(&id, _)| id != root_id)
+            .map(|(&id, stats)| (id, inst_path(tree.inner(), id), stats))
+            .collect();
+        subtrees.sort_by(|(_, a_path, a), (_, b_path, b)| {
+            b.size.cmp(&a.size).then_with(|| a_path.cmp(b_path))
+        });
+        let largest_subtrees = subtrees
+            .into_iter()
+            .take(MAX_LISTED)
+            .map(|(id, path, stats)| SubtreeReport {
+                path,
+                class_name: tree.get_instance(id).unwrap().class_name().to_string(),
+                instance_count: stats.instance_count,
+                size: stats.size,
+                file: file(id),
+            })
+            .collect();
+
+        BuildReport {
+            instance_count: stats[&root_id].instance_count,
+            instance_counts,
+            scripts: ScriptsReport {
+                count: scripts.len(),
+                total_source_size,
+                largest: largest_scripts,
+            },
+            largest_subtrees,
+            budget_violations: check_budgets_with_stats(tree, budgets, &stats),
+        }
+    }
+}
+
+/// Checks the given tree against each of the budgets, returning a description
+/// of each way that it's over them.
+pub fn check_budgets(tree: &RojoTree, budgets: &[Budget]) -> Vec<String> {
+    if budgets.is_empty() {
+        return Vec::new();
+    }
+
+    check_budgets_with_stats(tree, budgets, &subtree_stats(tree))
+}
+
+fn check_budgets_with_stats(
+    tree: &RojoTree,
+    budgets: &[Budget],
+    stats: &HashMap<Ref, SubtreeStats>,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for budget in budgets {
+        let label = match budget.path.as_str() {
+            "" => "the whole tree".to_owned(),
+            path => format!("`{path}`"),
+        };
+        let Some(id) = find_instance(tree, &budget.path) else {
+            violations.push(format!(
+                "The budget for {label} can't be checked, because there's no instance there"
+            ));
+            continue;
+        };
+        let stats = stats[&id];
+
+        if let Some(max_instances) = budget.max_instances {
+            if stats.instance_count > max_instances {
+                violations.push(format!(
+                    "The budget for {label} allows {max_instances} instances, but it has {}",
+                    stats.instance_count
+                ));
+            }
+        }
+
+        if let Some(max_size) = budget.max_size {
+            match serialized_size(tree, id) {
+                Ok(size) if size > max_size => violations.push(format!(
+                    "The budget for {label} allows {max_size} bytes, but it's {size} bytes \
+                     when serialized"
+                )),
+                Ok(_) => {}
+                Err(err) => violations.push(format!(
+                    "The budget for {label} can't be checked, because it couldn't be \
+                     serialized: {err}"
+                )),
+            }
+        }
+
+        if let Some(max_script_size) = budget.max_script_size {
+            for instance in tree.descendants(id) {
+                let Some(source_size) = source_size(instance.properties()) else {
+                    continue;
+                };
+                if source_size > max_script_size {
+                    violations.push(format!(
+                        "The budget for {label} allows scripts of {max_script_size} bytes, \
+                         but `{}` is {source_size}",
+                        inst_path(tree.inner(), instance.id()),
+                    ));
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+/// Returns the instance count and size of every subtree in the tree.
+fn subtree_stats(tree: &RojoTree) -> HashMap<Ref, SubtreeStats> {
+    fn visit(tree: &RojoTree, id: Ref, stats: &mut HashMap<Ref, SubtreeStats>) -> SubtreeStats {
+        let instance = tree.get_instance(id).unwrap();
+        let mut subtree = SubtreeStats {
+            instance_count: 1,
+            size: instance.name().len()
+                + instance.class_name().len()
+                + instance
+                    .properties()
+                    .iter()
+                    .map(|(key, value)| key.len() + value_size(value))
+                    .sum::<usize>(),
+        };
+
+        for &child_id in instance.children() {
+            let child = visit(tree, child_id, stats);
+            subtree.instance_count += child.instance_count;
+            subtree.size += child.size;
+        }
+
+        stats.insert(id, subtree);
+        subtree
+    }
+
+    let mut stats = HashMap::new();
+    visit(tree, tree.get_root_id(), &mut stats);
+    stats
+}
+
+/// Returns how many bytes the subtree at `id` takes up when it's serialized as
+/// a binary model. A DataModel is measured by its children, like places are
+/// built.
+fn serialized_size(tree: &RojoTree, id: Ref) -> Result<usize, rbx_binary::EncodeError> {
+    let instance = tree.get_instance(id).unwrap();
+    let ids = if instance.class_name() == "DataModel" {
+        instance.children()
+    } else {
+        &[id][..]
+    };
+
+    let mut contents = Vec::new();
+    rbx_binary::to_writer(&mut contents, tree.inner(), ids)?;
+    Ok(contents.len())
+}
+
+fn value_size(value: &Variant) -> usize {
+    match value {
+        Variant::String(value) => value.len(),
+        Variant::BinaryString(value) => AsRef::<[u8]>::as_ref(value).len(),
+        Variant::ContentId(value) => value.as_str().len(),
+        Variant::SharedString(value) => value.data().len(),
+        Variant::Tags(tags) => tags.iter().map(str::len).sum(),
+        Variant::Attributes(attributes) => attributes
+            .iter()
+            .map(|(key, value)| key.len() + value_size(value))
+            .sum(),
+        _ => FIXED_VALUE_SIZE,
+    }
+}
+
+/// Returns the length of the instance's `Source`, if it has one.
+fn source_size(properties: &rbx_dom_weak::UstrMap<Variant>) -> Option<usize> {
+    match properties.get(&"Source".into()) {
+        Some(Variant::String(source)) => Some(source.len()),
+        _ => None,
+    }
+}
+
+fn find_instance(tree: &RojoTree, path: &str) -> Option<Ref> {
+    let mut id = tree.get_root_id();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let instance = tree.get_instance(id)?;
+        id = instance
+            .children()
+            .iter()
+            .copied()
+            .find(|&child_id| tree.get_instance(child_id).unwrap().name() == name)?;
+    }
+
+    Some(id)
+}
+
+/// Returns the file or project that an instance came from, relative to
+/// `project_folder` when it's inside of it.
+pub fn file_for_instance(
+    tree: &RojoTree,
+    vfs: &Vfs,
+    id: Ref,
+    project_folder: &Path,
+) -> Option<String> {
+    let path = source_file(tree, vfs, id)?;
+
+    Some(display_absolute(
+        path.strip_prefix(project_folder).unwrap_or(&path),
+    ))
+}
+
+/// Returns the file or project that an instance came from. Instances made
+/// from a directory with an init file, like init scripts, came from the init
+/// file rather than the directory.
+pub fn source_file(tree: &RojoTree, vfs: &Vfs, id: Ref) -> Option<PathBuf> {
+    let metadata = tree.get_metadata(id)?;
+    let path = match metadata.instigating_source.as_ref()? {
+        // Project nodes with a `$path` are made from that path, which is the
+        // first of their relevant paths.
+        InstigatingSource::ProjectNode { node, .. } if node.path.is_some() => {
+            metadata.relevant_paths.first()?
+        }
+        // The root of a project is made from the project file, but if it has
+        // a `$path` that comes first in its relevant paths too.
+        InstigatingSource::Path(path) if Project::is_project_file(path) => {
+            match metadata.relevant_paths.first() {
+                Some(first) if !Project::is_project_file(first) => first,
+                _ => path,
+            }
+        }
+        source => source.path(),
+    };
+
+    match dir_init_file(&metadata.context.init_file_names, vfs, path) {
+        Ok(Some(init_path)) => Some(init_path),
+        _ => Some(path.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::InMemoryFs;
+
+    use crate::snapshot::{InstanceMetadata, InstanceSnapshot};
+
+    fn script(name: &str, source: &str) -> InstanceSnapshot {
+        InstanceSnapshot::new()
+            .name(name)
+            .class_name("ModuleScript")
+            .property("Source", source)
+            .metadata(
+                InstanceMetadata::new().instigating_source(InstigatingSource::Path(PathBuf::from(
+                    format!("/foo/src/{name}.luau"),
+                ))),
+            )
+    }
+
+    fn tree() -> RojoTree {
+        RojoTree::new(
+            InstanceSnapshot::new()
+                .name("foo")
+                .class_name("Folder")
+                .children([
+                    InstanceSnapshot::new()
+                        .name("Shared")
+                        .class_name("Folder")
+                        .children([script("Big", &"x".repeat(100)), script("Small", "x")]),
+                    InstanceSnapshot::new()
+                        .name("Value")
+                        .class_name("StringValue")
+                        .property("Value", "hello"),
+                ]),
+        )
+    }
+
+    #[test]
+    fn report() {
+        let vfs = Vfs::new(InMemoryFs::new());
+        let report = BuildReport::new(&tree(), &vfs, Path::new("/foo"), &[]);
+
+        insta::assert_yaml_snapshot!(report);
+    }
+
+    #[test]
+    fn budgets() {
+        let budgets = [
+            Budget {
+                path: String::new(),
+                max_instances: Some(10),
+                max_script_size: Some(50),
+                max_size: None,
+            },
+            Budget {
+                path: "Shared".to_owned(),
+                max_instances: Some(2),
+                max_script_size: None,
+                max_size: Some(100),
+            },
+            Budget {
+                path: "Missing".to_owned(),
+                max_instances: Some(1),
+                max_script_size: None,
+                max_size: None,
+            },
+        ];
+
+        insta::assert_yaml_snapshot!(check_budgets(&tree(), &budgets));
+    }
+
+    #[test]
+    fn within_budgets() {
+        let budgets = [Budget {
+            path: "Shared/Small".to_owned(),
+            max_instances: Some(1),
+            max_script_size: Some(1),
+            max_size: Some(1000),
+        }];
+
+        assert!(check_budgets(&tree(), &budgets).is_empty());
+    }
+}
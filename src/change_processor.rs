@@ -1,19 +1,27 @@
 use crossbeam_channel::{select, Receiver, RecvError, Sender};
 use jod_thread::JoinHandle;
 use memofs::{IoResultExt, Vfs, VfsEvent};
-use rbx_dom_weak::types::{Ref, Variant};
-use std::path::PathBuf;
+use rbx_dom_weak::{
+    types::{Ref, Variant},
+    ustr,
+};
+use std::path::{Path, PathBuf};
 use std::{
-    fs,
+    fs, io,
     sync::{Arc, Mutex},
 };
 
 use crate::{
+    file_format,
     message_queue::MessageQueue,
+    preprocess::is_preprocessed,
+    project::Project,
     snapshot::{
-        apply_patch_set, compute_patch_set, AppliedPatchSet, InstigatingSource, PatchSet, RojoTree,
+        apply_patch_set, compute_patch_set, AppliedPatchSet, InstanceWithMeta, InstigatingSource,
+        PatchSet, PatchUpdate, RojoTree,
     },
-    snapshot_middleware::{snapshot_from_vfs, snapshot_project_node},
+    snapshot_middleware::{snapshot_from_vfs, snapshot_project_node, Middleware},
+    syncback::{syncback_changed_properties, syncback_new_instance},
 };
 
 /// Processes file change events, updates the DOM, and sends those updates
@@ -46,11 +54,15 @@ pub struct ChangeProcessor {
 impl ChangeProcessor {
     /// Spin up the ChangeProcessor, connecting it to the given tree, VFS, and
     /// outbound message queue.
+    ///
+    /// The project is used to decide how instances created by clients are
+    /// written to the file system.
     pub fn start(
         tree: Arc<Mutex<RojoTree>>,
         vfs: Arc<Vfs>,
         message_queue: Arc<MessageQueue<AppliedPatchSet>>,
         tree_mutation_receiver: Receiver<PatchSet>,
+        project: Project,
     ) -> Self {
         let (shutdown_sender, shutdown_receiver) = crossbeam_channel::bounded(1);
         let vfs_receiver = vfs.event_receiver();
@@ -58,6 +70,7 @@ impl ChangeProcessor {
             tree,
             vfs,
             message_queue,
+            project,
         };
 
         let job_thread = jod_thread::Builder::new()
@@ -111,6 +124,10 @@ struct JobThreadContext {
     /// Whenever changes are applied to the DOM, we should push those changes
     /// into this message queue to inform any connected clients.
     message_queue: Arc<MessageQueue<AppliedPatchSet>>,
+
+    /// The root project of the session, used when syncing instances created
+    /// by clients back to the file system.
+    project: Project,
 }
 
 impl JobThreadContext {
@@ -124,27 +141,7 @@ impl JobThreadContext {
         let mut tree = self.tree.lock().unwrap();
         let mut applied_patches = Vec::new();
 
-        // Find the nearest ancestor to this path that has
-        // associated instances in the tree. This helps make sure
-        // that we handle additions correctly, especially if we
-        // receive events for descendants of a large tree being
-        // created all at once.
-        let mut current_path = path.as_path();
-        let affected_ids = loop {
-            let ids = tree.get_ids_at_path(current_path);
-
-            log::trace!("Path {} affects IDs {:?}", current_path.display(), ids);
-
-            if !ids.is_empty() {
-                break ids.to_vec();
-            }
-
-            log::trace!("Trying parent path...");
-            match current_path.parent() {
-                Some(parent) => current_path = parent,
-                None => break Vec::new(),
-            }
-        };
+        let affected_ids = ids_affected_by_path(&tree, &path);
 
         for id in affected_ids {
             if let Some(patch) = compute_and_apply_changes(&mut tree, &self.vfs, id) {
@@ -201,13 +198,7 @@ impl JobThreadContext {
                     if let Some(instigating_source) = &instance.metadata().instigating_source {
                         match instigating_source {
                             InstigatingSource::Path(path) => {
-                                if let Err(err) = fs::remove_file(path) {
-                                    log::error!(
-                                        "Failed to remove file {}: {}",
-                                        path.display(),
-                                        err
-                                    );
-                                }
+                                remove_instance_files(path, instance);
                             }
                             InstigatingSource::ProjectNode { .. } => {
                                 log::warn!(
@@ -228,6 +219,49 @@ impl JobThreadContext {
                 }
             }
 
+            for add in &patch_set.added_instances {
+                let Some(parent) = tree.get_instance(add.parent_id) else {
+                    log::warn!(
+                        "Cannot add instance {}, its parent {:?} does not exist.",
+                        add.instance.name,
+                        add.parent_id
+                    );
+                    continue;
+                };
+
+                let Some(parent_dir) = self.directory_for_instance(parent) else {
+                    log::warn!(
+                        "Cannot add instance {} to {}, it is not backed by a directory.",
+                        add.instance.name,
+                        parent.name()
+                    );
+                    continue;
+                };
+
+                let fs_snapshot = match syncback_new_instance(
+                    &self.vfs,
+                    &tree,
+                    add.instance.clone(),
+                    &parent_dir,
+                    &self.project,
+                ) {
+                    Ok(fs_snapshot) => fs_snapshot,
+                    Err(err) => {
+                        log::error!("Failed to add instance {}: {:?}", add.instance.name, err);
+                        continue;
+                    }
+                };
+
+                if let Err(err) = fs_snapshot.write_to_vfs(&parent_dir, &self.vfs) {
+                    log::error!(
+                        "Failed to write instance {} to {}: {}",
+                        add.instance.name,
+                        parent_dir.display(),
+                        err
+                    );
+                }
+            }
+
             for update in &patch_set.updated_instances {
                 let id = update.id;
 
@@ -244,40 +278,56 @@ impl JobThreadContext {
                         log::warn!("Cannot change metadata yet.");
                     }
 
-                    for (key, changed_value) in &update.changed_properties {
-                        if key == "Source" {
-                            if let Some(instigating_source) =
-                                &instance.metadata().instigating_source
-                            {
-                                match instigating_source {
-                                    InstigatingSource::Path(path) => {
-                                        if let Some(Variant::String(value)) = changed_value {
-                                            if let Err(err) = fs::write(path, value) {
-                                                log::error!(
-                                                    "Failed to write file {}: {}",
-                                                    path.display(),
-                                                    err
-                                                );
-                                            }
-                                        } else {
-                                            log::warn!("Cannot change Source to non-string value.");
+                    // The Source of data modules is generated from their
+                    // files, so it can't be written back directly.
+                    let is_data_module = matches!(
+                        instance.metadata().middleware,
+                        Some(Middleware::Json | Middleware::Toml | Middleware::Yaml)
+                    );
+                    let only_source = !is_data_module
+                        && update
+                            .changed_properties
+                            .keys()
+                            .all(|key| key.as_str() == "Source");
+
+                    if !only_source {
+                        self.persist_changed_properties(&tree, id, update);
+                    } else if let Some(changed_value) =
+                        update.changed_properties.get(&ustr("Source"))
+                    {
+                        if let Some(instigating_source) = &instance.metadata().instigating_source {
+                            match instigating_source {
+                                InstigatingSource::Path(path) => {
+                                    if let Some(Variant::String(value)) = changed_value {
+                                        if self.has_preprocessor_directives(instance, path) {
+                                            log::warn!(
+                                                "Cannot change Source of {}, it has preprocessor \
+                                                directives that would be lost",
+                                                path.display()
+                                            );
+                                        } else if let Err(err) = fs::write(path, value) {
+                                            log::error!(
+                                                "Failed to write file {}: {}",
+                                                path.display(),
+                                                err
+                                            );
                                         }
-                                    }
-                                    InstigatingSource::ProjectNode { .. } => {
-                                        log::warn!(
-                                            "Cannot remove instance {:?}, it's from a project file",
-                                            id
-                                        );
+                                    } else {
+                                        log::warn!("Cannot change Source to non-string value.");
                                     }
                                 }
-                            } else {
-                                log::warn!(
-                                    "Cannot update instance {:?}, it is not an instigating source.",
-                                    id
-                                );
+                                InstigatingSource::ProjectNode { .. } => {
+                                    log::warn!(
+                                        "Cannot remove instance {:?}, it's from a project file",
+                                        id
+                                    );
+                                }
                             }
                         } else {
-                            log::warn!("Cannot change properties besides BaseScript.Source.");
+                            log::warn!(
+                                "Cannot update instance {:?}, it is not an instigating source.",
+                                id
+                            );
                         }
                     }
                 } else {
@@ -285,6 +335,14 @@ impl JobThreadContext {
                 }
             }
 
+            // Added instances are not applied to the tree directly. Once they've
+            // been written to the file system, the VFS events for their files
+            // will add them to the tree with the right metadata.
+            let patch_set = PatchSet {
+                added_instances: Vec::new(),
+                ..patch_set
+            };
+
             apply_patch_set(&mut tree, patch_set)
         };
 
@@ -292,9 +350,128 @@ impl JobThreadContext {
             self.message_queue.push_messages(&[applied_patch]);
         }
     }
+
+    /// Writes the properties of an instance changed by a client back to the
+    /// files they came from.
+    fn persist_changed_properties(&self, tree: &RojoTree, id: Ref, update: &PatchUpdate) {
+        let fs_snapshot = match syncback_changed_properties(
+            &self.vfs,
+            tree,
+            id,
+            &update.changed_properties,
+            &self.project,
+        ) {
+            Ok(fs_snapshot) => fs_snapshot,
+            Err(err) => {
+                log::error!(
+                    "Failed to persist properties of instance {:?}: {:?}",
+                    id,
+                    err
+                );
+                return;
+            }
+        };
+
+        if let Err(err) = fs_snapshot.write_to_vfs(self.project.folder_location(), &self.vfs) {
+            log::error!("Failed to persist properties of instance {:?}: {}", id, err);
+        }
+    }
+
+    /// Returns the directory that children of the given instance should be
+    /// written into, if the instance is backed by one.
+    ///
+    /// Directory-backed instances list their directory as their first
+    /// relevant path, including project nodes whose `$path` is a directory.
+    fn directory_for_instance(&self, instance: InstanceWithMeta) -> Option<PathBuf> {
+        let path = instance.metadata().relevant_paths.first()?;
+
+        match self.vfs.metadata(path) {
+            Ok(meta) if meta.is_dir() => Some(path.to_path_buf()),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the file at `path` has preprocessor directives that
+    /// writing a new Source over it would lose. Like syncback, this is only
+    /// checked for instances whose sources are preprocessed.
+    fn has_preprocessor_directives(&self, instance: InstanceWithMeta, path: &Path) -> bool {
+        let Some(defines) = &instance.metadata().context.defines else {
+            return false;
+        };
+
+        match self.vfs.read_to_string_lf_normalized(path) {
+            Ok(contents) => is_preprocessed(&contents, defines),
+            // There's nothing in the file to lose if it can't be read.
+            Err(_) => false,
+        }
+    }
+}
+
+/// Removes the file or directory backing an instance, along with any adjacent
+/// meta files that applied to it.
+fn remove_instance_files(path: &Path, instance: InstanceWithMeta) {
+    let result = match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        // If the path is already gone, an ancestor of this instance was
+        // probably removed in the same patch.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        log::error!("Failed to remove {}: {}", path.display(), err);
+        return;
+    }
+
+    for relevant_path in &instance.metadata().relevant_paths {
+        let is_meta_file = relevant_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| {
+                file_format::EXTENSIONS
+                    .iter()
+                    .any(|extension| name.ends_with(&format!(".meta.{extension}")))
+            })
+            .unwrap_or(false);
+
+        if is_meta_file && relevant_path.is_file() {
+            if let Err(err) = fs::remove_file(relevant_path) {
+                log::error!("Failed to remove file {}: {}", relevant_path.display(), err);
+            }
+        }
+    }
+}
+
+/// Finds the nearest ancestor to the given path that has associated instances
+/// in the tree and returns those instances.
+///
+/// This helps make sure that we handle additions correctly, especially if we
+/// receive events for descendants of a large tree being created all at once.
+pub(crate) fn ids_affected_by_path(tree: &RojoTree, path: &Path) -> Vec<Ref> {
+    let mut current_path = path;
+    loop {
+        let ids = tree.get_ids_at_path(current_path);
+
+        log::trace!("Path {} affects IDs {:?}", current_path.display(), ids);
+
+        if !ids.is_empty() {
+            break ids.to_vec();
+        }
+
+        log::trace!("Trying parent path...");
+        match current_path.parent() {
+            Some(parent) => current_path = parent,
+            None => break Vec::new(),
+        }
+    }
 }
 
-fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: Ref) -> Option<AppliedPatchSet> {
+pub(crate) fn compute_and_apply_changes(
+    tree: &mut RojoTree,
+    vfs: &Vfs,
+    id: Ref,
+) -> Option<AppliedPatchSet> {
     let metadata = tree
         .get_metadata(id)
         .expect("metadata missing for instance present in tree");
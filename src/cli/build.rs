@@ -1,24 +1,59 @@
 use std::{
-    io::{BufWriter, Write},
-    mem::forget,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+    mem::{self, forget},
     path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
+use blake3::Hash;
 use clap::{CommandFactory, Parser};
-use fs_err::File;
 use memofs::Vfs;
+use rbx_dom_weak::{
+    types::{Ref, Variant},
+    InstanceBuilder, WeakDom,
+};
 use roblox_install::RobloxStudio;
+use serde::Serialize;
 use tokio::runtime::Runtime;
 
-use crate::serve_session::ServeSession;
+use crate::{
+    build_report::{check_budgets, file_for_instance, source_file, BuildReport},
+    message_queue::MessageQueue,
+    preprocess::DefineValue,
+    serve_session::ServeSession,
+    snapshot::{AppliedPatchSet, RojoTree},
+    strip::{strip_source, StripSources},
+    syncback::inst_path,
+    workspace::{run_for_each, Workspace},
+    Project,
+};
 
-use super::resolve_path;
+use super::{
+    parse_define, resolve_path,
+    syncback::{read_dom, FileKind},
+};
 
 const UNKNOWN_OUTPUT_KIND_ERR: &str = "Could not detect what kind of file to build. \
                                        Expected output file to end in .rbxl, .rbxlx, .rbxm, or .rbxmx.";
 const UNKNOWN_PLUGIN_KIND_ERR: &str = "Could not detect what kind of file to build. \
                                        Expected plugin file to end in .rbxm or .rbxmx.";
+const UNKNOWN_BASE_KIND_ERR: &str = "Could not detect what kind of file the base is. \
+                                     Expected base file to end in .rbxl, .rbxlx, .rbxm, or .rbxmx.";
+
+/// How long to wait for more changes before rebuilding in watch mode, so that
+/// saving several files at once only causes one rebuild.
+const REBUILD_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The most changed instances to list before each rebuild in watch mode.
+const MAX_LISTED_CHANGES: usize = 10;
+
+/// The name of the environment variable that `--on-build` commands are given
+/// the path of the output file in.
+const OUTPUT_PATH_VAR: &str = "ROJO_OUTPUT_PATH";
 
 /// Generates a model or place file from the Rojo project.
 #[derive(Debug, Parser)]
@@ -42,10 +77,50 @@ pub struct BuildCommand {
     /// Whether to automatically rebuild when any input files change.
     #[clap(long)]
     pub watch: bool,
+
+    /// The name of a profile from the project file to use.
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// Defines a name for preprocessing `--#if` directives in Lua sources,
+    /// given as `NAME` or `NAME=VALUE`. Can be given more than once.
+    #[clap(long = "define", value_name = "NAME[=VALUE]", parse(try_from_str = parse_define))]
+    pub defines: Vec<(String, DefineValue)>,
+
+    /// An existing place or model to build the project into.
+    ///
+    /// Instances from the project replace the matching instances in the base,
+    /// and everything else in the base is kept unless the project says to
+    /// remove unknown instances.
+    #[clap(long)]
+    pub base: Option<PathBuf>,
+
+    /// Where to write a JSON report of the instances and scripts in the
+    /// project and how large they are.
+    #[clap(long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+
+    /// Where to write a JSON file that maps each line of the scripts whose
+    /// sources were stripped, with the project's `stripSources` setting, back
+    /// to the line in the original file that it came from.
+    #[clap(long, value_name = "PATH")]
+    pub source_mapping: Option<PathBuf>,
+
+    /// A command to run after each time the output file is written, such as
+    /// to publish or copy it somewhere. The path of the output file is given
+    /// to it in the `ROJO_OUTPUT_PATH` environment variable.
+    #[clap(long, value_name = "COMMAND")]
+    pub on_build: Option<String>,
 }
 
 impl BuildCommand {
     pub fn run(self) -> anyhow::Result<()> {
+        if let Some(workspace_path) = Workspace::locate(&resolve_path(&self.project)?) {
+            return self.run_workspace(&workspace_path);
+        }
+
+        let on_build = self.on_build_args()?;
+
         let (output_path, output_kind) = match (self.output, self.plugin) {
             (None, None) => {
                 BuildCommand::command()
@@ -75,31 +150,66 @@ impl BuildCommand {
             _ => unreachable!(),
         };
 
+        let base = match self.base {
+            Some(base) => {
+                let base_kind = FileKind::from_path(&base).context(UNKNOWN_BASE_KIND_ERR)?;
+                if base_kind.is_place() != output_kind.is_place() {
+                    let kind = if output_kind.is_place() {
+                        "place"
+                    } else {
+                        "model"
+                    };
+                    bail!(
+                        "The base file {} must be a {kind} to build a {kind}",
+                        base.display()
+                    );
+                }
+
+                Some((resolve_path(&base)?.into_owned(), base_kind))
+            }
+            None => None,
+        };
+
         let project_path = resolve_path(&self.project)?;
 
         log::trace!("Constructing in-memory filesystem");
         let vfs = Vfs::new_default()?;
         vfs.set_watch_enabled(self.watch);
 
-        let session = ServeSession::new(vfs, project_path)?;
+        let session = ServeSession::new_with_defines(
+            vfs,
+            project_path,
+            self.profile.as_deref(),
+            &self.defines.into_iter().collect(),
+        )?;
         let mut cursor = session.message_queue().cursor();
 
-        write_model(&session, &output_path, output_kind)?;
+        let options = BuildOptions {
+            output: output_path,
+            output_kind,
+            base,
+            report: self.report,
+            source_mapping: self.source_mapping,
+            on_build,
+            log_prefix: String::new(),
+        };
+        let mut last_hash = None;
+        build(&session, &options, &mut last_hash)?;
 
         if self.watch {
             let rt = Runtime::new().context("Failed to start the async runtime for watch mode")?;
+            let message_queue = session.message_queue();
 
-            loop {
-                let receiver = session.message_queue().subscribe(cursor);
-                let (new_cursor, _patch_set) = match rt.block_on(receiver) {
-                    Ok(message) => message,
-                    // The message queue was dropped, so there is nothing left
-                    // to watch. Stop watching gracefully.
-                    Err(_) => break,
-                };
-                cursor = new_cursor;
+            // The message queue is only dropped when there's nothing left to
+            // watch, so stop watching gracefully when that happens.
+            while let Some(patch_sets) = wait_for_changes(&rt, message_queue, &mut cursor) {
+                print!("{}", describe_changes(&session.tree(), &patch_sets));
 
-                write_model(&session, &output_path, output_kind)?;
+                // A failed build shouldn't stop the watcher, since the next
+                // change might fix it.
+                if let Err(err) = build(&session, &options, &mut last_hash) {
+                    log::error!("{err:?}");
+                }
             }
         }
 
@@ -109,6 +219,69 @@ impl BuildCommand {
 
         Ok(())
     }
+
+    /// Builds every project in the workspace that has an output.
+    fn run_workspace(self, workspace_path: &Path) -> anyhow::Result<()> {
+        if self.output.is_some() || self.plugin.is_some() {
+            bail!(
+                "--output and --plugin can't be used with a workspace, since the workspace \
+                 file gives the output of each project"
+            );
+        }
+        if self.watch {
+            bail!("--watch can't be used with a workspace");
+        }
+        if self.base.is_some() || self.report.is_some() || self.source_mapping.is_some() {
+            bail!("--base, --report, and --source-mapping can't be used with a workspace");
+        }
+
+        let workspace = Workspace::load(workspace_path)?;
+        let projects: Vec<_> = workspace
+            .projects
+            .iter()
+            .filter(|project| project.output.is_some())
+            .collect();
+        let on_build = self.on_build_args()?;
+        let defines = self.defines.iter().cloned().collect();
+
+        run_for_each(&projects, |project| {
+            let output = project.output.clone().unwrap();
+            let output_kind =
+                OutputKind::from_output_path(&output).context(UNKNOWN_OUTPUT_KIND_ERR)?;
+
+            let vfs = Vfs::new_default()?;
+            vfs.set_watch_enabled(false);
+
+            let profile = project.profile.as_deref().or(self.profile.as_deref());
+            let session = ServeSession::new_with_defines(vfs, &project.path, profile, &defines)?;
+
+            let options = BuildOptions {
+                output,
+                output_kind,
+                base: None,
+                report: None,
+                source_mapping: None,
+                on_build: on_build.clone(),
+                log_prefix: format!("[{}] ", session.project_name()),
+            };
+            build(&session, &options, &mut None)?;
+
+            forget(session);
+            Ok(())
+        })
+    }
+
+    /// Returns the `--on-build` command split into its arguments.
+    fn on_build_args(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let Some(command) = &self.on_build else {
+            return Ok(None);
+        };
+
+        shlex::split(command)
+            .filter(|args| !args.is_empty())
+            .with_context(|| format!("the command `{command}` could not be parsed"))
+            .map(Some)
+    }
 }
 
 /// The different kinds of output that Rojo can build to.
@@ -149,60 +322,641 @@ impl OutputKind {
             _ => None,
         }
     }
+
+    fn is_place(self) -> bool {
+        matches!(self, OutputKind::Rbxlx | OutputKind::Rbxl)
+    }
 }
 
 fn xml_encode_config() -> rbx_xml::EncodeOptions<'static> {
     rbx_xml::EncodeOptions::new().property_behavior(rbx_xml::EncodePropertyBehavior::WriteUnknown)
 }
 
+/// Where and how to build the project.
+struct BuildOptions {
+    output: PathBuf,
+    output_kind: OutputKind,
+    base: Option<(PathBuf, FileKind)>,
+    report: Option<PathBuf>,
+    source_mapping: Option<PathBuf>,
+    /// The `--on-build` command, split into its arguments.
+    on_build: Option<Vec<String>>,
+    /// Put in front of every line that's printed while building, so that
+    /// projects in a workspace, which are built in parallel, can be told
+    /// apart.
+    log_prefix: String,
+}
+
+/// A script whose source was stripped while building.
+struct StrippedScript {
+    id: Ref,
+    original: String,
+    /// The line in the original source that each line came from.
+    lines: Vec<usize>,
+}
+
+/// The lines that each stripped script came from, as written by
+/// `--source-mapping`.
+#[derive(Serialize)]
+struct SourceMapping {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    lines: Vec<usize>,
+}
+
+/// Builds the project, and runs the `--on-build` command if the output file
+/// was written.
+fn build(
+    session: &ServeSession,
+    options: &BuildOptions,
+    last_hash: &mut Option<Hash>,
+) -> anyhow::Result<()> {
+    let written = write_model(session, options, last_hash)?;
+    if let (true, Some(on_build)) = (written, &options.on_build) {
+        run_on_build(on_build, &options.output)?;
+    }
+
+    Ok(())
+}
+
+/// Builds the project and writes it to the output file, unless it's the same
+/// as the last build, whose hash is kept in `last_hash`. Returns whether the
+/// output file was written.
 #[profiling::function]
 fn write_model(
     session: &ServeSession,
-    output: &Path,
-    output_kind: OutputKind,
-) -> anyhow::Result<()> {
-    println!("Building project '{}'", session.project_name());
+    options: &BuildOptions,
+    last_hash: &mut Option<Hash>,
+) -> anyhow::Result<bool> {
+    let prefix = &options.log_prefix;
+    println!("{prefix}Building project '{}'", session.project_name());
+
+    let mut tree = session.tree();
+    let project = session.root_project();
+
+    // Sources are only stripped while the tree is encoded, so that the tree
+    // keeps matching the files it came from between builds in watch mode.
+    let strip = project.strip_sources.unwrap_or(StripSources::None);
+    let mut stripped = strip_tree_sources(&mut tree, session.vfs(), strip);
+    let contents = encode_tree(&tree, session.vfs(), project, options);
+    restore_tree_sources(&mut tree, &mut stripped);
+    let contents = contents?;
+
+    let filename = options
+        .output
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("<invalid utf-8>");
 
-    let tree = session.tree();
-    let root_id = tree.get_root_id();
+    let hash = blake3::hash(&contents);
+    if *last_hash == Some(hash) {
+        println!("{prefix}{filename} is unchanged, skipping write");
+        return Ok(false);
+    }
+
+    log::trace!("Writing output file");
+    fs_err::write(&options.output, &contents)?;
+    *last_hash = Some(hash);
+    println!("{prefix}Built project to {filename}");
+
+    if let Some(mapping_path) = &options.source_mapping {
+        let mapping: BTreeMap<_, _> = stripped
+            .into_iter()
+            .map(|script| {
+                let path = inst_path(tree.inner(), script.id).replace('/', ".");
+                let mapping = SourceMapping {
+                    file: file_for_instance(
+                        &tree,
+                        session.vfs(),
+                        script.id,
+                        project.folder_location(),
+                    ),
+                    lines: script.lines,
+                };
+                (path, mapping)
+            })
+            .collect();
+        fs_err::write(mapping_path, serde_json::to_vec_pretty(&mapping)?)?;
+    }
+
+    Ok(true)
+}
+
+/// Checks the tree against the project's budgets, writes the report if one
+/// was asked for, and encodes the tree in the format of the output file.
+fn encode_tree(
+    tree: &RojoTree,
+    vfs: &Vfs,
+    project: &Project,
+    options: &BuildOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let budget_violations = match &options.report {
+        Some(report_path) => {
+            let report = BuildReport::new(tree, vfs, project.folder_location(), &project.budgets);
+            let contents = serde_json::to_vec_pretty(&report)?;
+            fs_err::write(report_path, contents)?;
+            report.budget_violations
+        }
+        None => check_budgets(tree, &project.budgets),
+    };
+    if !budget_violations.is_empty() {
+        bail!(
+            "The project is over its budgets:\n{}",
+            budget_violations
+                .iter()
+                .map(|violation| format!("  - {violation}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
 
-    log::trace!("Opening output file for write");
-    let mut file = BufWriter::new(File::create(output)?);
+    // The base is read again for every build so that changes to it are picked
+    // up in watch mode, and so that each build starts from a clean copy.
+    let base_dom = match &options.base {
+        Some((base_path, base_kind)) => {
+            let mut base_dom = read_dom(base_path, *base_kind)?;
+            graft_onto_base(tree, &mut base_dom);
+            Some(base_dom)
+        }
+        None => None,
+    };
+    let dom = base_dom.as_ref().unwrap_or_else(|| tree.inner());
+    let root_id = dom.root_ref();
+
+    let mut contents = Vec::new();
 
-    match output_kind {
+    match options.output_kind {
         OutputKind::Rbxm => {
-            rbx_binary::to_writer(&mut file, tree.inner(), &[root_id])?;
+            rbx_binary::to_writer(&mut contents, dom, &[root_id])?;
         }
         OutputKind::Rbxl => {
-            let root_instance = tree.get_instance(root_id).unwrap();
-            let top_level_ids = root_instance.children();
+            let top_level_ids = dom.root().children();
 
-            rbx_binary::to_writer(&mut file, tree.inner(), top_level_ids)?;
+            rbx_binary::to_writer(&mut contents, dom, top_level_ids)?;
         }
         OutputKind::Rbxmx => {
             // Model files include the root instance of the tree and all its
             // descendants.
 
-            rbx_xml::to_writer(&mut file, tree.inner(), &[root_id], xml_encode_config())?;
+            rbx_xml::to_writer(&mut contents, dom, &[root_id], xml_encode_config())?;
         }
         OutputKind::Rbxlx => {
             // Place files don't contain an entry for the DataModel, but our
             // WeakDom representation does.
 
-            let root_instance = tree.get_instance(root_id).unwrap();
-            let top_level_ids = root_instance.children();
+            let top_level_ids = dom.root().children();
 
-            rbx_xml::to_writer(&mut file, tree.inner(), top_level_ids, xml_encode_config())?;
+            rbx_xml::to_writer(&mut contents, dom, top_level_ids, xml_encode_config())?;
         }
     }
 
-    file.flush()?;
+    Ok(contents)
+}
 
-    let filename = output
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("<invalid utf-8>");
-    println!("Built project to {}", filename);
+/// Strips the sources of the scripts in the tree that were made from Lua
+/// files, returning what they were before.
+fn strip_tree_sources(tree: &mut RojoTree, vfs: &Vfs, mode: StripSources) -> Vec<StrippedScript> {
+    if mode == StripSources::None {
+        return Vec::new();
+    }
+
+    // Scripts are picked by the file they came from rather than their
+    // middleware, since init scripts used as a project's root are made by the
+    // project middleware.
+    let script_ids: Vec<Ref> = tree
+        .descendants(tree.get_root_id())
+        .filter(|instance| instance.properties().contains_key(&"Source".into()))
+        .map(|instance| instance.id())
+        .filter(|&id| {
+            source_file(tree, vfs, id)
+                .and_then(|path| path.extension()?.to_str().map(str::to_owned))
+                .is_some_and(|extension| extension == "lua" || extension == "luau")
+        })
+        .collect();
+
+    let mut stripped = Vec::new();
+    for id in script_ids {
+        let mut instance = tree.get_instance_mut(id).unwrap();
+        if let Some(Variant::String(source)) = instance.properties_mut().get_mut(&"Source".into()) {
+            let stripped_source = strip_source(source, mode);
+            stripped.push(StrippedScript {
+                id,
+                original: mem::replace(source, stripped_source.source),
+                lines: stripped_source.lines,
+            });
+        }
+    }
+
+    stripped
+}
+
+fn restore_tree_sources(tree: &mut RojoTree, stripped: &mut [StrippedScript]) {
+    for script in stripped {
+        let mut instance = tree.get_instance_mut(script.id).unwrap();
+        instance.properties_mut().insert(
+            "Source".into(),
+            Variant::String(mem::take(&mut script.original)),
+        );
+    }
+}
+
+/// Waits for the next messages after `cursor` in the queue, and then for more
+/// until none have arrived for `REBUILD_DEBOUNCE`. Returns `None` if the queue
+/// was dropped.
+fn wait_for_changes<T: Clone>(
+    rt: &Runtime,
+    message_queue: &MessageQueue<T>,
+    cursor: &mut u32,
+) -> Option<Vec<T>> {
+    let (new_cursor, mut messages) = rt.block_on(message_queue.subscribe(*cursor)).ok()?;
+    *cursor = new_cursor;
+
+    // Changes tend to come in bursts, like when several files are saved at
+    // once, so wait for them to settle before rebuilding.
+    loop {
+        thread::sleep(REBUILD_DEBOUNCE);
+        if message_queue.cursor() == *cursor {
+            return Some(messages);
+        }
+
+        let (new_cursor, new_messages) = rt.block_on(message_queue.subscribe(*cursor)).ok()?;
+        *cursor = new_cursor;
+        messages.extend(new_messages);
+    }
+}
+
+/// Lists the instances that changed in the given patches, which are about to
+/// be rebuilt.
+fn describe_changes(tree: &RojoTree, patch_sets: &[AppliedPatchSet]) -> String {
+    let mut changed = Vec::new();
+    let mut seen = HashSet::new();
+    let mut removed = 0;
+
+    for patch_set in patch_sets {
+        removed += patch_set.removed.len();
+
+        let ids = patch_set
+            .added
+            .iter()
+            .chain(patch_set.updated.iter().map(|update| &update.id));
+        for &id in ids {
+            if seen.insert(id) {
+                changed.push(id);
+            }
+        }
+    }
+
+    // Instances that were added or updated and then removed in the same burst
+    // of changes aren't in the tree anymore.
+    changed.retain(|&id| tree.get_instance(id).is_some());
+
+    let mut description = format!(
+        "Rebuilding after {} changed and {} removed instances\n",
+        changed.len(),
+        removed
+    );
+    for &id in changed.iter().take(MAX_LISTED_CHANGES) {
+        writeln!(description, "  {}", inst_path(tree.inner(), id)).unwrap();
+    }
+    if changed.len() > MAX_LISTED_CHANGES {
+        writeln!(
+            description,
+            "  ...and {} more",
+            changed.len() - MAX_LISTED_CHANGES
+        )
+        .unwrap();
+    }
+
+    description
+}
+
+/// Runs the `--on-build` command, which has already been split into its
+/// arguments.
+fn run_on_build(args: &[String], output: &Path) -> anyhow::Result<()> {
+    log::debug!("Running on-build command {:?}", args);
+
+    let status = Command::new(&args[0])
+        .args(&args[1..])
+        .env(OUTPUT_PATH_VAR, output)
+        .status()
+        .with_context(|| format!("could not run on-build command `{}`", args[0]))?;
+
+    if !status.success() {
+        bail!("on-build command `{}` failed ({})", args[0], status);
+    }
 
     Ok(())
 }
+
+/// Merges the instances from `tree` into `base`, the same way the plugin
+/// merges a project into an open place. Each instance is matched with a child
+/// of the same name and class in the base, and only the properties that Rojo
+/// knows about are changed. Children in the base that don't match anything are
+/// kept unless their parent doesn't ignore unknown instances.
+fn graft_onto_base(tree: &RojoTree, base: &mut WeakDom) {
+    let mut grafted = HashMap::new();
+    graft_instance(
+        tree,
+        tree.get_root_id(),
+        base,
+        base.root_ref(),
+        &mut grafted,
+    );
+
+    // Refs from the project point at instances in the project's tree, so they
+    // need to point at the instances they were grafted onto instead. Refs that
+    // were already in the base are left alone.
+    for (&tree_id, &base_id) in &grafted {
+        let tree_instance = tree.get_instance(tree_id).unwrap();
+        let base_instance = base.get_by_ref_mut(base_id).unwrap();
+
+        for (key, value) in tree_instance.properties() {
+            if let Variant::Ref(target) = value {
+                let target = grafted.get(target).copied().unwrap_or_else(Ref::none);
+                base_instance.properties.insert(*key, Variant::Ref(target));
+            }
+        }
+    }
+}
+
+fn graft_instance(
+    tree: &RojoTree,
+    tree_id: Ref,
+    base: &mut WeakDom,
+    base_id: Ref,
+    grafted: &mut HashMap<Ref, Ref>,
+) {
+    grafted.insert(tree_id, base_id);
+
+    let tree_instance = tree.get_instance(tree_id).unwrap();
+    let base_instance = base.get_by_ref_mut(base_id).unwrap();
+    base_instance.name = tree_instance.name().to_owned();
+    for (key, value) in tree_instance.properties() {
+        base_instance.properties.insert(*key, value.clone());
+    }
+
+    let mut unmatched = base_instance.children().to_vec();
+    for &tree_child_id in tree_instance.children() {
+        let tree_child = tree.get_instance(tree_child_id).unwrap();
+        let matching = unmatched.iter().position(|&base_child_id| {
+            let base_child = base.get_by_ref(base_child_id).unwrap();
+            base_child.name == tree_child.name() && base_child.class == tree_child.class_name()
+        });
+
+        let base_child_id = match matching {
+            Some(index) => unmatched.remove(index),
+            None => base.insert(
+                base_id,
+                InstanceBuilder::new(tree_child.class_name()).with_name(tree_child.name()),
+            ),
+        };
+        graft_instance(tree, tree_child_id, base, base_child_id, grafted);
+    }
+
+    if !tree_instance.metadata().ignore_unknown_instances {
+        for base_child_id in unmatched {
+            base.destroy(base_child_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{sync::Arc, time::Instant};
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    use crate::snapshot::AppliedPatchUpdate;
+
+    fn project_session() -> ServeSession {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir([(
+                "default.project.json",
+                VfsSnapshot::file(
+                    r#"{
+                            "name": "foo",
+                            "tree": {
+                                "$className": "DataModel",
+                                "Workspace": {
+                                    "$className": "Workspace",
+                                    "Map": {
+                                        "$className": "Model",
+                                        "Spawn": {
+                                            "$className": "SpawnLocation",
+                                            "$attributes": { "Rojo_Id": "spawn" }
+                                        }
+                                    }
+                                },
+                                "ReplicatedStorage": {
+                                    "$className": "ReplicatedStorage",
+                                    "$ignoreUnknownInstances": false,
+                                    "Shared": {
+                                        "$className": "Folder",
+                                        "Spawn": {
+                                            "$className": "ObjectValue",
+                                            "$attributes": { "Rojo_Target_Value": "spawn" }
+                                        }
+                                    }
+                                }
+                            }
+                        }"#,
+                ),
+            )]),
+        )
+        .unwrap();
+
+        ServeSession::new(Vfs::new(imfs), "/foo").unwrap()
+    }
+
+    fn base_place() -> WeakDom {
+        let mut base = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let root = base.root_ref();
+
+        let baseplate = InstanceBuilder::new("Part").with_name("Baseplate");
+        let baseplate_id = baseplate.referent();
+        base.insert(
+            root,
+            InstanceBuilder::new("Workspace")
+                .with_name("Workspace")
+                .with_property("Gravity", 100.0f32)
+                .with_child(InstanceBuilder::new("Terrain").with_name("Terrain"))
+                .with_child(baseplate)
+                .with_child(
+                    InstanceBuilder::new("Model")
+                        .with_name("Map")
+                        .with_child(InstanceBuilder::new("Part").with_name("Tree")),
+                ),
+        );
+        base.insert(
+            root,
+            InstanceBuilder::new("Lighting")
+                .with_name("Lighting")
+                .with_child(InstanceBuilder::new("Sky").with_name("Sky")),
+        );
+        base.insert(
+            root,
+            InstanceBuilder::new("ReplicatedStorage")
+                .with_name("ReplicatedStorage")
+                .with_child(
+                    InstanceBuilder::new("ObjectValue")
+                        .with_name("Old")
+                        .with_property("Value", baseplate_id),
+                )
+                .with_child(
+                    InstanceBuilder::new("Folder")
+                        .with_name("Shared")
+                        .with_child(InstanceBuilder::new("Folder").with_name("Unmanaged")),
+                ),
+        );
+
+        base
+    }
+
+    /// Describes every instance in `dom` along with its Ref properties, which
+    /// are shown as the name of the instance they point to.
+    fn describe(dom: &WeakDom) -> String {
+        fn describe_instance(dom: &WeakDom, id: Ref, depth: usize, output: &mut String) {
+            let instance = dom.get_by_ref(id).unwrap();
+            write!(
+                output,
+                "{}{} ({})",
+                "  ".repeat(depth),
+                instance.name,
+                instance.class
+            )
+            .unwrap();
+
+            let mut properties: Vec<_> = instance.properties.iter().collect();
+            properties.sort_by_key(|(key, _)| key.as_str());
+            for (key, value) in properties {
+                if let Variant::Ref(target) = value {
+                    let target = dom.get_by_ref(*target).map_or("nil", |target| &target.name);
+                    write!(output, " {key}={target}").unwrap();
+                }
+            }
+            output.push('\n');
+
+            for &child_id in instance.children() {
+                describe_instance(dom, child_id, depth + 1, output);
+            }
+        }
+
+        let mut output = String::new();
+        describe_instance(dom, dom.root_ref(), 0, &mut output);
+        output
+    }
+
+    fn find(tree: &RojoTree, path: &str) -> Ref {
+        tree.descendants(tree.get_root_id())
+            .find(|instance| inst_path(tree.inner(), instance.id()) == path)
+            .unwrap_or_else(|| panic!("{path} should exist in the tree"))
+            .id()
+    }
+
+    #[test]
+    fn wait_for_changes_debounces() {
+        let rt = Runtime::new().unwrap();
+        let message_queue = Arc::new(MessageQueue::new());
+        let mut cursor = message_queue.cursor();
+
+        message_queue.push_messages(&[1]);
+        let pusher = {
+            let message_queue = Arc::clone(&message_queue);
+            thread::spawn(move || {
+                // Well within the debounce, so it's part of the same burst.
+                thread::sleep(REBUILD_DEBOUNCE / 5);
+                message_queue.push_messages(&[2]);
+
+                thread::sleep(REBUILD_DEBOUNCE * 5);
+                message_queue.push_messages(&[3]);
+            })
+        };
+
+        let start = Instant::now();
+        let first = wait_for_changes(&rt, &message_queue, &mut cursor).unwrap();
+        assert_eq!(first, [1, 2]);
+        assert!(start.elapsed() >= REBUILD_DEBOUNCE);
+
+        let second = wait_for_changes(&rt, &message_queue, &mut cursor).unwrap();
+        assert_eq!(second, [3]);
+
+        pusher.join().unwrap();
+    }
+
+    #[test]
+    fn describe_changes_lists_instances() {
+        let session = project_session();
+        let tree = session.tree();
+        let map = find(&tree, "Workspace/Map");
+        let spawn = find(&tree, "Workspace/Map/Spawn");
+        let shared = find(&tree, "ReplicatedStorage/Shared");
+
+        let mut first = AppliedPatchSet::new();
+        first.added.push(map);
+        first.updated.push(AppliedPatchUpdate::new(spawn));
+        // Added and then removed again before the rebuild.
+        first.added.push(Ref::new());
+
+        let mut second = AppliedPatchSet::new();
+        second.updated.push(AppliedPatchUpdate::new(map));
+        second.updated.push(AppliedPatchUpdate::new(shared));
+        second.removed.push(Ref::new());
+
+        insta::assert_snapshot!(describe_changes(&tree, &[first, second]));
+    }
+
+    #[test]
+    fn write_model_skips_identical_output() {
+        let session = project_session();
+        let dir = tempfile::tempdir().unwrap();
+        let options = BuildOptions {
+            output: dir.path().join("foo.rbxmx"),
+            output_kind: OutputKind::Rbxmx,
+            base: None,
+            report: None,
+            source_mapping: None,
+            on_build: None,
+            log_prefix: String::new(),
+        };
+
+        let mut last_hash = None;
+        assert!(write_model(&session, &options, &mut last_hash).unwrap());
+        let contents = fs_err::read(&options.output).unwrap();
+
+        // Watch mode doesn't touch the output if nothing in it changed, even
+        // if it was changed by something else.
+        fs_err::write(&options.output, "edited").unwrap();
+        assert!(!write_model(&session, &options, &mut last_hash).unwrap());
+        assert_eq!(fs_err::read(&options.output).unwrap(), b"edited");
+
+        last_hash = Some(blake3::hash(b"a different build"));
+        assert!(write_model(&session, &options, &mut last_hash).unwrap());
+        assert_eq!(fs_err::read(&options.output).unwrap(), contents);
+    }
+
+    #[test]
+    fn graft_keeps_unmanaged_instances() {
+        let session = project_session();
+        let mut base = base_place();
+        graft_onto_base(&session.tree(), &mut base);
+
+        insta::assert_snapshot!(describe(&base));
+    }
+
+    #[test]
+    fn graft_keeps_unmanaged_properties() {
+        let session = project_session();
+        let mut base = base_place();
+        graft_onto_base(&session.tree(), &mut base);
+
+        let workspace = base.get_by_ref(base.root().children()[0]).unwrap();
+        assert_eq!(
+            workspace.properties.get(&"Gravity".into()),
+            Some(&Variant::Float32(100.0))
+        );
+    }
+}
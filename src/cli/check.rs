@@ -0,0 +1,580 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write as _},
+    mem::forget,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use clap::Parser;
+use memofs::Vfs;
+use rayon::prelude::*;
+use rbx_dom_weak::types::{Ref, Variant, VariantType};
+use rbx_reflection::{ClassTag, DataType, PropertyDescriptor, PropertyKind, PropertySerialization};
+use serde::Serialize;
+use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+
+use crate::{
+    path_serializer,
+    serve_session::ServeSession,
+    snapshot::{InstanceWithMeta, RojoTree},
+    syncback::inst_path,
+    workspace::Workspace,
+};
+
+use super::{resolve_path, GlobalOptions, OutputFormat};
+
+/// Checks a project for problems that would otherwise only show up in Roblox
+/// Studio, like unknown classes and properties or properties with values of
+/// the wrong type.
+///
+/// Exits with an error if any problems are found, so that it can be used in CI.
+#[derive(Debug, Parser)]
+pub struct CheckCommand {
+    /// Path to the project to check. Defaults to the current directory.
+    #[clap(default_value = "")]
+    pub project: PathBuf,
+
+    /// The format to report problems in. Valid values are text and json.
+    #[clap(long, default_value = "text")]
+    pub format: OutputFormat,
+}
+
+impl CheckCommand {
+    pub fn run(self, global: GlobalOptions) -> anyhow::Result<()> {
+        let project_path = resolve_path(&self.project)?;
+
+        let report = match Workspace::locate(&project_path) {
+            // Every project in a workspace is checked at once, and their
+            // problems are listed together with paths relative to the
+            // workspace's folder.
+            Some(workspace_path) => {
+                let workspace = Workspace::load(&workspace_path)?;
+                let base_path = workspace.folder_location();
+                let problems = workspace
+                    .projects
+                    .par_iter()
+                    .map(|project| {
+                        let mut report = check_project(
+                            &project.path,
+                            project.profile.as_deref(),
+                            Some(base_path),
+                        )?;
+                        for problem in &mut report.problems {
+                            if problem.kind == ProblemKind::LoadFailed {
+                                let path = project
+                                    .path
+                                    .strip_prefix(base_path)
+                                    .unwrap_or(&project.path);
+                                problem.path = Some(path.to_path_buf());
+                            }
+                        }
+                        Ok(report.problems)
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                CheckReport {
+                    problems: problems.into_iter().flatten().collect(),
+                }
+            }
+            None => check_project(&project_path, None, None)?,
+        };
+
+        match self.format {
+            OutputFormat::Json => {
+                let mut stdout = io::stdout().lock();
+                serde_json::to_writer_pretty(&mut stdout, &report)?;
+                writeln!(stdout)?;
+            }
+            OutputFormat::Text => print_report(&report, global.color.into())?,
+        }
+
+        let errors = report.count(Severity::Error);
+        let warnings = report.count(Severity::Warning);
+        if errors > 0 {
+            bail!("Found {errors} error(s) and {warnings} warning(s)");
+        }
+        if self.format == OutputFormat::Text {
+            eprintln!("Found no errors and {warnings} warning(s).");
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the project at the given path. Problems are reported with paths
+/// relative to `base_path`, or to the project's folder if there isn't one.
+fn check_project(
+    project_path: &Path,
+    profile: Option<&str>,
+    base_path: Option<&Path>,
+) -> anyhow::Result<CheckReport> {
+    let vfs = Vfs::new_default()?;
+    vfs.set_watch_enabled(false);
+
+    let report = match ServeSession::new_with_profile(vfs, project_path, profile) {
+        Ok(session) => {
+            let tree = session.tree();
+            let base_path = base_path.unwrap_or_else(|| session.root_project().folder_location());
+            let report = check_tree(&tree, base_path);
+            drop(tree);
+
+            // It is potentially prohibitively expensive to drop a
+            // ServeSession, and the program is about to exit anyway so
+            // we're just going to forget about it.
+            forget(session);
+
+            report
+        }
+        // Problems like property values that can't be resolved stop the
+        // project from loading at all, so they're reported on their own.
+        Err(err) => CheckReport {
+            problems: vec![Problem {
+                severity: Severity::Error,
+                kind: ProblemKind::LoadFailed,
+                message: format!("{:#}", anyhow::Error::from(err)),
+                instance: None,
+                path: None,
+            }],
+        },
+    };
+
+    Ok(report)
+}
+
+/// Every problem found in a project.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckReport {
+    problems: Vec<Problem>,
+}
+
+impl CheckReport {
+    fn count(&self, severity: Severity) -> usize {
+        self.problems
+            .iter()
+            .filter(|problem| problem.severity == severity)
+            .count()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Problem {
+    severity: Severity,
+    kind: ProblemKind,
+    message: String,
+
+    /// The path to the Instance with the problem, as it would appear in
+    /// Roblox Studio.
+    instance: Option<String>,
+
+    /// The file the Instance came from, relative to the project's folder.
+    #[serde(serialize_with = "path_serializer::serialize_option_absolute")]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum ProblemKind {
+    /// The project couldn't be loaded at all.
+    LoadFailed,
+    /// An Instance's class isn't in the reflection database.
+    UnknownClass,
+    /// A property isn't in the reflection database for its Instance's class.
+    UnknownProperty,
+    /// A property won't be saved in place or model files.
+    NonSerializableProperty,
+    /// A property's value doesn't have the type the property expects.
+    TypeMismatch,
+    /// An Instance is somewhere Roblox won't allow it to be.
+    InvalidParent,
+    /// An Instance has the same name as one of its siblings.
+    DuplicateName,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => formatter.write_str("error"),
+            Severity::Warning => formatter.write_str("warning"),
+        }
+    }
+}
+
+fn check_tree(tree: &RojoTree, base_path: &Path) -> CheckReport {
+    let mut report = CheckReport::default();
+    let database = rbx_reflection_database::get().unwrap();
+
+    let mut to_visit = vec![tree.get_root_id()];
+    while let Some(referent) = to_visit.pop() {
+        let inst = tree.get_instance(referent).unwrap();
+        to_visit.extend(inst.children().iter().rev());
+
+        let source = source_path(tree, referent, base_path);
+        let mut problem = |severity, kind, message| {
+            report.problems.push(Problem {
+                severity,
+                kind,
+                message,
+                instance: Some(instance_name(tree, referent)),
+                path: source.clone(),
+            });
+        };
+
+        let class_name = inst.class_name();
+        if !database.classes.contains_key(class_name.as_str()) {
+            problem(
+                Severity::Error,
+                ProblemKind::UnknownClass,
+                format!("Unknown class {class_name}"),
+            );
+        } else if !source.as_deref().is_some_and(is_roblox_file) {
+            // Files saved by Roblox can have properties that are missing from
+            // the reflection database, but they're still what Roblox expects.
+            for (prop_name, value) in inst.properties() {
+                if let Some((severity, kind, message)) =
+                    check_property(class_name.as_str(), prop_name.as_str(), value)
+                {
+                    problem(severity, kind, message);
+                }
+            }
+        }
+
+        let parent_class = tree
+            .get_instance(inst.parent())
+            .map(|parent| parent.class_name());
+        if let Some(parent_class) = parent_class {
+            if let Some(allowed) = allowed_parents(class_name.as_str()) {
+                if !allowed.contains(&parent_class.as_str()) {
+                    problem(
+                        Severity::Error,
+                        ProblemKind::InvalidParent,
+                        format!(
+                            "{class_name} can only be a child of {}, not {parent_class}",
+                            allowed.join(" or ")
+                        ),
+                    );
+                }
+            }
+        }
+
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for child in inst.children() {
+            let child = tree.get_instance(*child).unwrap();
+            *names.entry(child.name()).or_default() += 1;
+        }
+        let mut duplicates: Vec<_> = names.into_iter().filter(|(_, count)| *count > 1).collect();
+        duplicates.sort_unstable();
+        for (name, count) in duplicates {
+            problem(
+                Severity::Warning,
+                ProblemKind::DuplicateName,
+                format!("{count} children are named {name}, which Rojo can't tell apart"),
+            );
+        }
+    }
+
+    report
+}
+
+fn check_property(
+    class_name: &str,
+    prop_name: &str,
+    value: &Variant,
+) -> Option<(Severity, ProblemKind, String)> {
+    let Some(descriptor) = find_canonical_descriptor(class_name, prop_name) else {
+        return Some((
+            Severity::Error,
+            ProblemKind::UnknownProperty,
+            format!("Unknown property {class_name}.{prop_name}"),
+        ));
+    };
+
+    if let PropertyKind::Canonical {
+        serialization: PropertySerialization::DoesNotSerialize,
+    } = &descriptor.kind
+    {
+        return Some((
+            Severity::Warning,
+            ProblemKind::NonSerializableProperty,
+            format!("{class_name}.{prop_name} is not saved in place or model files"),
+        ));
+    }
+
+    let matches = match (&descriptor.data_type, value.ty()) {
+        (DataType::Enum(_), VariantType::Enum | VariantType::EnumItem) => true,
+        (DataType::Enum(_), _) => false,
+        (DataType::Value(expected), actual) => {
+            *expected == actual || equivalent_types(*expected, actual)
+        }
+        _ => true,
+    };
+    if !matches {
+        let expected = match &descriptor.data_type {
+            DataType::Enum(enum_name) => format!("Enum.{enum_name}"),
+            DataType::Value(ty) => format!("{ty:?}"),
+            other => format!("{other:?}"),
+        };
+        return Some((
+            Severity::Error,
+            ProblemKind::TypeMismatch,
+            format!(
+                "{class_name}.{prop_name} expects a value of type {expected}, but it has a {:?}",
+                value.ty()
+            ),
+        ));
+    }
+
+    None
+}
+
+/// Returns whether a value of type `actual` is accepted for a property of type
+/// `expected` even though the types differ, since it's converted when the
+/// Instance is saved.
+fn equivalent_types(expected: VariantType, actual: VariantType) -> bool {
+    matches!(
+        (expected, actual),
+        (VariantType::Color3, VariantType::Color3uint8)
+            | (VariantType::Color3uint8, VariantType::Color3)
+            | (VariantType::String, VariantType::BinaryString)
+            | (VariantType::BinaryString, VariantType::String)
+            | (VariantType::Content, VariantType::ContentId)
+            | (VariantType::ContentId, VariantType::Content)
+    )
+}
+
+fn is_roblox_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("rbxm" | "rbxmx")
+    )
+}
+
+/// Finds the descriptor for a property on a class or one of its superclasses,
+/// following aliases to the property they're for.
+fn find_canonical_descriptor(
+    class_name: &str,
+    prop_name: &str,
+) -> Option<&'static PropertyDescriptor<'static>> {
+    let database = rbx_reflection_database::get().unwrap();
+    let mut current_class_name = class_name;
+
+    loop {
+        let class = database.classes.get(current_class_name)?;
+        if let Some(descriptor) = class.properties.get(prop_name) {
+            return match &descriptor.kind {
+                PropertyKind::Alias { alias_for } => {
+                    find_canonical_descriptor(current_class_name, alias_for)
+                }
+                _ => Some(descriptor),
+            };
+        }
+
+        current_class_name = class.superclass?;
+    }
+}
+
+/// Returns the only classes that an Instance of the given class can be a child
+/// of, if there's a limit on it.
+fn allowed_parents(class_name: &str) -> Option<&'static [&'static str]> {
+    match class_name {
+        "Terrain" => Some(&["Workspace"]),
+        "StarterPlayerScripts" | "StarterCharacterScripts" => Some(&["StarterPlayer"]),
+        _ => {
+            let database = rbx_reflection_database::get().unwrap();
+            let class = database.classes.get(class_name)?;
+            class
+                .tags
+                .contains(&ClassTag::Service)
+                .then_some(&["DataModel"])
+        }
+    }
+}
+
+/// Returns the path to the given Instance as it would appear in Roblox Studio,
+/// using the name of the root Instance for the root itself.
+fn instance_name(tree: &RojoTree, referent: Ref) -> String {
+    let path = inst_path(tree.inner(), referent);
+    if path.is_empty() {
+        tree.inner().root().name.clone()
+    } else {
+        path
+    }
+}
+
+/// Finds the file that the given Instance came from, which is the file of its
+/// nearest ancestor that has one for Instances inside of model files.
+fn source_path(tree: &RojoTree, referent: Ref, base_path: &Path) -> Option<PathBuf> {
+    let mut current: Option<InstanceWithMeta> = tree.get_instance(referent);
+    while let Some(inst) = current {
+        if let Some(source) = &inst.metadata().instigating_source {
+            let path = source.path();
+            return Some(path.strip_prefix(base_path).unwrap_or(path).to_path_buf());
+        }
+        current = tree.get_instance(inst.parent());
+    }
+
+    None
+}
+
+fn print_report(report: &CheckReport, color: ColorChoice) -> io::Result<()> {
+    let writer = BufferWriter::stderr(color);
+    let mut buffer = writer.buffer();
+
+    for problem in &report.problems {
+        let color = match problem.severity {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        };
+        buffer.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        write!(&mut buffer, "{}", problem.severity)?;
+        buffer.reset()?;
+        writeln!(&mut buffer, ": {}", problem.message)?;
+
+        match (&problem.instance, &problem.path) {
+            (Some(instance), Some(path)) => {
+                writeln!(&mut buffer, "  at {instance} ({})", path.display())?
+            }
+            (Some(instance), None) => writeln!(&mut buffer, "  at {instance}")?,
+            _ => {}
+        }
+    }
+
+    writer.print(&buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    fn check_project(
+        project: &'static str,
+        files: Vec<(&'static str, VfsSnapshot)>,
+    ) -> CheckReport {
+        let mut imfs = InMemoryFs::new();
+        let mut children = vec![("default.project.json", VfsSnapshot::file(project))];
+        children.extend(files);
+        imfs.load_snapshot("/foo", VfsSnapshot::dir(children))
+            .unwrap();
+
+        let session = ServeSession::new(Vfs::new(imfs), "/foo").unwrap();
+        let tree = session.tree();
+        check_tree(&tree, Path::new("/foo"))
+    }
+
+    #[test]
+    fn valid_project() {
+        let report = check_project(
+            r#"{
+                "name": "foo",
+                "tree": {
+                    "$className": "DataModel",
+                    "Workspace": {
+                        "Part": {
+                            "$className": "Part",
+                            "$properties": { "Anchored": true, "Material": "Neon" }
+                        }
+                    },
+                    "ReplicatedStorage": { "$path": "src" }
+                }
+            }"#,
+            vec![(
+                "src",
+                VfsSnapshot::dir([("module.luau", VfsSnapshot::file("return nil"))]),
+            )],
+        );
+
+        assert_eq!(report.problems, Vec::new());
+    }
+
+    #[test]
+    fn problems() {
+        let report = check_project(
+            r#"{
+                "name": "foo",
+                "tree": {
+                    "$className": "DataModel",
+                    "Workspace": {
+                        "Unknown": { "$className": "NotARealClass" },
+                        "Lighting": { "$className": "Lighting" },
+                        "Value": {
+                            "$className": "StringValue",
+                            "$properties": { "Value": { "Bool": true } }
+                        }
+                    },
+                    "ReplicatedStorage": { "$path": "src" }
+                }
+            }"#,
+            vec![(
+                "src",
+                VfsSnapshot::dir([
+                    ("a.luau", VfsSnapshot::file("return nil")),
+                    ("a.server.luau", VfsSnapshot::file("print(\"a\")")),
+                    (
+                        "unknown.model.json",
+                        VfsSnapshot::file(
+                            r#"{
+                                "className": "Folder",
+                                "properties": { "NotAProperty": { "Bool": true } }
+                            }"#,
+                        ),
+                    ),
+                ]),
+            )],
+        );
+
+        let summary: Vec<_> = report
+            .problems
+            .iter()
+            .map(|problem| {
+                (
+                    problem.kind,
+                    problem.instance.clone().unwrap(),
+                    problem.path.clone(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    ProblemKind::DuplicateName,
+                    "ReplicatedStorage".to_owned(),
+                    Some(PathBuf::from("default.project.json"))
+                ),
+                (
+                    ProblemKind::UnknownProperty,
+                    "ReplicatedStorage/unknown".to_owned(),
+                    Some(PathBuf::from("src/unknown.model.json"))
+                ),
+                (
+                    ProblemKind::InvalidParent,
+                    "Workspace/Lighting".to_owned(),
+                    Some(PathBuf::from("default.project.json"))
+                ),
+                (
+                    ProblemKind::UnknownClass,
+                    "Workspace/Unknown".to_owned(),
+                    Some(PathBuf::from("default.project.json"))
+                ),
+                (
+                    ProblemKind::TypeMismatch,
+                    "Workspace/Value".to_owned(),
+                    Some(PathBuf::from("default.project.json"))
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,646 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::{self, Write as _},
+    mem::forget,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Context;
+use blake3::Hash;
+use clap::Parser;
+use memofs::Vfs;
+use rbx_dom_weak::{
+    types::{Ref, Variant},
+    Instance, Ustr, WeakDom,
+};
+use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+
+use crate::{
+    glob::IgnorableGlob,
+    serve_session::ServeSession,
+    snapshot::{InstigatingSource, RojoTree},
+    syncback::{filter_properties, hash_tree, inst_path, is_ignored, prefilter_trees},
+    variant_eq::variant_eq,
+    Project, ProjectNode,
+};
+
+use super::{
+    resolve_path,
+    syncback::{read_dom, FileKind, UNKNOWN_INPUT_KIND_ERR},
+    GlobalOptions,
+};
+
+/// The longest a property value can be when displayed before it's truncated.
+const MAX_VALUE_LENGTH: usize = 80;
+
+/// Compares a project against a Roblox place or model file and reports the
+/// Instances and properties that differ between them.
+///
+/// Nothing is written to the file system, so this can be used to see how a
+/// published place has drifted from a project before running syncback.
+#[derive(Debug, Parser)]
+pub struct DiffCommand {
+    /// Path to the project to compare.
+    #[clap(default_value = "")]
+    pub project: PathBuf,
+
+    /// Path to the Roblox file to compare the project against.
+    #[clap(long, short)]
+    pub input: PathBuf,
+}
+
+impl DiffCommand {
+    pub fn run(&self, global: GlobalOptions) -> anyhow::Result<()> {
+        let project_path = resolve_path(&self.project)?;
+        let input_path = resolve_path(&self.input)?;
+
+        let input_kind = FileKind::from_path(&input_path).context(UNKNOWN_INPUT_KIND_ERR)?;
+        let dom_start_timer = Instant::now();
+        let mut dom_new = read_dom(&input_path, input_kind)?;
+        log::debug!(
+            "Finished opening file in {:0.02}s",
+            dom_start_timer.elapsed().as_secs_f32()
+        );
+
+        let vfs = Vfs::new_default()?;
+        vfs.set_watch_enabled(false);
+
+        let project_start_timer = Instant::now();
+        let session = ServeSession::new(vfs, project_path)?;
+        log::debug!(
+            "Finished opening project in {:0.02}s",
+            project_start_timer.elapsed().as_secs_f32()
+        );
+
+        let mut tree = session.tree();
+        let diff = diff_trees(
+            session.vfs(),
+            session.root_project(),
+            &mut tree,
+            &mut dom_new,
+        )?;
+        drop(tree);
+
+        print_diff(&diff, global.color.into())?;
+
+        let (added, removed, changed) = diff.counts();
+        if added + removed + changed == 0 {
+            eprintln!("No differences found.");
+        } else {
+            eprintln!("{added} added, {removed} removed, {changed} changed.");
+        }
+
+        // It is potentially prohibitively expensive to drop a ServeSession,
+        // and the program is about to exit anyway so we're just going to forget
+        // about it.
+        forget(session);
+
+        Ok(())
+    }
+}
+
+/// A single line of a diff between two trees.
+#[derive(Debug, Clone, PartialEq)]
+struct DiffLine {
+    depth: usize,
+    kind: DiffKind,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffKind {
+    /// An Instance that isn't different itself, but has descendants that are.
+    Context,
+    /// An Instance only present in the input file.
+    Added,
+    /// An Instance only present in the project.
+    Removed,
+    /// An Instance present in both with different properties.
+    Changed,
+    /// A property that differs on a changed Instance.
+    Property,
+}
+
+impl DiffKind {
+    fn marker(self) -> &'static str {
+        match self {
+            DiffKind::Context | DiffKind::Property => "  ",
+            DiffKind::Added => "+ ",
+            DiffKind::Removed => "- ",
+            DiffKind::Changed => "~ ",
+        }
+    }
+
+    fn color(self) -> Option<Color> {
+        match self {
+            DiffKind::Context | DiffKind::Property => None,
+            DiffKind::Added => Some(Color::Green),
+            DiffKind::Removed => Some(Color::Red),
+            DiffKind::Changed => Some(Color::Yellow),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TreeDiff {
+    lines: Vec<DiffLine>,
+}
+
+impl TreeDiff {
+    /// Returns the number of added, removed, and changed Instances.
+    fn counts(&self) -> (usize, usize, usize) {
+        let count = |kind| self.lines.iter().filter(|line| line.kind == kind).count();
+
+        (
+            count(DiffKind::Added),
+            count(DiffKind::Removed),
+            count(DiffKind::Changed),
+        )
+    }
+
+    fn push(&mut self, depth: usize, kind: DiffKind, text: String) {
+        self.lines.push(DiffLine { depth, kind, text });
+    }
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let indent = self.depth * 2;
+        write!(f, "{:indent$}{}{}", "", self.kind.marker(), self.text)
+    }
+}
+
+impl fmt::Display for TreeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+struct DiffContext<'a> {
+    project: &'a Project,
+    ignore_patterns: Option<Vec<IgnorableGlob>>,
+    old_tree: &'a RojoTree,
+    old_dom: &'a WeakDom,
+    new_dom: &'a WeakDom,
+    old_hashes: HashMap<Ref, Hash>,
+    new_hashes: HashMap<Ref, Hash>,
+}
+
+/// Computes the differences between the tree built from a project and the
+/// tree read from a Roblox file. The roots of the trees are always compared,
+/// regardless of their names.
+///
+/// Both trees are filtered the way syncback filters them first, and subtrees
+/// that syncback would skip aren't compared, so that only differences that
+/// syncback would write are reported.
+fn diff_trees(
+    vfs: &Vfs,
+    project: &Project,
+    old_tree: &mut RojoTree,
+    new_dom: &mut WeakDom,
+) -> anyhow::Result<TreeDiff> {
+    prefilter_trees(vfs, old_tree, new_dom, project);
+
+    let ignore_patterns = project
+        .syncback_rules
+        .as_ref()
+        .map(|rules| rules.compile_globs())
+        .transpose()?;
+
+    let old_dom = old_tree.inner();
+    let context = DiffContext {
+        project,
+        ignore_patterns,
+        old_tree,
+        old_dom,
+        new_dom,
+        old_hashes: hash_tree(project, old_dom, old_dom.root_ref()),
+        new_hashes: hash_tree(project, new_dom, new_dom.root_ref()),
+    };
+
+    let mut diff = TreeDiff::default();
+    diff_instance(
+        &context,
+        old_dom.root_ref(),
+        new_dom.root_ref(),
+        &project.file_location,
+        0,
+        &mut diff,
+    );
+
+    Ok(diff)
+}
+
+fn diff_instance(
+    context: &DiffContext,
+    old_ref: Ref,
+    new_ref: Ref,
+    path: &Path,
+    depth: usize,
+    diff: &mut TreeDiff,
+) {
+    // Hashes include descendants, so matching hashes mean the subtrees are
+    // identical and there's nothing to report.
+    if context.old_hashes.get(&old_ref) == context.new_hashes.get(&new_ref) {
+        return;
+    }
+
+    let old_inst = context.old_dom.get_by_ref(old_ref).unwrap();
+    let new_inst = context.new_dom.get_by_ref(new_ref).unwrap();
+
+    let changed_properties = diff_properties(context.project, old_inst, new_inst);
+    let kind = if changed_properties.is_empty() {
+        DiffKind::Context
+    } else {
+        DiffKind::Changed
+    };
+    let first_line = diff.lines.len();
+    diff.push(depth, kind, display_instance(new_inst));
+
+    for (name, old_value, new_value) in changed_properties {
+        diff.push(
+            depth + 1,
+            DiffKind::Property,
+            format!(
+                "{name}: {} -> {}",
+                display_value(old_value),
+                display_value(new_value)
+            ),
+        );
+    }
+
+    let old_children = old_inst.children();
+    let mut paired = vec![false; old_children.len()];
+
+    for &new_child_ref in new_inst.children() {
+        let new_child = context.new_dom.get_by_ref(new_child_ref).unwrap();
+        let child_inst_path = inst_path(context.new_dom, new_child_ref);
+
+        let matching = old_children.iter().enumerate().find(|(index, old_ref)| {
+            let old_child = context.old_dom.get_by_ref(**old_ref).unwrap();
+            !paired[*index]
+                && old_child.name == new_child.name
+                && old_child.class == new_child.class
+        });
+
+        match matching {
+            Some((index, &old_child_ref)) => {
+                paired[index] = true;
+                let child_path = instance_path(context, old_child_ref, path);
+                if !is_ignored(
+                    context.project,
+                    &context.ignore_patterns,
+                    &child_inst_path,
+                    &child_path,
+                ) {
+                    diff_instance(
+                        context,
+                        old_child_ref,
+                        new_child_ref,
+                        &child_path,
+                        depth + 1,
+                        diff,
+                    );
+                }
+            }
+            None => {
+                let child_path = path.join(new_child.name.as_str());
+                if !is_ignored(
+                    context.project,
+                    &context.ignore_patterns,
+                    &child_inst_path,
+                    &child_path,
+                ) {
+                    diff.push(depth + 1, DiffKind::Added, display_instance(new_child));
+                }
+            }
+        }
+    }
+
+    for (index, &old_child_ref) in old_children.iter().enumerate() {
+        if paired[index] {
+            continue;
+        }
+        let child_inst_path = inst_path(context.old_dom, old_child_ref);
+        let child_path = instance_path(context, old_child_ref, path);
+        if !is_ignored(
+            context.project,
+            &context.ignore_patterns,
+            &child_inst_path,
+            &child_path,
+        ) {
+            let old_child = context.old_dom.get_by_ref(old_child_ref).unwrap();
+            diff.push(depth + 1, DiffKind::Removed, display_instance(old_child));
+        }
+    }
+
+    // The subtrees can differ only in places that syncback skips, which
+    // leaves nothing to show below an unchanged Instance.
+    if kind == DiffKind::Context && diff.lines.len() == first_line + 1 {
+        diff.lines.pop();
+    }
+}
+
+/// Returns the path of the file that an Instance in the project came from,
+/// which is where syncback would write it. Instances without one of their
+/// own are put inside of `parent_path`.
+fn instance_path(context: &DiffContext, old_ref: Ref, parent_path: &Path) -> PathBuf {
+    let metadata = context.old_tree.get_metadata(old_ref);
+    match metadata.and_then(|meta| meta.instigating_source.as_ref()) {
+        Some(InstigatingSource::Path(path)) => path.clone(),
+        Some(InstigatingSource::ProjectNode {
+            path,
+            node:
+                ProjectNode {
+                    path: Some(node_path),
+                    ..
+                },
+            ..
+        }) => path.parent().unwrap().join(node_path.path()),
+        _ => {
+            let name = context.old_dom.get_by_ref(old_ref).unwrap().name.as_str();
+            parent_path.join(name)
+        }
+    }
+}
+
+/// Returns every property that differs between two Instances, sorted by
+/// name. Properties are filtered the same way syncback filters them, so
+/// properties at their default values are treated as missing.
+fn diff_properties<'a>(
+    project: &Project,
+    old_inst: &'a Instance,
+    new_inst: &'a Instance,
+) -> Vec<(Ustr, Option<&'a Variant>, Option<&'a Variant>)> {
+    let old_properties = filter_properties(project, old_inst);
+    let new_properties = filter_properties(project, new_inst);
+
+    let mut changed = Vec::new();
+    for (&name, &old_value) in &old_properties {
+        match new_properties.get(&name) {
+            Some(&new_value) if variant_eq(old_value, new_value) => {}
+            new_value => changed.push((name, Some(old_value), new_value.copied())),
+        }
+    }
+    for (&name, &new_value) in &new_properties {
+        if !old_properties.contains_key(&name) {
+            changed.push((name, None, Some(new_value)));
+        }
+    }
+
+    changed.sort_unstable_by(|(a, _, _), (b, _, _)| a.as_str().cmp(b.as_str()));
+    changed
+}
+
+fn display_instance(inst: &Instance) -> String {
+    format!("{} ({})", inst.name, inst.class)
+}
+
+fn display_value(value: Option<&Variant>) -> String {
+    let Some(value) = value else {
+        return "<default>".to_owned();
+    };
+
+    let mut output = String::new();
+    match value {
+        Variant::String(string) if string.contains('\n') => {
+            write!(output, "<{} lines>", string.lines().count()).unwrap();
+        }
+        Variant::String(string) => write!(output, "{string:?}").unwrap(),
+        _ => write!(output, "{value:?}").unwrap(),
+    }
+
+    if output.len() > MAX_VALUE_LENGTH {
+        let mut end = MAX_VALUE_LENGTH;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.truncate(end);
+        output.push_str("...");
+    }
+
+    output
+}
+
+fn print_diff(diff: &TreeDiff, color: ColorChoice) -> io::Result<()> {
+    let writer = BufferWriter::stdout(color);
+    let mut buffer = writer.buffer();
+
+    for line in &diff.lines {
+        buffer.set_color(ColorSpec::new().set_fg(line.kind.color()))?;
+        writeln!(&mut buffer, "{line}")?;
+    }
+    buffer.reset()?;
+
+    writer.print(&buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+    use rbx_dom_weak::InstanceBuilder;
+
+    fn session_for(snapshot: VfsSnapshot) -> ServeSession {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo", snapshot).unwrap();
+
+        ServeSession::new(Vfs::new(imfs), "/foo").unwrap()
+    }
+
+    fn project_session() -> ServeSession {
+        session_for(VfsSnapshot::dir([(
+            "default.project.json",
+            VfsSnapshot::file(
+                r#"{
+                            "name": "foo",
+                            "tree": {
+                                "$className": "Folder",
+                                "Unchanged": {
+                                    "$className": "StringValue",
+                                    "$properties": { "Value": "same" }
+                                },
+                                "Changed": {
+                                    "$className": "StringValue",
+                                    "$properties": { "Value": "old" }
+                                },
+                                "Parent": {
+                                    "$className": "Folder",
+                                    "Removed": { "$className": "Folder" }
+                                }
+                            }
+                        }"#,
+            ),
+        )]))
+    }
+
+    #[test]
+    fn identical_trees() {
+        let session = project_session();
+        let mut tree = session.tree();
+
+        let mut new_dom = WeakDom::new(
+            InstanceBuilder::new("Folder")
+                .with_name("Game")
+                .with_child(
+                    InstanceBuilder::new("StringValue")
+                        .with_name("Unchanged")
+                        .with_property("Value", "same"),
+                )
+                .with_child(
+                    InstanceBuilder::new("StringValue")
+                        .with_name("Changed")
+                        .with_property("Value", "old"),
+                )
+                .with_child(
+                    InstanceBuilder::new("Folder")
+                        .with_name("Parent")
+                        .with_child(InstanceBuilder::new("Folder").with_name("Removed")),
+                ),
+        );
+
+        let diff = diff_trees(
+            session.vfs(),
+            session.root_project(),
+            &mut tree,
+            &mut new_dom,
+        )
+        .unwrap();
+        assert!(diff.lines.is_empty());
+    }
+
+    #[test]
+    fn added_removed_and_changed() {
+        let session = project_session();
+        let mut tree = session.tree();
+
+        let mut new_dom = WeakDom::new(
+            InstanceBuilder::new("Folder")
+                .with_name("Game")
+                .with_child(
+                    InstanceBuilder::new("StringValue")
+                        .with_name("Unchanged")
+                        .with_property("Value", "same"),
+                )
+                .with_child(
+                    InstanceBuilder::new("StringValue")
+                        .with_name("Changed")
+                        .with_property("Value", "new"),
+                )
+                .with_child(
+                    InstanceBuilder::new("Folder")
+                        .with_name("Parent")
+                        .with_child(InstanceBuilder::new("Part").with_name("Added")),
+                ),
+        );
+
+        let diff = diff_trees(
+            session.vfs(),
+            session.root_project(),
+            &mut tree,
+            &mut new_dom,
+        )
+        .unwrap();
+        insta::assert_snapshot!(diff.to_string());
+        assert_eq!(diff.counts(), (1, 1, 1));
+    }
+
+    #[test]
+    fn skips_what_syncback_ignores() {
+        let session = session_for(VfsSnapshot::dir([
+            (
+                "default.project.json",
+                VfsSnapshot::file(
+                    r#"{
+                            "name": "foo",
+                            "tree": {
+                                "$className": "DataModel",
+                                "ReplicatedStorage": {
+                                    "Kept": { "$className": "Folder" },
+                                    "Ignored": { "$className": "Folder" },
+                                    "Src": { "$path": "src" }
+                                },
+                                "Workspace": {}
+                            },
+                            "syncbackRules": {
+                                "ignoreTrees": ["ReplicatedStorage/Ignored"],
+                                "ignorePaths": ["src/Skipped"]
+                            }
+                        }"#,
+                ),
+            ),
+            (
+                "src",
+                VfsSnapshot::dir([(
+                    "Skipped",
+                    VfsSnapshot::dir([("Value.txt", VfsSnapshot::file("old"))]),
+                )]),
+            ),
+        ]));
+        let mut tree = session.tree();
+
+        let mut new_dom = WeakDom::new(InstanceBuilder::new("DataModel").with_name("Game"));
+        let root_ref = new_dom.root_ref();
+        let storage_ref = new_dom.insert(
+            root_ref,
+            InstanceBuilder::new("ReplicatedStorage").with_name("ReplicatedStorage"),
+        );
+        let kept_ref = new_dom.insert(
+            storage_ref,
+            InstanceBuilder::new("Folder").with_name("Kept"),
+        );
+        new_dom.insert(kept_ref, InstanceBuilder::new("Part").with_name("Added"));
+        let ignored_ref = new_dom.insert(
+            storage_ref,
+            InstanceBuilder::new("Folder").with_name("Ignored"),
+        );
+        new_dom.insert(ignored_ref, InstanceBuilder::new("Part").with_name("Added"));
+        let src_ref = new_dom.insert(storage_ref, InstanceBuilder::new("Folder").with_name("Src"));
+        let skipped_ref =
+            new_dom.insert(src_ref, InstanceBuilder::new("Folder").with_name("Skipped"));
+        new_dom.insert(
+            skipped_ref,
+            InstanceBuilder::new("StringValue")
+                .with_name("Value")
+                .with_property("Value", "new"),
+        );
+        new_dom.insert(skipped_ref, InstanceBuilder::new("Part").with_name("Added"));
+
+        // Services the project doesn't have aren't reported, and neither is
+        // the CurrentCamera, since syncCurrentCamera is off by default.
+        new_dom.insert(
+            root_ref,
+            InstanceBuilder::new("Lighting").with_name("Lighting"),
+        );
+        let workspace_ref = new_dom.insert(
+            root_ref,
+            InstanceBuilder::new("Workspace").with_name("Workspace"),
+        );
+        let camera_ref = new_dom.insert(
+            workspace_ref,
+            InstanceBuilder::new("Camera").with_name("Camera"),
+        );
+        new_dom
+            .get_by_ref_mut(workspace_ref)
+            .unwrap()
+            .properties
+            .insert("CurrentCamera".into(), Variant::Ref(camera_ref));
+
+        let diff = diff_trees(
+            session.vfs(),
+            session.root_project(),
+            &mut tree,
+            &mut new_dom,
+        )
+        .unwrap();
+        insta::assert_snapshot!(diff.to_string());
+        assert_eq!(diff.counts(), (1, 0, 0));
+    }
+}
@@ -25,7 +25,8 @@ impl FmtProjectCommand {
         let project = Project::load_fuzzy(&vfs, &base_path)?
             .context("A project file is required to run 'rojo fmt-project'")?;
 
-        let serialized = serde_json::to_string_pretty(&project)
+        let serialized = project
+            .to_file_contents()
             .context("could not re-encode project file as JSON")?;
 
         fs_err::write(&project.file_location, serialized)
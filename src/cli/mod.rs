@@ -1,10 +1,13 @@
 //! Defines Rojo's CLI through clap types.
 
 mod build;
+mod check;
+mod diff;
 mod doc;
 mod fmt_project;
 mod init;
 mod plugin;
+mod schema;
 mod serve;
 mod sourcemap;
 mod syncback;
@@ -16,11 +19,16 @@ use anyhow::Context;
 use clap::Parser;
 use thiserror::Error;
 
+use crate::{preprocess::DefineValue, snapshot_middleware::set_external_commands_allowed};
+
 pub use self::build::BuildCommand;
+pub use self::check::CheckCommand;
+pub use self::diff::DiffCommand;
 pub use self::doc::DocCommand;
 pub use self::fmt_project::FmtProjectCommand;
 pub use self::init::{InitCommand, InitKind};
 pub use self::plugin::{PluginCommand, PluginSubcommand};
+pub use self::schema::SchemaCommand;
 pub use self::serve::ServeCommand;
 pub use self::sourcemap::SourcemapCommand;
 pub use self::syncback::SyncbackCommand;
@@ -40,6 +48,8 @@ pub struct Options {
 
 impl Options {
     pub fn run(self) -> anyhow::Result<()> {
+        set_external_commands_allowed(self.global.allow_external_commands);
+
         match self.subcommand {
             Subcommand::Init(subcommand) => subcommand.run(),
             Subcommand::Serve(subcommand) => subcommand.run(self.global),
@@ -50,6 +60,9 @@ impl Options {
             Subcommand::Doc(subcommand) => subcommand.run(),
             Subcommand::Plugin(subcommand) => subcommand.run(),
             Subcommand::Syncback(subcommand) => subcommand.run(self.global),
+            Subcommand::Diff(subcommand) => subcommand.run(self.global),
+            Subcommand::Check(subcommand) => subcommand.run(self.global),
+            Subcommand::Schema(subcommand) => subcommand.run(),
         }
     }
 }
@@ -63,6 +76,11 @@ pub struct GlobalOptions {
     /// Set color behavior. Valid values are auto, always, and never.
     #[clap(long("color"), global(true), default_value("auto"))]
     pub color: ColorChoice,
+
+    /// Allow sync rules that use the external middleware to run their
+    /// commands. Only pass this for projects you trust.
+    #[clap(long, global(true))]
+    pub allow_external_commands: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -113,6 +131,36 @@ pub struct ColorChoiceParseError {
     attempted: String,
 }
 
+/// The format that a command's results are written to stdout in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Text,
+
+    /// A JSON document, for use by other tools.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = OutputFormatParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(OutputFormatParseError {
+                attempted: source.to_owned(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid output format '{attempted}'. Valid values are: text, json")]
+pub struct OutputFormatParseError {
+    attempted: String,
+}
+
 #[derive(Debug, Parser)]
 pub enum Subcommand {
     Init(InitCommand),
@@ -124,6 +172,22 @@ pub enum Subcommand {
     Doc(DocCommand),
     Plugin(PluginCommand),
     Syncback(SyncbackCommand),
+    Diff(DiffCommand),
+    Check(CheckCommand),
+    Schema(SchemaCommand),
+}
+
+/// Parses a `--define` option, which is either `NAME` or `NAME=VALUE`.
+pub(super) fn parse_define(define: &str) -> Result<(String, DefineValue), String> {
+    let (name, value) = match define.split_once('=') {
+        Some((name, value)) => (name, DefineValue::parse(value)),
+        None => (define, DefineValue::Bool(true)),
+    };
+    if name.is_empty() {
+        return Err(format!("'{define}' does not name anything to define"));
+    }
+
+    Ok((name.to_owned(), value))
 }
 
 pub(super) fn resolve_path(path: &Path) -> anyhow::Result<Cow<'_, Path>> {
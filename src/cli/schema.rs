@@ -0,0 +1,39 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+
+use crate::schema::{generate_schema, SchemaKind};
+
+/// Generates a JSON Schema for one of the kinds of files Rojo reads, for use
+/// in editors. Valid kinds are project, model, meta, and init-meta.
+#[derive(Debug, Parser)]
+pub struct SchemaCommand {
+    /// The kind of file to generate a schema for.
+    pub kind: SchemaKind,
+
+    /// Where to write the schema. If not specified, it's written to stdout.
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+impl SchemaCommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let schema = generate_schema(self.kind);
+        let mut contents =
+            serde_json::to_vec_pretty(&schema).context("could not encode schema as JSON")?;
+        contents.push(b'\n');
+
+        match &self.output {
+            Some(output) => fs_err::write(output, contents)?,
+            None => {
+                let stdout = std::io::stdout();
+                let mut handle = stdout.lock();
+                handle.write_all(&contents)?;
+                handle.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
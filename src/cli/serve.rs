@@ -1,17 +1,23 @@
 use std::{
+    collections::HashSet,
     io::{self, Write},
     net::{IpAddr, Ipv4Addr},
-    path::PathBuf,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread,
 };
 
+use anyhow::{bail, Context as _};
 use clap::Parser;
 use memofs::Vfs;
+use rayon::prelude::*;
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
-use crate::{serve_session::ServeSession, web::LiveServer};
+use crate::{
+    preprocess::DefineValue, serve_session::ServeSession, web::LiveServer, workspace::Workspace,
+};
 
-use super::{resolve_path, GlobalOptions};
+use super::{parse_define, resolve_path, GlobalOptions};
 
 const DEFAULT_BIND_ADDRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
 const DEFAULT_PORT: u16 = 34872;
@@ -39,15 +45,32 @@ pub struct ServeCommand {
     /// validation for binds where it is otherwise off (such as `0.0.0.0`).
     #[clap(long, value_delimiter = ',')]
     pub allowed_hosts: Vec<String>,
+
+    /// The name of a profile from the project file to use.
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// Defines a name for preprocessing `--#if` directives in Lua sources,
+    /// given as `NAME` or `NAME=VALUE`. Can be given more than once.
+    #[clap(long = "define", value_name = "NAME[=VALUE]", parse(try_from_str = parse_define))]
+    pub defines: Vec<(String, DefineValue)>,
 }
 
 impl ServeCommand {
     pub fn run(self, global: GlobalOptions) -> anyhow::Result<()> {
         let project_path = resolve_path(&self.project)?;
+        if let Some(workspace_path) = Workspace::locate(&project_path) {
+            return self.run_workspace(&workspace_path, global);
+        }
 
         let vfs = Vfs::new_default()?;
 
-        let session = Arc::new(ServeSession::new(vfs, project_path)?);
+        let session = Arc::new(ServeSession::new_with_defines(
+            vfs,
+            project_path,
+            self.profile.as_deref(),
+            &self.defines.into_iter().collect(),
+        )?);
 
         let ip = self
             .address
@@ -70,14 +93,98 @@ impl ServeCommand {
         let server = LiveServer::new(session);
 
         server.start((ip, port).into(), allowed_hosts, || {
-            let _ = show_start_message(ip, port, global.color.into());
+            let _ = show_start_message(ip, port, None, global.color.into());
         })?;
 
         Ok(())
     }
+
+    /// Serves every project in the workspace from this process, each on its
+    /// own port.
+    fn run_workspace(self, workspace_path: &Path, global: GlobalOptions) -> anyhow::Result<()> {
+        if self.port.is_some() {
+            bail!(
+                "--port can't be used with a workspace. Give each project a servePort in \
+                 the workspace file or its project file instead."
+            );
+        }
+
+        let workspace = Workspace::load(workspace_path)?;
+        let defines = self.defines.iter().cloned().collect();
+
+        let sessions = workspace
+            .projects
+            .par_iter()
+            .map(|project| {
+                let vfs = Vfs::new_default()?;
+                let profile = project.profile.as_deref().or(self.profile.as_deref());
+                let session = ServeSession::new_with_defines(vfs, &project.path, profile, &defines)
+                    .with_context(|| format!("{} failed to load", project.path.display()))?;
+
+                let port = project
+                    .serve_port
+                    .or_else(|| session.project_port())
+                    .with_context(|| {
+                        format!(
+                            "{} needs a servePort in the workspace file or its project file, \
+                             since every project in a workspace is served on its own port",
+                            project.path.display()
+                        )
+                    })?;
+
+                Ok((Arc::new(session), port))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut ports = HashSet::new();
+        for (session, port) in &sessions {
+            if !ports.insert(*port) {
+                bail!(
+                    "More than one project in the workspace uses port {port}, including {}",
+                    session.project_name()
+                );
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        for (session, port) in sessions {
+            let ip = self
+                .address
+                .or_else(|| session.serve_address())
+                .unwrap_or(DEFAULT_BIND_ADDRESS.into());
+            let allowed_hosts = if self.allowed_hosts.is_empty() {
+                session.serve_allowed_hosts().to_vec()
+            } else {
+                self.allowed_hosts.clone()
+            };
+            let name = session.project_name().to_owned();
+            let color = global.color.into();
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                let server = LiveServer::new(session);
+                let result = server.start((ip, port).into(), allowed_hosts, || {
+                    let _ = show_start_message(ip, port, Some(&name), color);
+                });
+                let _ = sender.send(result);
+            });
+        }
+        drop(sender);
+
+        // Servers only stop when they fail, so the first one to stop takes
+        // the rest down with it.
+        receiver
+            .recv()
+            .context("Every Rojo server stopped without an error")?
+    }
 }
 
-fn show_start_message(bind_address: IpAddr, port: u16, color: ColorChoice) -> io::Result<()> {
+fn show_start_message(
+    bind_address: IpAddr,
+    port: u16,
+    project_name: Option<&str>,
+    color: ColorChoice,
+) -> io::Result<()> {
     let mut green = ColorSpec::new();
     green.set_fg(Some(Color::Green)).set_bold(true);
 
@@ -90,7 +197,10 @@ fn show_start_message(bind_address: IpAddr, port: u16, color: ColorChoice) -> io
         bind_address.to_string()
     };
 
-    writeln!(&mut buffer, "Rojo server listening:")?;
+    match project_name {
+        Some(name) => writeln!(&mut buffer, "Rojo server for {name} listening:")?,
+        None => writeln!(&mut buffer, "Rojo server listening:")?,
+    }
 
     write!(&mut buffer, "  Address: ")?;
     buffer.set_color(&green)?;
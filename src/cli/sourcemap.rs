@@ -5,7 +5,7 @@ use std::{
     path::{self, Path, PathBuf},
 };
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Parser;
 use fs_err::File;
 use memofs::Vfs;
@@ -17,6 +17,7 @@ use tokio::runtime::Runtime;
 use crate::{
     serve_session::ServeSession,
     snapshot::{AppliedPatchSet, InstanceWithMeta, RojoTree},
+    workspace::{run_for_each, Workspace},
 };
 
 use super::resolve_path;
@@ -68,33 +69,36 @@ pub struct SourcemapCommand {
     /// Whether the sourcemap should use absolute paths instead of relative paths.
     #[clap(long)]
     pub absolute: bool,
+
+    /// The name of a profile from the project file to use.
+    #[clap(long)]
+    pub profile: Option<String>,
 }
 
 impl SourcemapCommand {
     pub fn run(self) -> anyhow::Result<()> {
+        // Pre-build a rayon threadpool with a low number of threads to avoid
+        // dynamic creation overhead on systems with a high number of cpus.
+        log::trace!("Setting rayon global threadpool");
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_cpus::get().min(6))
+            .build_global()
+            .ok();
+
         let project_path = dunce::canonicalize(resolve_path(&self.project)?)?;
+        if let Some(workspace_path) = Workspace::locate(&project_path) {
+            return self.run_workspace(&workspace_path);
+        }
 
         log::trace!("Constructing filesystem with StdBackend");
         let vfs = Vfs::new_default()?;
         vfs.set_watch_enabled(self.watch);
 
         log::trace!("Setting up session for sourcemap generation");
-        let session = ServeSession::new(vfs, project_path)?;
+        let session = ServeSession::new_with_profile(vfs, project_path, self.profile.as_deref())?;
         let mut cursor = session.message_queue().cursor();
 
-        let filter = if self.include_non_scripts {
-            filter_nothing
-        } else {
-            filter_non_scripts
-        };
-
-        // Pre-build a rayon threadpool with a low number of threads to avoid
-        // dynamic creation overhead on systems with a high number of cpus.
-        log::trace!("Setting rayon global threadpool");
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(num_cpus::get().min(6))
-            .build_global()
-            .ok();
+        let filter = self.filter();
 
         log::trace!("Writing initial sourcemap");
         write_sourcemap(&session, self.output.as_deref(), filter, self.absolute)?;
@@ -125,6 +129,54 @@ impl SourcemapCommand {
 
         Ok(())
     }
+
+    /// Writes a sourcemap for every project in the workspace that has one.
+    fn run_workspace(self, workspace_path: &Path) -> anyhow::Result<()> {
+        if self.output.is_some() {
+            bail!(
+                "--output can't be used with a workspace, since the workspace file gives \
+                 the sourcemap path of each project"
+            );
+        }
+        if self.watch {
+            bail!("--watch can't be used with a workspace");
+        }
+
+        let workspace = Workspace::load(workspace_path)?;
+        let projects: Vec<_> = workspace
+            .projects
+            .iter()
+            .filter(|project| project.sourcemap.is_some())
+            .collect();
+        let filter = self.filter();
+
+        run_for_each(&projects, |project| {
+            let project_path = dunce::canonicalize(&project.path)?;
+
+            let vfs = Vfs::new_default()?;
+            vfs.set_watch_enabled(false);
+
+            let profile = project.profile.as_deref().or(self.profile.as_deref());
+            let session = ServeSession::new_with_profile(vfs, project_path, profile)?;
+            write_sourcemap(
+                &session,
+                project.sourcemap.as_deref(),
+                filter,
+                self.absolute,
+            )?;
+
+            forget(session);
+            Ok(())
+        })
+    }
+
+    fn filter(&self) -> fn(&InstanceWithMeta) -> bool {
+        if self.include_non_scripts {
+            filter_nothing
+        } else {
+            filter_non_scripts
+        }
+    }
 }
 
 fn filter_nothing(_instance: &InstanceWithMeta) -> bool {
@@ -286,6 +338,7 @@ mod test {
             include_non_scripts: false,
             watch: false,
             absolute: false,
+            profile: None,
         };
         assert!(sourcemap_command.run().is_ok());
 
@@ -312,6 +365,7 @@ mod test {
             include_non_scripts: false,
             watch: false,
             absolute: true,
+            profile: None,
         };
         assert!(sourcemap_command.run().is_ok());
 
@@ -325,8 +379,8 @@ mod test {
                 match value {
                     Content::Seq(ref mut vec) => {
                         for path in vec.iter().map(|i| i.as_str().unwrap()) {
-                            assert_eq!(fs_err::canonicalize(path).is_ok(), true, "path was not valid");
-                            assert_eq!(Path::new(path).is_absolute(), true, "path was not absolute");
+                            assert!(fs_err::canonicalize(path).is_ok(), "path was not valid");
+                            assert!(Path::new(path).is_absolute(), "path was not absolute");
 
                             paths_count += 1;
                         }
@@ -1,28 +1,37 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     io::{self, BufReader, Write as _},
     mem::forget,
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Context;
+use blake3::Hash;
 use clap::Parser;
+use crossbeam_channel::RecvTimeoutError;
 use fs_err::File;
-use memofs::Vfs;
-use rbx_dom_weak::{InstanceBuilder, WeakDom};
+use memofs::{Vfs, VfsEvent};
+use rbx_dom_weak::{types::Ref, InstanceBuilder, WeakDom};
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
 
 use crate::{
     path_serializer::display_absolute,
     serve_session::ServeSession,
-    syncback::{syncback_loop, FsSnapshot},
+    snapshot::RojoTree,
+    syncback::{syncback_loop_incremental, FsSnapshot, SyncbackCache, SyncbackReport},
+    Project,
 };
 
-use super::{resolve_path, GlobalOptions};
+use super::{resolve_path, GlobalOptions, OutputFormat};
 
-const UNKNOWN_INPUT_KIND_ERR: &str = "Could not detect what kind of file was inputted. \
+pub(super) const UNKNOWN_INPUT_KIND_ERR: &str = "Could not detect what kind of file was inputted. \
                                        Expected input file to end in .rbxl, .rbxlx, .rbxm, or .rbxmx.";
 
+/// How long to wait after the input file changes before syncing back, in case
+/// it's still being written to.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
 /// Performs 'syncback' for the provided project, using the `input` file
 /// given.
 ///
@@ -54,6 +63,43 @@ pub struct SyncbackCommand {
     /// If provided, the prompt for writing to the file system is skipped.
     #[clap(long, short = 'y')]
     pub non_interactive: bool,
+
+    /// The format to list changes in. Valid values are text and json.
+    ///
+    /// With json, a report of every file that will be written or removed is
+    /// emitted into stdout, including changes that are skipped because of
+    /// the project's syncback rules. This implies `--list`.
+    #[clap(long, default_value = "text")]
+    pub format: OutputFormat,
+
+    /// If provided, syncback keeps running and syncs back again every time
+    /// the input file changes, like when it's saved from Roblox Studio.
+    ///
+    /// Only the first syncback asks before writing to the file system.
+    #[clap(long)]
+    pub watch: bool,
+
+    /// If provided, the project's tree is cached in a `.rojo-syncback-cache`
+    /// file next to the project file, and only files that have changed since
+    /// the cache was written are read again.
+    #[clap(long)]
+    pub incremental: bool,
+}
+
+/// The project that's being synced back to.
+#[derive(Clone, Copy)]
+struct ProjectOld<'a> {
+    vfs: &'a Vfs,
+    project: &'a Project,
+    /// The canonical path the project was loaded from.
+    start_path: &'a Path,
+}
+
+/// What's needed to write the syncback cache after syncback is run.
+struct CacheState {
+    path: PathBuf,
+    read_at: SystemTime,
+    hashes: HashMap<Ref, Hash>,
 }
 
 impl SyncbackCommand {
@@ -62,24 +108,90 @@ impl SyncbackCommand {
         let path_new = resolve_path(&self.input)?;
 
         let input_kind = FileKind::from_path(&path_new).context(UNKNOWN_INPUT_KIND_ERR)?;
-        let dom_start_timer = Instant::now();
-        let dom_new = read_dom(&path_new, input_kind)?;
-        log::debug!(
-            "Finished opening file in {:0.02}s",
-            dom_start_timer.elapsed().as_secs_f32()
-        );
 
+        // The project's tree is built again from the file system before
+        // every syncback instead of being kept up to date by watching it, so
+        // that each syncback sees every file written by the one before it.
         let vfs = Vfs::new_default()?;
         vfs.set_watch_enabled(false);
+        let start_path = vfs.canonicalize(&path_old)?;
+        let project = Project::load_initial_project(&vfs, &start_path)?;
+
+        let project_old = ProjectOld {
+            vfs: &vfs,
+            project: &project,
+            start_path: &start_path,
+        };
+
+        // The input is watched before the first syncback runs, so that saves
+        // made while it's running aren't missed.
+        let input_vfs = if self.watch {
+            Some(watch_input_folder(&path_new)?)
+        } else {
+            None
+        };
+
+        let written = self.syncback(&project_old, &path_new, input_kind, &global, true)?;
+
+        if let Some(input_vfs) = input_vfs.filter(|_| written) {
+            self.watch_input(&input_vfs, &project_old, &path_new, input_kind, &global)?;
+        }
 
+        Ok(())
+    }
+
+    /// Builds the project's tree from the file system, restoring it from the
+    /// syncback cache if `--incremental` was given.
+    fn load_tree(
+        &self,
+        project_old: &ProjectOld,
+    ) -> anyhow::Result<(RojoTree, Option<CacheState>)> {
         let project_start_timer = Instant::now();
-        let session_old = ServeSession::new(vfs, path_old.clone())?;
+        let (tree, cache) = if self.incremental {
+            let (tree, cache) = load_incremental(project_old)?;
+            (tree, Some(cache))
+        } else {
+            let tree = ServeSession::build_tree(
+                project_old.vfs,
+                project_old.project,
+                project_old.start_path,
+                &BTreeMap::new(),
+            )?;
+            (tree, None)
+        };
         log::debug!(
             "Finished opening project in {:0.02}s",
             project_start_timer.elapsed().as_secs_f32()
         );
 
-        let mut dom_old = session_old.tree();
+        Ok((tree, cache))
+    }
+
+    /// Runs syncback once with the current contents of the input file and
+    /// project.
+    ///
+    /// With `--incremental`, the project's tree is written to the syncback
+    /// cache right before the file system is, so nothing is cached for dry
+    /// runs or declined prompts.
+    ///
+    /// Returns whether syncback went ahead, which is only false if the user
+    /// declined the prompt to write to the file system.
+    fn syncback(
+        &self,
+        project_old: &ProjectOld,
+        path_new: &Path,
+        input_kind: FileKind,
+        global: &GlobalOptions,
+        prompt: bool,
+    ) -> anyhow::Result<bool> {
+        let (mut dom_old, cache) = self.load_tree(project_old)?;
+
+        let dom_start_timer = Instant::now();
+        let dom_new = read_dom(path_new, input_kind)?;
+        log::debug!(
+            "Finished opening file in {:0.02}s",
+            dom_start_timer.elapsed().as_secs_f32()
+        );
 
         log::debug!("Old root: {}", dom_old.inner().root().class);
         log::debug!("New root: {}", dom_new.root().class);
@@ -99,24 +211,43 @@ impl SyncbackCommand {
 
         let syncback_timer = Instant::now();
         eprintln!("Beginning syncback...");
-        let snapshot = syncback_loop(
-            session_old.vfs(),
+        let mut report = SyncbackReport::new();
+        let (mut old_hashes, cache) = match cache {
+            Some(CacheState {
+                path,
+                read_at,
+                hashes,
+            }) => (hashes, Some((path, read_at))),
+            None => (HashMap::new(), None),
+        };
+        let snapshot = syncback_loop_incremental(
+            project_old.vfs,
             &mut dom_old,
             dom_new,
-            session_old.root_project(),
+            project_old.project,
+            &mut report,
+            &mut old_hashes,
         )?;
         log::debug!(
             "Syncback finished in {:.02}s!",
             syncback_timer.elapsed().as_secs_f32()
         );
 
-        let base_path = session_old.root_project().folder_location();
-        if self.list {
-            list_files(&snapshot, global.color.into(), base_path)?;
+        let base_path = project_old.project.folder_location();
+        match self.format {
+            OutputFormat::Json => {
+                let mut stdout = io::stdout().lock();
+                serde_json::to_writer_pretty(&mut stdout, &report)?;
+                writeln!(stdout)?;
+            }
+            OutputFormat::Text if self.list => {
+                list_files(&snapshot, global.color.into(), base_path)?;
+            }
+            OutputFormat::Text => {}
         }
 
         if !self.dry_run {
-            if !self.non_interactive {
+            if prompt && !self.non_interactive {
                 eprintln!(
                     "Would write {} files/folders and remove {} files/folders.",
                     snapshot.added_paths().len(),
@@ -129,12 +260,33 @@ impl SyncbackCommand {
                 line = line.trim().to_lowercase();
                 if line != "y" {
                     eprintln!("Aborting due to user input!");
-                    return Ok(());
+                    forget(dom_old);
+                    return Ok(false);
                 }
             }
+
+            // The tree has to be cached before anything is written, since the
+            // cache can't include changes that haven't been read back yet.
+            if let Some((path, read_at)) = cache {
+                let cache_timer = Instant::now();
+                if let Err(err) =
+                    SyncbackCache::new(project_old.vfs, &dom_old, &old_hashes, read_at).save(&path)
+                {
+                    log::warn!("Could not write the syncback cache: {err:#}");
+                }
+                log::debug!(
+                    "Wrote syncback cache in {:.02}s",
+                    cache_timer.elapsed().as_secs_f32()
+                );
+            }
+
             eprintln!("Writing to the file system...");
-            snapshot.write_to_vfs(base_path, session_old.vfs())?;
-            eprintln!("Finished syncback.")
+            snapshot.write_to_vfs(base_path, project_old.vfs)?;
+            eprintln!(
+                "Finished syncback. Wrote {} files/folders and removed {} files/folders.",
+                snapshot.added_paths().len(),
+                snapshot.removed_paths().len()
+            );
         } else {
             eprintln!(
                 "Would write {} files/folders and remove {} files/folders.",
@@ -144,17 +296,131 @@ impl SyncbackCommand {
             eprintln!("Aborting before writing to file system due to `--dry-run`");
         }
 
-        // It is potentially prohibitively expensive to drop a ServeSession,
-        // and the program is about to exit anyway so we're just going to forget
-        // about it.
-        drop(dom_old);
-        forget(session_old);
+        // It is potentially prohibitively expensive to drop the tree, and it
+        // isn't needed anymore, so we're just going to forget about it.
+        forget(dom_old);
+
+        Ok(true)
+    }
+
+    /// Runs syncback again every time the input file changes, until the
+    /// process is stopped.
+    fn watch_input(
+        &self,
+        input_vfs: &Vfs,
+        project_old: &ProjectOld,
+        path_new: &Path,
+        input_kind: FileKind,
+        global: &GlobalOptions,
+    ) -> anyhow::Result<()> {
+        let receiver = input_vfs.event_receiver();
+
+        eprintln!(
+            "Watching {} for changes. Press Ctrl+C to stop.",
+            path_new.display()
+        );
+
+        while let Ok(event) = receiver.recv() {
+            input_vfs.commit_event(&event)?;
+            if !is_input_event(&event, path_new) {
+                continue;
+            }
+
+            // Saving a place can take several writes, so wait for them to
+            // settle before reading it.
+            loop {
+                match receiver.recv_timeout(WATCH_DEBOUNCE) {
+                    Ok(event) => input_vfs.commit_event(&event)?,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+
+            if !path_new.exists() {
+                continue;
+            }
+
+            eprintln!("{} changed.", path_new.display());
+            // A failed syncback shouldn't stop the watcher, since the next
+            // save may fix it.
+            if let Err(err) = self.syncback(project_old, path_new, input_kind, global, false) {
+                log::error!("{err:?}");
+            }
+        }
 
         Ok(())
     }
 }
 
-fn read_dom(path: &Path, file_kind: FileKind) -> anyhow::Result<WeakDom> {
+/// Starts watching the folder that the input file is in. Studio doesn't
+/// always save over the file in place, so the folder is watched instead of
+/// the file itself.
+fn watch_input_folder(path_new: &Path) -> anyhow::Result<Vfs> {
+    let input_folder = path_new
+        .parent()
+        .context("The input file should be inside of a folder")?;
+    let input_vfs = Vfs::new_default()?;
+    input_vfs.read_dir(input_folder)?;
+
+    Ok(input_vfs)
+}
+
+/// Builds the project's tree, restoring it from the syncback cache if
+/// possible.
+fn load_incremental(project_old: &ProjectOld) -> anyhow::Result<(RojoTree, CacheState)> {
+    let ProjectOld {
+        vfs,
+        project,
+        start_path,
+    } = *project_old;
+    let read_at = SystemTime::now();
+    let cache_path = SyncbackCache::path_for(project);
+
+    let restored = match SyncbackCache::load(&cache_path) {
+        Ok(Some(cache)) => cache.restore(vfs, project)?,
+        Ok(None) => None,
+        Err(err) => {
+            log::warn!("Ignoring the syncback cache because: {err:#}");
+            None
+        }
+    };
+
+    let (tree, hashes) = match restored {
+        Some((tree, hashes)) => {
+            log::debug!("Restored project tree from the syncback cache");
+            (tree, hashes)
+        }
+        None => {
+            log::debug!("Building project tree without the syncback cache");
+            let tree = ServeSession::build_tree(vfs, project, start_path, &BTreeMap::new())?;
+            (tree, HashMap::new())
+        }
+    };
+
+    let cache = CacheState {
+        path: cache_path,
+        read_at,
+        hashes,
+    };
+
+    Ok((tree, cache))
+}
+
+/// Returns whether a file system event is for the input file.
+fn is_input_event(event: &VfsEvent, input_path: &Path) -> bool {
+    let path = match event {
+        VfsEvent::Create(path) | VfsEvent::Write(path) => path,
+        _ => return false,
+    };
+
+    path == input_path
+        || match (dunce::canonicalize(path), dunce::canonicalize(input_path)) {
+            (Ok(path), Ok(input_path)) => path == input_path,
+            _ => false,
+        }
+}
+
+pub(super) fn read_dom(path: &Path, file_kind: FileKind) -> anyhow::Result<WeakDom> {
     let content = BufReader::new(File::open(path)?);
     match file_kind {
         FileKind::Rbxl => rbx_binary::from_reader(content).with_context(|| {
@@ -216,7 +482,7 @@ fn xml_decode_config() -> rbx_xml::DecodeOptions<'static> {
 
 /// The different kinds of input that Rojo can syncback.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum FileKind {
+pub(super) enum FileKind {
     /// An XML model file.
     Rbxmx,
 
@@ -231,7 +497,7 @@ enum FileKind {
 }
 
 impl FileKind {
-    fn from_path(output: &Path) -> Option<FileKind> {
+    pub(super) fn from_path(output: &Path) -> Option<FileKind> {
         let extension = output.extension()?.to_str()?;
 
         match extension {
@@ -242,6 +508,10 @@ impl FileKind {
             _ => None,
         }
     }
+
+    pub(super) fn is_place(self) -> bool {
+        matches!(self, FileKind::Rbxlx | FileKind::Rbxl)
+    }
 }
 
 fn list_files(snapshot: &FsSnapshot, color: ColorChoice, base_path: &Path) -> io::Result<()> {
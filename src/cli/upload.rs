@@ -34,6 +34,10 @@ pub struct UploadCommand {
     /// Asset ID to upload to.
     #[clap(long = "asset_id")]
     pub asset_id: u64,
+
+    /// The name of a profile from the project file to use.
+    #[clap(long)]
+    pub profile: Option<String>,
 }
 
 impl UploadCommand {
@@ -42,7 +46,7 @@ impl UploadCommand {
 
         let vfs = Vfs::new_default()?;
 
-        let session = ServeSession::new(vfs, project_path)?;
+        let session = ServeSession::new_with_profile(vfs, project_path, self.profile.as_deref())?;
 
         let tree = session.tree();
         let inner_tree = tree.inner();
@@ -0,0 +1,298 @@
+//! Reading and writing the files Rojo deserializes into its own types, like
+//! projects, models, and meta files, which can be written as JSON (with
+//! comments), YAML, or TOML.
+//!
+//! Every format is converted to and from a `serde_json::Value`, so the same
+//! serde types and the same processing of those values work for all of them.
+
+use std::path::Path;
+
+use anyhow::{bail, format_err, Context as _};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Number, Value};
+use yaml_rust2::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
+
+use crate::json;
+
+/// The extensions that project, model, and meta files can have, in the order
+/// that meta files with them are applied.
+pub const EXTENSIONS: [&str; 4] = ["json", "jsonc", "yaml", "toml"];
+
+/// The format of a project, model, or meta file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// JSON, which may contain comments and trailing commas.
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl FileFormat {
+    /// Returns the format of the file at the given path based on its
+    /// extension. Anything that isn't YAML or TOML is read as JSON.
+    ///
+    /// Like [`EXTENSIONS`], this only recognizes `.yaml` for YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") => FileFormat::Yaml,
+            Some("toml") => FileFormat::Toml,
+            _ => FileFormat::Json,
+        }
+    }
+
+    /// Parses the given contents into a `serde_json::Value`. `context` should
+    /// describe the file, and is included in any errors.
+    pub fn parse_value(self, slice: &[u8], context: impl Fn() -> String) -> anyhow::Result<Value> {
+        match self {
+            FileFormat::Json => json::parse_value_from_slice_with_context(slice, context),
+            FileFormat::Yaml => {
+                let text = std::str::from_utf8(slice)
+                    .with_context(|| format!("{}: File is not valid UTF-8", context()))?;
+                let mut documents = YamlLoader::load_from_str(text)
+                    .with_context(|| format!("{}: YAML parse error", context()))?;
+                let document = documents
+                    .pop()
+                    .ok_or_else(|| format_err!("{}: File contains no YAML document", context()))?;
+                if !documents.is_empty() {
+                    bail!("{}: File contains more than one YAML document", context());
+                }
+                yaml_to_json(document).with_context(context)
+            }
+            FileFormat::Toml => {
+                let value: toml::Value = toml::from_slice(slice)
+                    .with_context(|| format!("{}: TOML parse error", context()))?;
+                Ok(toml_to_json(value))
+            }
+        }
+    }
+
+    /// Serializes the given value into this format.
+    pub fn to_vec<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        match self {
+            FileFormat::Json => Ok(serde_json::to_vec_pretty(value)?),
+            FileFormat::Yaml => {
+                let value = serde_json::to_value(value)?;
+                let mut contents = String::new();
+                YamlEmitter::new(&mut contents)
+                    .dump(&json_to_yaml(value))
+                    .context("cannot serialize YAML")?;
+                // The emitter always starts a document with a `---` marker,
+                // which files with only one document don't need.
+                let mut contents = contents
+                    .strip_prefix("---\n")
+                    .unwrap_or(&contents)
+                    .to_owned();
+                contents.push('\n');
+                Ok(contents.into_bytes())
+            }
+            FileFormat::Toml => {
+                let mut value = serde_json::to_value(value)?;
+                // TOML has no null, so fields without a value are left out.
+                remove_nulls(&mut value);
+                let value = toml::Value::try_from(value).context("cannot serialize TOML")?;
+                Ok(toml::to_string_pretty(&value)
+                    .context("cannot serialize TOML")?
+                    .into_bytes())
+            }
+        }
+    }
+}
+
+/// Parses the given contents in the format the path's extension says they're
+/// in and deserializes them into a specific type. `context` should describe
+/// the file, and is included in any errors.
+pub fn from_slice_with_context<T: DeserializeOwned>(
+    path: &Path,
+    slice: &[u8],
+    context: impl Fn() -> String,
+) -> anyhow::Result<T> {
+    match FileFormat::from_path(path) {
+        FileFormat::Json => json::from_slice_with_context(slice, context),
+        format => {
+            let value = format.parse_value(slice, &context)?;
+            serde_json::from_value(value)
+                .with_context(|| format!("{}: Invalid structure", context()))
+        }
+    }
+}
+
+fn yaml_to_json(value: Yaml) -> anyhow::Result<Value> {
+    Ok(match value {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(value) => Value::Bool(value),
+        Yaml::Integer(value) => Value::from(value),
+        Yaml::Real(_) => {
+            let number = value.as_f64().context("invalid YAML number")?;
+            Value::Number(
+                Number::from_f64(number)
+                    .with_context(|| format!("{number} cannot be represented in JSON"))?,
+            )
+        }
+        Yaml::String(value) => Value::String(value),
+        Yaml::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(yaml_to_json)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Yaml::Hash(hash) => {
+            let mut map = Map::with_capacity(hash.len());
+            for (key, value) in hash {
+                let key = match key {
+                    Yaml::String(key) | Yaml::Real(key) => key,
+                    Yaml::Integer(key) => key.to_string(),
+                    Yaml::Boolean(key) => key.to_string(),
+                    other => bail!("YAML keys must be strings, got {other:?}"),
+                };
+                map.insert(key, yaml_to_json(value)?);
+            }
+            Value::Object(map)
+        }
+        Yaml::Alias(_) => bail!("YAML aliases are not supported"),
+        Yaml::BadValue => bail!("invalid YAML value"),
+    })
+}
+
+fn json_to_yaml(value: Value) -> Yaml {
+    match value {
+        Value::Null => Yaml::Null,
+        Value::Bool(value) => Yaml::Boolean(value),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => Yaml::Integer(value),
+            None => Yaml::Real(number.to_string()),
+        },
+        Value::String(value) => Yaml::String(value),
+        Value::Array(values) => Yaml::Array(values.into_iter().map(json_to_yaml).collect()),
+        Value::Object(map) => {
+            let mut hash = Hash::with_capacity(map.len());
+            for (key, value) in map {
+                hash.insert(Yaml::String(key), json_to_yaml(value));
+            }
+            Yaml::Hash(hash)
+        }
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(value) => Value::String(value),
+        toml::Value::Integer(value) => Value::from(value),
+        toml::Value::Float(value) => Number::from_f64(value).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(value) => Value::Bool(value),
+        toml::Value::Datetime(value) => Value::String(value.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn from_path() {
+        assert_eq!(
+            FileFormat::from_path(Path::new("default.project.json")),
+            FileFormat::Json
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("foo.model.jsonc")),
+            FileFormat::Json
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("init.meta.yaml")),
+            FileFormat::Yaml
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("default.project.toml")),
+            FileFormat::Toml
+        );
+    }
+
+    #[test]
+    fn parse_yaml() {
+        let value = FileFormat::Yaml
+            .parse_value(
+                b"name: foo\ntree:\n  $className: Folder\n  $properties:\n    Size: [1, 2.5, 3]\n",
+                || "foo.project.yaml".to_owned(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "name": "foo",
+                "tree": {
+                    "$className": "Folder",
+                    "$properties": { "Size": [1, 2.5, 3] },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_toml() {
+        let value = FileFormat::Toml
+            .parse_value(
+                b"name = \"foo\"\n\n[tree]\n\"$className\" = \"Folder\"\n",
+                || "foo.project.toml".to_owned(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            value,
+            json!({ "name": "foo", "tree": { "$className": "Folder" } })
+        );
+    }
+
+    #[test]
+    fn parse_errors_include_context() {
+        let err = FileFormat::Yaml
+            .parse_value(b"a: [", || "foo.meta.yaml".to_owned())
+            .unwrap_err();
+        assert!(err.to_string().contains("foo.meta.yaml"), "{err}");
+
+        let err = FileFormat::Toml
+            .parse_value(b"a = ", || "foo.meta.toml".to_owned())
+            .unwrap_err();
+        assert!(err.to_string().contains("foo.meta.toml"), "{err}");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = json!({
+            "className": "Part",
+            "properties": { "Anchored": true, "Size": [1, 2.5, 3] },
+            "attributes": { "Name": "foo" },
+            "id": null,
+        });
+
+        for format in [FileFormat::Json, FileFormat::Yaml, FileFormat::Toml] {
+            let contents = format.to_vec(&value).unwrap();
+            let mut parsed = format.parse_value(&contents, String::new).unwrap();
+            remove_nulls(&mut parsed);
+
+            let mut expected = value.clone();
+            remove_nulls(&mut expected);
+            assert_eq!(parsed, expected, "{format:?}");
+        }
+    }
+}
@@ -1,9 +1,13 @@
 //! Wrapper around globset's Glob type that has better serialization
 //! characteristics by coupling Glob and GlobMatcher into a single type.
 
-use std::path::Path;
+use std::{
+    hash::{Hash, Hasher},
+    path::Path,
+};
 
 use globset::{Glob as InnerGlob, GlobMatcher};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
 
 pub use globset::Error;
@@ -25,6 +29,11 @@ impl Glob {
     pub fn is_match<P: AsRef<Path>>(&self, path: P) -> bool {
         self.matcher.is_match(path)
     }
+
+    /// Returns the pattern this glob was created from.
+    pub fn as_str(&self) -> &str {
+        self.inner.glob()
+    }
 }
 
 impl PartialEq for Glob {
@@ -35,12 +44,28 @@ impl PartialEq for Glob {
 
 impl Eq for Glob {}
 
+impl Hash for Glob {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
 impl Serialize for Glob {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         serializer.serialize_str(self.inner.glob())
     }
 }
 
+impl JsonSchema for Glob {
+    fn schema_name() -> String {
+        "Glob".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
 impl<'de> Deserialize<'de> for Glob {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         let glob = String::deserialize(deserializer)?;
@@ -99,6 +124,16 @@ impl Serialize for IgnorableGlob {
     }
 }
 
+impl JsonSchema for IgnorableGlob {
+    fn schema_name() -> String {
+        "IgnorableGlob".to_owned()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        String::json_schema(generator)
+    }
+}
+
 impl<'de> Deserialize<'de> for IgnorableGlob {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         let pattern = String::deserialize(deserializer)?;
@@ -8,23 +8,29 @@ pub mod cli;
 mod tree_view;
 
 mod auth_cookie;
+mod build_report;
 mod change_processor;
+mod file_format;
 mod glob;
 mod json;
 mod lua_ast;
 mod message_queue;
 mod multimap;
 mod path_serializer;
+mod preprocess;
 mod project;
 mod resolution;
 mod rojo_ref;
+mod schema;
 mod serve_session;
 mod session_id;
 mod snapshot;
 mod snapshot_middleware;
+mod strip;
 mod syncback;
 mod variant_eq;
 mod web;
+mod workspace;
 
 // TODO: Work out what we should expose publicly
 
@@ -1,11 +1,15 @@
 //! Defines module for defining a small Lua AST for simple codegen. Rojo uses
 //! this module to convert JSON into generated Lua code.
 
+mod parse;
+
 use std::{
     fmt::{self, Write},
     num::FpCategory,
 };
 
+pub(crate) use self::parse::parse_return;
+
 /// Trait that helps turn a type into an equivalent Lua snippet.
 ///
 /// Designed to be similar to the `Display` trait from Rust's std.
@@ -37,6 +41,13 @@ where
 
 pub(crate) enum Statement {
     Return(Expression),
+
+    /// Returns an expression asserted to be the named type, like
+    /// `return {} :: Name`.
+    ReturnAs(Expression, String),
+
+    /// Declares an exported type, like `export type Name = {}`.
+    ExportType(String, Type),
 }
 
 impl FmtLua for Statement {
@@ -46,6 +57,15 @@ impl FmtLua for Statement {
                 write!(output, "return ")?;
                 literal.fmt_lua(output)
             }
+            Self::ReturnAs(literal, type_name) => {
+                write!(output, "return ")?;
+                literal.fmt_lua(output)?;
+                write!(output, " :: {}", type_name)
+            }
+            Self::ExportType(name, ty) => {
+                write!(output, "export type {} = ", name)?;
+                ty.fmt_lua(output)
+            }
         }
     }
 }
@@ -57,6 +77,7 @@ impl fmt::Display for Statement {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Expression {
     Nil,
     Bool(bool),
@@ -190,6 +211,7 @@ impl FmtLua for Vec<Expression> {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Table {
     pub entries: Vec<(Expression, Expression)>,
 }
@@ -211,6 +233,181 @@ impl FmtLua for Table {
     }
 }
 
+/// A Luau type, as inferred from the value of an `Expression`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Type {
+    Nil,
+    Boolean,
+    Number,
+    String,
+    Any,
+    Array(Box<Type>),
+    Table(TableType),
+    Union(Vec<Type>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TableType {
+    pub fields: Vec<(String, Type)>,
+    pub indexer: Option<(Box<Type>, Box<Type>)>,
+}
+
+impl Type {
+    /// Infers the type of a literal expression. Arrays and indexers take the
+    /// union of the types of all of their values.
+    pub fn infer(value: &Expression) -> Self {
+        match value {
+            Expression::Nil => Self::Nil,
+            Expression::Bool(_) => Self::Boolean,
+            Expression::Number(_) => Self::Number,
+            Expression::String(_) => Self::String,
+            Expression::Array(values) => {
+                Self::Array(Box::new(Self::union(values.iter().map(Self::infer))))
+            }
+            Expression::Table(table) => {
+                let mut fields = Vec::new();
+                let mut index_keys = Vec::new();
+                let mut index_values = Vec::new();
+
+                for (key, value) in &table.entries {
+                    match key {
+                        Expression::String(key) => fields.push((key.clone(), Self::infer(value))),
+                        _ => {
+                            index_keys.push(Self::infer(key));
+                            index_values.push(Self::infer(value));
+                        }
+                    }
+                }
+
+                let indexer = if index_keys.is_empty() {
+                    None
+                } else {
+                    Some((
+                        Box::new(Self::union(index_keys)),
+                        Box::new(Self::union(index_values)),
+                    ))
+                };
+
+                Self::Table(TableType { fields, indexer })
+            }
+        }
+    }
+
+    /// Combines types into one, removing duplicates. An empty set of types
+    /// becomes `any`.
+    fn union(types: impl IntoIterator<Item = Type>) -> Self {
+        let mut unique: Vec<Type> = Vec::new();
+        for ty in types {
+            let members = match ty {
+                Self::Union(members) => members,
+                ty => vec![ty],
+            };
+            for member in members {
+                if !unique.contains(&member) {
+                    unique.push(member);
+                }
+            }
+        }
+
+        match unique.len() {
+            0 => Self::Any,
+            1 => unique.pop().unwrap(),
+            _ => Self::Union(unique),
+        }
+    }
+}
+
+impl FmtLua for Type {
+    fn fmt_lua(&self, output: &mut LuaStream<'_>) -> fmt::Result {
+        match self {
+            Self::Nil => write!(output, "nil"),
+            Self::Boolean => write!(output, "boolean"),
+            Self::Number => write!(output, "number"),
+            Self::String => write!(output, "string"),
+            Self::Any => write!(output, "any"),
+            Self::Array(inner) => {
+                write!(output, "{{ ")?;
+                inner.fmt_lua(output)?;
+                write!(output, " }}")
+            }
+            Self::Table(inner) => inner.fmt_lua(output),
+            Self::Union(members) => {
+                let non_nil: Vec<&Type> = members.iter().filter(|ty| **ty != Self::Nil).collect();
+                let optional = non_nil.len() < members.len();
+
+                if optional && non_nil.len() > 1 {
+                    write!(output, "(")?;
+                }
+                for (index, member) in non_nil.iter().enumerate() {
+                    if index > 0 {
+                        write!(output, " | ")?;
+                    }
+                    member.fmt_lua(output)?;
+                }
+                if optional {
+                    if non_nil.len() > 1 {
+                        write!(output, ")")?;
+                    }
+                    write!(output, "?")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FmtLua for TableType {
+    fn fmt_lua(&self, output: &mut LuaStream<'_>) -> fmt::Result {
+        if self.fields.is_empty() && self.indexer.is_none() {
+            return write!(output, "{{}}");
+        }
+
+        writeln!(output, "{{")?;
+        output.indent();
+
+        for (key, ty) in &self.fields {
+            key.fmt_table_key(output)?;
+            write!(output, ": ")?;
+            ty.fmt_lua(output)?;
+            writeln!(output, ",")?;
+        }
+
+        if let Some((key, value)) = &self.indexer {
+            write!(output, "[")?;
+            key.fmt_lua(output)?;
+            write!(output, "]: ")?;
+            value.fmt_lua(output)?;
+            writeln!(output, ",")?;
+        }
+
+        output.unindent();
+        write!(output, "}}")
+    }
+}
+
+/// Turns the name of an Instance into a name that can be used for a type,
+/// like `game-settings` into `GameSettings`.
+pub(crate) fn type_name_for(name: &str) -> String {
+    let mut type_name: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect();
+
+    if !type_name.starts_with(is_valid_ident_char_start) {
+        type_name.insert_str(0, "Data");
+    }
+
+    type_name
+}
+
 fn is_valid_ident_char_start(value: char) -> bool {
     value.is_ascii_alphabetic() || value == '_'
 }
@@ -335,6 +532,42 @@ impl<'a> LuaStream<'a> {
 mod test {
     use super::*;
 
+    #[test]
+    fn type_names() {
+        assert_eq!(type_name_for("config"), "Config");
+        assert_eq!(type_name_for("game-settings.v2"), "GameSettingsV2");
+        assert_eq!(type_name_for("2024"), "Data2024");
+        assert_eq!(type_name_for(""), "Data");
+    }
+
+    #[test]
+    fn infer_types() {
+        let value = Expression::table(vec![
+            ("name".into(), "Bob".into()),
+            (
+                "mixed".into(),
+                Expression::Array(vec![
+                    Expression::Number(1.0),
+                    "two".into(),
+                    Expression::Number(3.0),
+                ]),
+            ),
+            (
+                "maybe".into(),
+                Expression::Array(vec!["a".into(), Expression::Nil]),
+            ),
+            ("empty".into(), Expression::Array(Vec::new())),
+            (
+                "nested".into(),
+                Expression::table(vec![("my key".into(), Expression::Bool(true))]),
+            ),
+            (Expression::Number(1.0), Expression::Number(5.0)),
+        ]);
+
+        let statement = Statement::ExportType("Data".to_owned(), Type::infer(&value));
+        insta::assert_snapshot!(statement.to_string());
+    }
+
     /// Regression test for https://github.com/rojo-rbx/rojo/issues/314
     #[test]
     fn bug_314() {
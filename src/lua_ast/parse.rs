@@ -0,0 +1,627 @@
+//! A parser for the subset of Lua that Rojo generates from data files, which
+//! is a single `return` statement of a literal value. This lets syncback turn
+//! ModuleScripts that were generated from JSON, TOML, or YAML files back into
+//! data.
+
+use std::{iter::Peekable, str::Chars};
+
+use thiserror::Error;
+
+use super::Expression;
+
+#[derive(Debug, Error)]
+#[error("{message} (line {line})")]
+pub struct ParseError {
+    message: String,
+    line: usize,
+}
+
+/// Parses Lua source that consists of only `return` followed by a literal:
+/// `nil`, a boolean, a number, a string, or a table of literals. Type
+/// declarations before the `return` and type assertions on the literal are
+/// skipped over.
+///
+/// Tables that only contain positional values are returned as arrays.
+/// Positional values in a table that also has keys are given their index as a
+/// key, like Lua does.
+pub(crate) fn parse_return(source: &str) -> Result<Expression, ParseError> {
+    let mut parser = Parser::new(source);
+
+    // Type declarations are generated before the return statement when
+    // `emitLuauTypes` is enabled. They don't matter for the value.
+    loop {
+        parser.skip_trivia()?;
+        let mut lookahead = parser.lookahead();
+        match lookahead.parse_word().as_deref() {
+            Some("export" | "type") => parser.skip_type(true)?,
+            _ => break,
+        }
+    }
+
+    parser.expect_word("return")?;
+    let expression = parser.parse_expression()?;
+
+    parser.skip_trivia()?;
+    if parser.eat_str("::") {
+        parser.skip_type(false)?;
+    }
+    parser.eat(';');
+    parser.skip_trivia()?;
+
+    match parser.peek() {
+        None => Ok(expression),
+        Some(c) => Err(parser.error(format!(
+            "expected the end of the source after the returned value, found '{c}'"
+        ))),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            message: message.into(),
+            line: self.line,
+        }
+    }
+
+    /// Returns a copy of this parser that can be advanced without affecting
+    /// this one.
+    fn lookahead(&self) -> Self {
+        Self {
+            chars: self.chars.clone(),
+            line: self.line,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_str(&mut self, expected: &str) -> bool {
+        let mut lookahead = self.lookahead();
+        if expected.chars().all(|c| lookahead.next() == Some(c)) {
+            *self = lookahead;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_trivia()?;
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{expected}', found '{c}'"))),
+            None => Err(self.error(format!(
+                "expected '{expected}', found the end of the source"
+            ))),
+        }
+    }
+
+    fn expect_word(&mut self, expected: &str) -> Result<(), ParseError> {
+        match self.parse_word() {
+            Some(word) if word == expected => Ok(()),
+            Some(word) => Err(self.error(format!("expected '{expected}', found '{word}'"))),
+            None => Err(self.error(format!("expected '{expected}'"))),
+        }
+    }
+
+    /// Skips over whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), ParseError> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next();
+                }
+                Some('-') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.next() != Some('-') {
+                        return Ok(());
+                    }
+                    self.next();
+                    self.next();
+
+                    if self.peek() == Some('[') {
+                        if let Some(level) = self.long_bracket_level() {
+                            self.parse_long_string(level)?;
+                            continue;
+                        }
+                    }
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.next();
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Skips over a type declaration or type, stopping at a `;` or the end of
+    /// the source. If `stop_at_return` is set, it also stops before a `return`
+    /// that isn't nested inside of brackets.
+    fn skip_type(&mut self, stop_at_return: bool) -> Result<(), ParseError> {
+        let mut depth = 0usize;
+
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                None => return Ok(()),
+                Some(';') if depth == 0 => return Ok(()),
+                Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut lookahead = self.lookahead();
+                    let word = lookahead.parse_word();
+                    if stop_at_return && depth == 0 && word.as_deref() == Some("return") {
+                        return Ok(());
+                    }
+                    *self = lookahead;
+                }
+                Some(quote @ ('"' | '\'')) => {
+                    self.next();
+                    self.parse_quoted_string(quote)?;
+                }
+                Some('{' | '(' | '[') => {
+                    self.next();
+                    depth += 1;
+                }
+                Some('}' | ')' | ']') => {
+                    self.next();
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| self.error("unbalanced brackets in type"))?;
+                }
+                Some(_) => {
+                    self.next();
+                }
+            }
+        }
+    }
+
+    fn parse_word(&mut self) -> Option<String> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        if word.is_empty() {
+            None
+        } else {
+            Some(word)
+        }
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, ParseError> {
+        self.skip_trivia()?;
+
+        match self.peek() {
+            Some('{') => self.parse_table(),
+            Some(quote @ ('"' | '\'')) => {
+                self.next();
+                self.parse_quoted_string(quote).map(Expression::String)
+            }
+            Some('[') => match self.long_bracket_level() {
+                Some(level) => self.parse_long_string(level).map(Expression::String),
+                None => Err(self.error("expected a value, found '['")),
+            },
+            Some('-') => {
+                self.next();
+                match self.parse_expression()? {
+                    Expression::Number(value) => Ok(Expression::Number(-value)),
+                    _ => Err(self.error("only numbers can be negated")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.parse_number_expression(),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let word = self.parse_word().unwrap();
+                match word.as_str() {
+                    "nil" => Ok(Expression::Nil),
+                    "true" => Ok(Expression::Bool(true)),
+                    "false" => Ok(Expression::Bool(false)),
+                    "math" => {
+                        self.expect('.')?;
+                        self.expect_word("huge")?;
+                        Ok(Expression::Number(f64::INFINITY))
+                    }
+                    _ => Err(self.error(format!("expected a value, found '{word}'"))),
+                }
+            }
+            Some(c) => Err(self.error(format!("expected a value, found '{c}'"))),
+            None => Err(self.error("expected a value, found the end of the source")),
+        }
+    }
+
+    /// Parses a number, including `0/0`, which is how NaN is written.
+    fn parse_number_expression(&mut self) -> Result<Expression, ParseError> {
+        let value = self.parse_number()?;
+
+        self.skip_trivia()?;
+        if self.eat('/') {
+            self.skip_trivia()?;
+            let divisor = self.parse_number()?;
+            Ok(Expression::Number(value / divisor))
+        } else {
+            Ok(Expression::Number(value))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<f64, ParseError> {
+        let mut literal = String::new();
+        while let Some(c) = self.peek() {
+            let is_exponent_sign = matches!(c, '+' | '-')
+                && matches!(literal.chars().last(), Some('e' | 'E'))
+                && !literal.starts_with("0x")
+                && !literal.starts_with("0X");
+
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || is_exponent_sign {
+                if c != '_' {
+                    literal.push(c);
+                }
+                self.next();
+            } else {
+                break;
+            }
+        }
+
+        let lowercase = literal.to_ascii_lowercase();
+        let parsed = if let Some(hex) = lowercase.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16).ok().map(|value| value as f64)
+        } else if let Some(binary) = lowercase.strip_prefix("0b") {
+            u64::from_str_radix(binary, 2)
+                .ok()
+                .map(|value| value as f64)
+        } else {
+            literal.parse().ok()
+        };
+
+        parsed.ok_or_else(|| self.error(format!("'{literal}' is not a valid number")))
+    }
+
+    /// Returns the level of the long bracket (like `[==[`) that starts at the
+    /// current position without consuming it, if there is one.
+    fn long_bracket_level(&mut self) -> Option<usize> {
+        let mut lookahead = self.chars.clone();
+        if lookahead.next() != Some('[') {
+            return None;
+        }
+
+        let mut level = 0;
+        loop {
+            match lookahead.next() {
+                Some('=') => level += 1,
+                Some('[') => return Some(level),
+                _ => return None,
+            }
+        }
+    }
+
+    fn parse_long_string(&mut self, level: usize) -> Result<String, ParseError> {
+        // The opening bracket has already been validated, so it can be skipped.
+        for _ in 0..level + 2 {
+            self.next();
+        }
+
+        // A newline immediately following the opening bracket is ignored.
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let closing = format!("]{}]", "=".repeat(level));
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                Some(c) => {
+                    value.push(c);
+                    if value.ends_with(&closing) {
+                        value.truncate(value.len() - closing.len());
+                        return Ok(value);
+                    }
+                }
+                None => return Err(self.error("unfinished long string")),
+            }
+        }
+    }
+
+    fn parse_quoted_string(&mut self, quote: char) -> Result<String, ParseError> {
+        // Escapes like `\xE2` are single bytes rather than characters, so the
+        // string is only decoded once all of it has been read.
+        let mut value = Vec::new();
+
+        loop {
+            match self.next() {
+                Some(c) if c == quote => {
+                    return String::from_utf8(value)
+                        .map_err(|_| self.error("string is not valid UTF-8"));
+                }
+                Some('\n') | None => return Err(self.error("unfinished string")),
+                Some('\\') => self.parse_escape(&mut value)?,
+                Some(c) => push_char(&mut value, c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self, value: &mut Vec<u8>) -> Result<(), ParseError> {
+        let escaped = match self.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('a') => '\u{7}',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('v') => '\u{b}',
+            Some('\n') => '\n',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            Some('z') => {
+                while self.peek().is_some_and(char::is_whitespace) {
+                    self.next();
+                }
+                return Ok(());
+            }
+            Some('x') => {
+                let digits: String = (0..2).filter_map(|_| self.next()).collect();
+                let byte = u8::from_str_radix(&digits, 16)
+                    .map_err(|_| self.error(format!("invalid escape sequence '\\x{digits}'")))?;
+                value.push(byte);
+                return Ok(());
+            }
+            Some('u') => {
+                self.expect('{')?;
+                let mut digits = String::new();
+                while let Some(c) = self.next() {
+                    if c == '}' {
+                        break;
+                    }
+                    digits.push(c);
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        self.error(format!("invalid escape sequence '\\u{{{digits}}}'"))
+                    })?
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while digits.len() < 3 && self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    digits.push(self.next().unwrap());
+                }
+                let byte = digits
+                    .parse::<u8>()
+                    .map_err(|_| self.error(format!("invalid escape sequence '\\{digits}'")))?;
+                value.push(byte);
+                return Ok(());
+            }
+            Some(c) => return Err(self.error(format!("invalid escape sequence '\\{c}'"))),
+            None => return Err(self.error("unfinished string")),
+        };
+
+        push_char(value, escaped);
+        Ok(())
+    }
+
+    fn parse_table(&mut self) -> Result<Expression, ParseError> {
+        self.expect('{')?;
+
+        let mut positional = Vec::new();
+        let mut keyed = Vec::new();
+
+        loop {
+            self.skip_trivia()?;
+            if self.eat('}') {
+                break;
+            }
+
+            match self.peek() {
+                Some('[') if self.long_bracket_level().is_none() => {
+                    self.next();
+                    let key = self.parse_expression()?;
+                    self.expect(']')?;
+                    self.expect('=')?;
+                    if matches!(key, Expression::Nil) {
+                        return Err(self.error("nil cannot be a table key"));
+                    }
+                    keyed.push((key, self.parse_expression()?));
+                }
+                Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                    // This may be either a key like `foo = 1` or a value like
+                    // `true`, so we have to look ahead past the word.
+                    let mut lookahead = self.lookahead();
+                    let word = lookahead.parse_word().unwrap();
+                    lookahead.skip_trivia()?;
+                    if lookahead.peek() == Some('=') {
+                        *self = lookahead;
+                        self.next();
+                        keyed.push((Expression::String(word), self.parse_expression()?));
+                    } else {
+                        positional.push(self.parse_expression()?);
+                    }
+                }
+                _ => positional.push(self.parse_expression()?),
+            }
+
+            self.skip_trivia()?;
+            if !(self.eat(',') || self.eat(';')) {
+                self.expect('}')?;
+                break;
+            }
+        }
+
+        if keyed.is_empty() {
+            return Ok(Expression::Array(positional));
+        }
+
+        let mut entries: Vec<(Expression, Expression)> = positional
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (Expression::Number((index + 1) as f64), value))
+            .collect();
+        entries.extend(keyed);
+
+        Ok(Expression::table(entries))
+    }
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parsing the output of `lua_ast` should give back the same output when
+    /// it's displayed again.
+    fn roundtrip(expression: Expression) {
+        let source = super::super::Statement::Return(expression).to_string();
+        let parsed = parse_return(&source).unwrap();
+        assert_eq!(super::super::Statement::Return(parsed).to_string(), source);
+    }
+
+    #[test]
+    fn roundtrip_generated() {
+        roundtrip(Expression::table(vec![
+            ("string".into(), "hello \"world\"\n\t\\".into()),
+            ("number".into(), Expression::Number(-12.5)),
+            ("huge".into(), Expression::Number(f64::INFINITY)),
+            ("tiny".into(), Expression::Number(f64::NEG_INFINITY)),
+            ("bool".into(), Expression::Bool(false)),
+            ("nothing".into(), Expression::Nil),
+            ("not an ident".into(), "value".into()),
+            (
+                "array".into(),
+                Expression::Array(vec![Expression::Number(1.0), "two".into()]),
+            ),
+            (
+                "nested".into(),
+                Expression::table(vec![("key".into(), "value".into())]),
+            ),
+        ]));
+    }
+
+    #[test]
+    fn handwritten() {
+        let parsed = parse_return(
+            r#"
+            -- Some settings
+            --[==[ with a long
+            comment ]==]
+            return {
+                speed = 0x10;
+                ["name"] = 'Bob\65',
+                tags = { "a", [[b]], },
+                [1] = nan_is_fine and nil,
+            }
+            "#,
+        );
+        let error = parsed.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected a value, found 'nan_is_fine' (line 9)"
+        );
+
+        let parsed = parse_return(
+            r#"return {
+                speed = 0x10;
+                ["name"] = 'Bob\65',
+                tags = { "a", [[b]], },
+                "positional",
+            };"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed,
+            Expression::table(vec![
+                (Expression::Number(1.0), "positional".into()),
+                ("speed".into(), Expression::Number(16.0)),
+                ("name".into(), "BobA".into()),
+                (
+                    "tags".into(),
+                    Expression::Array(vec!["a".into(), "b".into()])
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn typed() {
+        let parsed = parse_return(
+            r#"export type Config = {
+                ["return"]: { number },
+                nested: { value: string? },
+            }
+
+            return {
+                ["return"] = {1},
+            } :: Config"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed,
+            Expression::table(vec![(
+                "return".into(),
+                Expression::Array(vec![Expression::Number(1.0)])
+            )])
+        );
+    }
+
+    #[test]
+    fn nan() {
+        let parsed = parse_return("return 0/0").unwrap();
+        assert!(matches!(parsed, Expression::Number(value) if value.is_nan()));
+    }
+
+    #[test]
+    fn byte_escapes() {
+        assert_eq!(
+            parse_return(r#"return "\xE2\x82\xAC \226\130\172 \u{20AC} €""#).unwrap(),
+            "€ € € €".into()
+        );
+        assert!(parse_return(r#"return "\xE2""#).is_err());
+    }
+
+    #[test]
+    fn not_a_literal() {
+        assert!(parse_return("local x = 5 return x").is_err());
+        assert!(parse_return("return { foo = bar }").is_err());
+        assert!(parse_return("return 5 + 5").is_err());
+        assert!(parse_return("return {} print('hi')").is_err());
+    }
+}
@@ -44,3 +44,16 @@ where
 
     seq.end()
 }
+
+/// A serializer for serde that serialize an optional value with all directory
+/// separators converted into `/`.
+pub fn serialize_option_absolute<S, T>(path: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: AsRef<Path>,
+{
+    match path {
+        Some(path) => serialize_absolute(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,386 @@
+//! Preprocessing for Lua sources, which lets code be left out of a build
+//! depending on names defined by projects, profiles, and the command line.
+//!
+//! Directives are comments on their own line:
+//!
+//! - `--#if CONDITION`, `--#else`, and `--#end`, which can be nested.
+//!   `CONDITION` is one of `NAME`, `!NAME`, `NAME == VALUE`, or
+//!   `NAME != VALUE`, where values can be quoted with `"`.
+//! - `--#define NAME` and `--#define NAME VALUE`, which define a name for the
+//!   rest of the file.
+//!
+//! Lines that are left out are replaced with empty lines, as are the
+//! directives themselves, so that line numbers still match the file.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The value of a name defined for preprocessing. Names defined as `false`
+/// are left undefined, which can be used to undefine a name that's defined
+/// elsewhere.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum DefineValue {
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl DefineValue {
+    /// Parses a value given on the command line. `true` and `false` are
+    /// booleans, and anything else is a string.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "true" => DefineValue::Bool(true),
+            "false" => DefineValue::Bool(false),
+            _ => DefineValue::String(value.to_owned()),
+        }
+    }
+
+    /// Returns the value that conditions compare against, or `None` if this
+    /// value leaves its name undefined.
+    fn as_define(&self) -> Option<String> {
+        match self {
+            DefineValue::Bool(true) => Some("true".to_owned()),
+            DefineValue::Bool(false) => None,
+            DefineValue::Number(number) => Some(number.to_string()),
+            DefineValue::String(string) => Some(string.clone()),
+        }
+    }
+}
+
+/// Adds the given names to `defines`, replacing any values they already have.
+pub fn apply_defines<'a, I>(defines: &mut BTreeMap<String, String>, new_defines: I)
+where
+    I: IntoIterator<Item = (&'a String, &'a DefineValue)>,
+{
+    for (name, value) in new_defines {
+        match value.as_define() {
+            Some(value) => defines.insert(name.clone(), value),
+            None => defines.remove(name),
+        };
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("line {line}: {message}")]
+pub struct PreprocessError {
+    line: usize,
+    message: String,
+}
+
+impl PreprocessError {
+    fn new(line: usize, message: impl fmt::Display) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// An `--#if` that hasn't been closed yet.
+struct Block {
+    /// The line the `--#if` is on.
+    line: usize,
+    /// Whether the code around the block is included.
+    parent_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+impl Block {
+    fn is_active(&self) -> bool {
+        self.parent_active && self.condition != self.in_else
+    }
+}
+
+/// Returns whether running the directives in `source` would change it, in
+/// which case writing a new source over it would lose those directives.
+pub fn is_preprocessed(source: &str, defines: &BTreeMap<String, String>) -> bool {
+    !matches!(preprocess(source, defines), Ok(output) if output == source)
+}
+
+/// Runs the directives in `source` using the given defines. Sources without
+/// any directives are returned unchanged.
+pub fn preprocess<'a>(
+    source: &'a str,
+    defines: &BTreeMap<String, String>,
+) -> Result<Cow<'a, str>, PreprocessError> {
+    if !source.contains("--#") {
+        return Ok(Cow::Borrowed(source));
+    }
+
+    let mut defines = Cow::Borrowed(defines);
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut output = String::with_capacity(source.len());
+
+    for (index, line) in source.split('\n').enumerate() {
+        let line_number = index + 1;
+        if index > 0 {
+            output.push('\n');
+        }
+
+        let active = blocks.last().is_none_or(Block::is_active);
+        let Some((directive, rest)) = parse_directive(line) else {
+            if active {
+                output.push_str(line);
+            }
+            continue;
+        };
+
+        match directive {
+            "if" => {
+                let condition = evaluate(rest, &defines)
+                    .map_err(|message| PreprocessError::new(line_number, message))?;
+                blocks.push(Block {
+                    line: line_number,
+                    parent_active: active,
+                    condition,
+                    in_else: false,
+                });
+            }
+            "else" => {
+                let block = blocks.last_mut().ok_or_else(|| {
+                    PreprocessError::new(line_number, "`--#else` has no matching `--#if`")
+                })?;
+                if block.in_else {
+                    return Err(PreprocessError::new(
+                        line_number,
+                        format!(
+                            "the `--#if` on line {} already has an `--#else`",
+                            block.line
+                        ),
+                    ));
+                }
+                block.in_else = true;
+            }
+            "end" => {
+                blocks.pop().ok_or_else(|| {
+                    PreprocessError::new(line_number, "`--#end` has no matching `--#if`")
+                })?;
+            }
+            "define" => {
+                let (name, value) = match rest.split_once(char::is_whitespace) {
+                    Some((name, value)) => (name, DefineValue::parse(&unquote(value.trim()))),
+                    None => (rest, DefineValue::Bool(true)),
+                };
+                if !is_valid_name(name) {
+                    return Err(PreprocessError::new(
+                        line_number,
+                        format!("`{name}` is not a valid name to define"),
+                    ));
+                }
+                if active {
+                    apply_defines(defines.to_mut(), [(&name.to_owned(), &value)]);
+                }
+            }
+            _ => unreachable!("parse_directive only returns known directives"),
+        }
+    }
+
+    if let Some(block) = blocks.last() {
+        return Err(PreprocessError::new(
+            block.line,
+            "`--#if` is never closed with `--#end`",
+        ));
+    }
+
+    Ok(Cow::Owned(output))
+}
+
+/// Returns the name of the directive on the given line and the rest of the
+/// line after it, if the line is a directive. Other comments that start with
+/// `--#`, like `--#region`, aren't directives.
+fn parse_directive(line: &str) -> Option<(&'static str, &str)> {
+    let line = line.trim().strip_prefix("--#")?;
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let directive = ["if", "else", "end", "define"]
+        .into_iter()
+        .find(|directive| *directive == word)?;
+
+    Some((directive, rest.trim()))
+}
+
+fn evaluate(condition: &str, defines: &BTreeMap<String, String>) -> Result<bool, String> {
+    let invalid = || format!("`{condition}` is not a valid condition");
+
+    if let Some(name) = condition.strip_prefix('!') {
+        let name = name.trim();
+        return match is_valid_name(name) {
+            true => Ok(!defines.contains_key(name)),
+            false => Err(invalid()),
+        };
+    }
+
+    for (operator, equal) in [("==", true), ("!=", false)] {
+        if let Some((name, value)) = condition.split_once(operator) {
+            let name = name.trim();
+            if !is_valid_name(name) {
+                return Err(invalid());
+            }
+            let value = unquote(value.trim());
+            return Ok((defines.get(name) == Some(&value)) == equal);
+        }
+    }
+
+    match is_valid_name(condition) {
+        true => Ok(defines.contains_key(condition)),
+        false => Err(invalid()),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn defines(names: &[(&str, &str)]) -> BTreeMap<String, String> {
+        names
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn no_directives() {
+        let source = "local x = 1\n-- a comment\nreturn x\n";
+        assert!(matches!(
+            preprocess(source, &defines(&[])).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn if_else_keeps_line_numbers() {
+        let source = "\
+local x = 1
+--#if DEBUG
+print(\"debug\")
+--#else
+print(\"release\")
+--#end
+return x";
+
+        assert_eq!(
+            preprocess(source, &defines(&[("DEBUG", "true")])).unwrap(),
+            "local x = 1\n\nprint(\"debug\")\n\n\n\nreturn x"
+        );
+        assert_eq!(
+            preprocess(source, &defines(&[])).unwrap(),
+            "local x = 1\n\n\n\nprint(\"release\")\n\nreturn x"
+        );
+    }
+
+    #[test]
+    fn nested_and_comparisons() {
+        let source = "\
+--#if PLATFORM == \"console\"
+a
+  --#if !ANALYTICS
+  b
+  --#end
+--#else
+c
+--#end
+--#if PLATFORM != console
+d
+--#end";
+
+        assert_eq!(
+            preprocess(source, &defines(&[("PLATFORM", "console")])).unwrap(),
+            "\na\n\n  b\n\n\n\n\n\n\n"
+        );
+        assert_eq!(
+            preprocess(
+                source,
+                &defines(&[("PLATFORM", "pc"), ("ANALYTICS", "true")])
+            )
+            .unwrap(),
+            "\n\n\n\n\n\nc\n\n\nd\n"
+        );
+    }
+
+    #[test]
+    fn define_in_file() {
+        let source = "\
+--#if DEBUG
+--#define VERBOSE
+--#end
+--#define LEVEL 3
+--#if VERBOSE
+verbose
+--#end
+--#if LEVEL == 3
+three
+--#end";
+
+        assert_eq!(
+            preprocess(source, &defines(&[("DEBUG", "true")])).unwrap(),
+            "\n\n\n\n\nverbose\n\n\nthree\n"
+        );
+        assert_eq!(
+            preprocess(source, &defines(&[])).unwrap(),
+            "\n\n\n\n\n\n\n\nthree\n"
+        );
+    }
+
+    #[test]
+    fn other_comments_are_kept() {
+        let source = "--#region Setup\nlocal x = 1\n--#endregion";
+
+        assert_eq!(preprocess(source, &defines(&[])).unwrap(), source);
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("--#if DEBUG\nprint()", "line 1: `--#if` is never closed"),
+            (
+                "print()\n--#end",
+                "line 2: `--#end` has no matching `--#if`",
+            ),
+            ("--#else", "line 1: `--#else` has no matching `--#if`"),
+            (
+                "--#if A\n--#else\n--#else\n--#end",
+                "line 3: the `--#if` on line 1 already has an `--#else`",
+            ),
+            ("--#if A B", "line 1: `A B` is not a valid condition"),
+            ("--#if", "line 1: `` is not a valid condition"),
+        ];
+
+        for (source, expected) in cases {
+            let err = preprocess(source, &defines(&[])).unwrap_err().to_string();
+            assert!(err.starts_with(expected), "{source:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn define_values() {
+        let mut defines = defines(&[("DEBUG", "true"), ("LEVEL", "1")]);
+        let new_defines = BTreeMap::from([
+            ("DEBUG".to_owned(), DefineValue::Bool(false)),
+            ("LEVEL".to_owned(), DefineValue::Number(2.0)),
+            ("NAME".to_owned(), DefineValue::parse("beta")),
+        ]);
+        apply_defines(&mut defines, &new_defines);
+
+        assert_eq!(defines, self::defines(&[("LEVEL", "2"), ("NAME", "beta")]));
+    }
+}
@@ -4,20 +4,36 @@ use std::{
     fs, io,
     net::IpAddr,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 use memofs::Vfs;
 use rbx_dom_weak::Ustr;
-use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
 use crate::{
-    glob::IgnorableGlob, json, resolution::UnresolvedValue, snapshot::SyncRule,
+    build_report::Budget,
+    file_format::{self, FileFormat},
+    glob::IgnorableGlob,
+    json,
+    preprocess::DefineValue,
+    resolution::UnresolvedValue,
+    snapshot::{InitFileName, PathIgnoreRule, SyncRule},
+    snapshot_middleware::Middleware,
+    strip::StripSources,
     syncback::SyncbackRules,
 };
 
 /// Represents 'default' project names that act as `init` files
-pub static DEFAULT_PROJECT_NAMES: [&str; 2] = ["default.project.json", "default.project.jsonc"];
+pub static DEFAULT_PROJECT_NAMES: [&str; 4] = [
+    "default.project.json",
+    "default.project.jsonc",
+    "default.project.yaml",
+    "default.project.toml",
+];
 
 /// Error type returned by any function that handles projects.
 #[derive(Debug, Error)]
@@ -52,27 +68,115 @@ enum Error {
         source: serde_json::Error,
         path: PathBuf,
     },
+
+    #[error("Could not load the project {} extended by {}", .base.display(), .path.display())]
+    Extends {
+        source: Box<Error>,
+        path: PathBuf,
+        base: PathBuf,
+    },
+
+    #[error("The project {} extends itself through {}", .path.display(), .base.display())]
+    ExtendsCycle { path: PathBuf, base: PathBuf },
+
+    #[error("The project {} has no profile named '{name}'{}", .path.display(), available_profiles(.available))]
+    UnknownProfile {
+        name: String,
+        path: PathBuf,
+        available: Vec<String>,
+    },
+
+    #[error("The variable '{name}' used by '{key}' in project {} is not set in the environment or in the project's `variables`", .path.display())]
+    UnresolvedVariable {
+        name: String,
+        key: String,
+        path: PathBuf,
+    },
+
+    #[error("The value of '{key}' in project {} has an unterminated or empty variable", .path.display())]
+    InvalidVariable { key: String, path: PathBuf },
+
+    #[error(
+        "The init file name '{name}' in project {} uses {middleware:?} middleware, \
+        but only script, CSV, and project middleware can be used for init files",
+        .path.display()
+    )]
+    InvalidInitFileName {
+        name: String,
+        middleware: Middleware,
+        path: PathBuf,
+    },
+
+    #[error("The sync rule '{pattern}' in project {} {reason}", .path.display())]
+    InvalidSyncRule {
+        pattern: String,
+        reason: &'static str,
+        path: PathBuf,
+    },
+
+    #[error("The syncbackRules.{setting} entry '{key}' in project {} {reason}", .path.display())]
+    InvalidSyncbackMiddleware {
+        setting: &'static str,
+        key: String,
+        reason: String,
+        path: PathBuf,
+    },
+
+    #[error("The profile '{profile}' in project {} prunes '{node}', which is not in the project's tree", .path.display())]
+    UnknownPrunedNode {
+        profile: String,
+        node: String,
+        path: PathBuf,
+    },
+}
+
+fn available_profiles(available: &[String]) -> String {
+    if available.is_empty() {
+        String::new()
+    } else {
+        format!(". Available profiles: {}", available.join(", "))
+    }
 }
 
 /// Contains all of the configuration for a Rojo-managed project.
 ///
-/// Project files are stored in `.project.json` files.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Project files are stored in `.project.json` files, or in `.project.yaml` or
+/// `.project.toml` files, which are deserialized the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Project {
     #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
     schema: Option<String>,
 
+    /// If specified, the path to another project file that this project
+    /// extends, relative to this project's folder.
+    ///
+    /// The `tree`, `syncbackRules`, `syncRules`, and `globIgnorePaths` of the
+    /// other project are merged into this project's. Where both projects
+    /// define the same thing, this project's definition is used.
+    #[serde(rename = "$extends", skip_serializing_if = "Option::is_none")]
+    pub extends: Option<PathBuf>,
+
     /// The name of the top-level instance described by the project.
     pub name: Option<String>,
 
+    /// Values for variables used in this project's strings, like `${VERSION}`.
+    /// Environment variables with the same name take priority.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+
     /// The tree of instances described by this project. Projects always
     /// describe at least one instance.
     pub tree: ProjectNode,
 
     /// If specified, sets the default port that `rojo serve` should use when
     /// using this project for live sync.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u16>>")]
     pub serve_port: Option<u16>,
 
     /// If specified, contains the set of place IDs that this project is
@@ -80,7 +184,12 @@ pub struct Project {
     ///
     /// This setting is intended to help prevent syncing a Rojo project into the
     /// wrong Roblox place.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number_set",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<Vec<NumberOrString<u64>>>")]
     pub serve_place_ids: Option<HashSet<u64>>,
 
     /// If specified, contains a set of place IDs that this project is
@@ -88,17 +197,32 @@ pub struct Project {
     ///
     /// This setting is intended to help prevent syncing a Rojo project into the
     /// wrong Roblox place.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number_set",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<Vec<NumberOrString<u64>>>")]
     pub blocked_place_ids: Option<HashSet<u64>>,
 
     /// If specified, sets the current place's place ID when connecting to the
     /// Rojo server from Roblox Studio.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u64>>")]
     pub place_id: Option<u64>,
 
     /// If specified, sets the current place's game ID when connecting to the
     /// Rojo server from Roblox Studio.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u64>>")]
     pub game_id: Option<u64>,
 
     /// If specified, this address will be used in place of the default address
@@ -121,6 +245,30 @@ pub struct Project {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub emit_legacy_scripts: Option<bool>,
 
+    /// Determines if Rojo should include an exported Luau type, inferred from
+    /// the data, in the ModuleScripts it creates from JSON, TOML, and YAML
+    /// files. The returned value is asserted to be of that type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emit_luau_types: Option<bool>,
+
+    /// Names defined for preprocessing `--#if` directives in Lua sources,
+    /// along with their values. Sources are only preprocessed if this is set
+    /// or names are defined with the `--define` option. Nested projects add
+    /// their defines to the ones of the projects containing them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defines: Option<BTreeMap<String, DefineValue>>,
+
+    /// How the sources of scripts made from Lua files are stripped when the
+    /// project is built. Sources are left as they are by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_sources: Option<StripSources>,
+
+    /// Limits on the size of the project's tree, or parts of it, that fail
+    /// the build when they're exceeded. Budgets from the project this project
+    /// extends also apply.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<Budget>,
+
     /// A list of globs, relative to the folder the project file is in, that
     /// match files that should be excluded if Rojo encounters them.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -136,11 +284,37 @@ pub struct Project {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sync_rules: Vec<SyncRule>,
 
+    /// A list of file names that make the directory containing them take on
+    /// the identity of that file, like `init.luau` does. These are checked
+    /// before the default init file names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub init_file_names: Vec<InitFileName>,
+
+    /// Named variants of this project, which can be selected with the
+    /// `--profile` option of commands like `rojo build` and `rojo serve`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProjectProfile>,
+
     /// The path to the file that this project came from. Relative paths in the
     /// project should be considered relative to the parent of this field, also
     /// given by `Project::folder_location`.
     #[serde(skip)]
     pub file_location: PathBuf,
+
+    /// The name of the profile that has been applied to this project, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
+
+    /// The strings in this project's file that had variables substituted into
+    /// them, so that the variables can be put back when it's written again.
+    #[serde(skip)]
+    substitutions: Vec<Substitution>,
+
+    /// The project named by `extends`, after being merged with any project it
+    /// extends itself. The `$path`s in its tree are relative to this project's
+    /// folder rather than its own.
+    #[serde(skip)]
+    pub base: Option<Box<Project>>,
 }
 
 impl Project {
@@ -148,14 +322,18 @@ impl Project {
     pub fn is_project_file(path: &Path) -> bool {
         path.file_name()
             .and_then(|name| name.to_str())
-            .map(|name| name.ends_with(".project.json") || name.ends_with(".project.jsonc"))
+            .map(|name| {
+                file_format::EXTENSIONS
+                    .iter()
+                    .any(|extension| name.ends_with(&format!(".project.{extension}")))
+            })
             .unwrap_or(false)
     }
 
     /// Attempt to locate a project represented by the given path.
     ///
-    /// This will find a project if the path refers to a `.project.json` file,
-    /// or is a folder that contains a `default.project.json` file.
+    /// This will find a project if the path refers to a project file, or is a
+    /// folder that contains a default project file, like `default.project.json`.
     fn locate(path: &Path) -> Option<PathBuf> {
         let meta = fs::metadata(path).ok()?;
 
@@ -168,9 +346,8 @@ impl Project {
         } else {
             for filename in DEFAULT_PROJECT_NAMES {
                 let child_path = path.join(filename);
-                let child_meta = fs::metadata(&child_path).ok()?;
 
-                if child_meta.is_file() {
+                if fs::metadata(&child_path).is_ok_and(|meta| meta.is_file()) {
                     return Some(child_path);
                 }
             }
@@ -184,7 +361,8 @@ impl Project {
 
     /// Sets the name of a project. The order it handles is as follows:
     ///
-    /// - If the project is a `default.project.json`, uses the folder's name
+    /// - If the project is a `default.project.json`, or has a name that the
+    ///   project lists as an init file name for projects, uses the folder's name
     /// - If a fallback is specified, uses that blindly
     /// - Otherwise, loops through sync rules (including the default ones!) and
     ///   uses the name of the first one that matches and is a project file
@@ -197,9 +375,13 @@ impl Project {
                 path: self.file_location.clone(),
             })?;
 
-        // If you're editing this to be generic, make sure you also alter the
-        // snapshot middleware to support generic init paths.
-        for default_file_name in DEFAULT_PROJECT_NAMES {
+        let init_file_names = self
+            .init_file_names
+            .iter()
+            .filter(|init_file_name| init_file_name.middleware == Middleware::Project)
+            .map(|init_file_name| init_file_name.name.as_str());
+
+        for default_file_name in DEFAULT_PROJECT_NAMES.into_iter().chain(init_file_names) {
             if file_name == default_file_name {
                 let folder_name = self.folder_location().file_name().and_then(OsStr::to_str);
                 if let Some(folder_name) = folder_name {
@@ -236,14 +418,107 @@ impl Project {
         project_file_location: PathBuf,
         fallback_name: Option<&str>,
     ) -> Result<Self, Error> {
-        let mut project: Self = json::from_slice(contents).map_err(|e| Error::Json {
+        Self::load_from_slice_with_env(contents, project_file_location, fallback_name, &|name| {
+            std::env::var(name).ok()
+        })
+    }
+
+    /// Loads a Project file like `load_from_slice`, looking up the variables
+    /// that aren't defined by the project with `env` instead of in the
+    /// environment.
+    fn load_from_slice_with_env(
+        contents: &[u8],
+        project_file_location: PathBuf,
+        fallback_name: Option<&str>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, Error> {
+        let json_err = |e: anyhow::Error| Error::Json {
             source: serde_json::Error::io(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
-                e.to_string(),
+                format!("{e:#}"),
             )),
             path: project_file_location.clone(),
-        })?;
+        };
+
+        let mut value: Value = match FileFormat::from_path(&project_file_location) {
+            FileFormat::Json => json::from_slice(contents),
+            format => format.parse_value(contents, || "Invalid project file".to_owned()),
+        }
+        .map_err(json_err)?;
+        let variables: BTreeMap<String, String> = match value.get("variables") {
+            Some(variables) => {
+                serde_json::from_value(variables.clone()).map_err(|e| json_err(e.into()))?
+            }
+            None => BTreeMap::new(),
+        };
+        let lookup = |name: &str| env(name).or_else(|| variables.get(name).cloned());
+
+        let mut substitutions = Vec::new();
+        if let Value::Object(fields) = &mut value {
+            for (key, field) in fields.iter_mut() {
+                if key != "variables" {
+                    let mut pointer = format!("/{}", escape_pointer(key));
+                    substitute_variables(field, &lookup, &mut pointer, &mut substitutions)
+                        .map_err(|err| err.into_error(&project_file_location))?;
+                }
+            }
+        }
+
+        let mut project: Self = serde_json::from_value(value).map_err(|e| json_err(e.into()))?;
+        project.substitutions = substitutions;
         project.file_location = project_file_location;
+        for init_file_name in &project.init_file_names {
+            if init_file_name.dir_middleware().is_none() {
+                return Err(Error::InvalidInitFileName {
+                    name: init_file_name.name.clone(),
+                    middleware: init_file_name.middleware,
+                    path: project.file_location,
+                });
+            }
+        }
+        for rule in &project.sync_rules {
+            let reason = if rule.is_dir_rule() {
+                match rule.middleware {
+                    Middleware::Dir => None,
+                    Middleware::Ignore if rule.class_name.is_none() => None,
+                    Middleware::Ignore => {
+                        Some("sets className, which can only be used with dir middleware")
+                    }
+                    _ => Some("matches directories, so it can only use dir or ignore middleware"),
+                }
+            } else if rule.middleware == Middleware::Dir {
+                Some("uses dir middleware, so its pattern must end with a '/'")
+            } else if rule.class_name.is_some() {
+                Some("sets className, which only rules for directories can use")
+            } else if rule.middleware == Middleware::External {
+                rule.command
+                    .is_none()
+                    .then_some("uses external middleware, so it must set a command")
+            } else if rule.command.is_some() || rule.syncback_command.is_some() {
+                Some("sets a command, which only rules using external middleware can use")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                return Err(Error::InvalidSyncRule {
+                    pattern: rule.include.as_str().to_owned(),
+                    reason,
+                    path: project.file_location,
+                });
+            }
+        }
+        if let Some((setting, key, reason)) = project
+            .syncback_rules
+            .as_ref()
+            .and_then(SyncbackRules::invalid_middleware)
+        {
+            return Err(Error::InvalidSyncbackMiddleware {
+                setting,
+                key,
+                reason,
+                path: project.file_location,
+            });
+        }
         project.check_compatibility();
         if project.name.is_none() {
             project.set_file_name(fallback_name)?;
@@ -267,7 +542,8 @@ impl Project {
                 _ => e.into(),
             })?;
 
-            Ok(Some(Self::load_from_slice(&contents, project_path, None)?))
+            let project = Self::load_from_slice(&contents, project_path, None)?;
+            Ok(Some(project.load_base(vfs, &mut Vec::new())?))
         } else {
             Ok(None)
         }
@@ -291,11 +567,295 @@ impl Project {
             _ => e.into(),
         })?;
 
-        Ok(Self::load_from_slice(
-            &contents,
-            project_path,
-            fallback_name,
-        )?)
+        let project = Self::load_from_slice(&contents, project_path, fallback_name)?;
+        Ok(project.load_base(vfs, &mut Vec::new())?)
+    }
+
+    /// Loads the project this project `extends`, if any, and merges it into
+    /// this one. `chain` holds the projects that are already being loaded
+    /// because they extend this one, and is used to detect cycles.
+    fn load_base(mut self, vfs: &Vfs, chain: &mut Vec<PathBuf>) -> Result<Self, Error> {
+        let Some(extends) = &self.extends else {
+            return Ok(self);
+        };
+
+        let path = vfs
+            .canonicalize(&self.file_location)
+            .unwrap_or_else(|_| self.file_location.clone());
+        let base_path = self.folder_location().join(extends);
+        let extends_err = |source: Error| Error::Extends {
+            source: Box::new(source),
+            path: self.file_location.clone(),
+            base: base_path.clone(),
+        };
+
+        let canonical_base_path = vfs
+            .canonicalize(&base_path)
+            .map_err(|e| extends_err(e.into()))?;
+        if canonical_base_path == path || chain.contains(&canonical_base_path) {
+            return Err(Error::ExtendsCycle {
+                path: self.file_location.clone(),
+                base: base_path,
+            });
+        }
+
+        let contents = vfs.read(&base_path).map_err(|e| extends_err(e.into()))?;
+        chain.push(path);
+        let base = Self::load_from_slice(&contents, base_path.clone(), self.name.as_deref())
+            .and_then(|base| base.load_base(vfs, chain))
+            .map_err(extends_err)?;
+        chain.pop();
+
+        self.merge_base(base);
+
+        Ok(self)
+    }
+
+    /// Merges `base` into this project, keeping this project's definitions
+    /// wherever both projects define something.
+    fn merge_base(&mut self, mut base: Project) {
+        let folder = self.folder_location().to_path_buf();
+        let base_folder = base.folder_location().to_path_buf();
+        if base_folder != folder {
+            base.tree.rebase_paths(&base_folder, &folder);
+            for rule in &mut base.sync_rules {
+                if rule.base_path.as_os_str().is_empty() {
+                    rule.base_path.clone_from(&base_folder);
+                }
+            }
+            if let Some(rules) = &mut base.syncback_rules {
+                rules.rebase_ignore_paths(&base_folder, &folder);
+            }
+        }
+
+        self.tree.merge_base(&base.tree);
+
+        self.syncback_rules = match (self.syncback_rules.take(), &base.syncback_rules) {
+            (Some(mut rules), Some(base_rules)) => {
+                rules.merge_base(base_rules);
+                Some(rules)
+            }
+            (rules, base_rules) => rules.or_else(|| base_rules.clone()),
+        };
+
+        // The first matching sync rule and init file name are used, so this
+        // project's have to come before the base project's.
+        self.sync_rules.extend(base.sync_rules.iter().cloned());
+        self.init_file_names
+            .extend(base.init_file_names.iter().cloned());
+        self.budgets.extend(base.budgets.iter().cloned());
+
+        if let Some(base_defines) = &base.defines {
+            let defines = self.defines.get_or_insert_with(BTreeMap::new);
+            for (name, value) in base_defines {
+                defines.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        self.base = Some(Box::new(base));
+    }
+
+    /// Returns this project as it's written in its own file, without anything
+    /// merged into it from the project it extends.
+    pub fn without_base(&self) -> Project {
+        let mut project = self.clone();
+        let Some(base) = project.base.take() else {
+            return project;
+        };
+
+        project.tree = self.tree.without_base(&base.tree);
+        project.syncback_rules = match (&self.syncback_rules, &base.syncback_rules) {
+            (Some(rules), Some(base_rules)) => rules.without_base(base_rules),
+            (rules, _) => rules.clone(),
+        };
+        project
+            .sync_rules
+            .retain(|rule| !base.sync_rules.contains(rule));
+        project
+            .init_file_names
+            .retain(|init_file_name| !base.init_file_names.contains(init_file_name));
+        project
+            .budgets
+            .retain(|budget| !base.budgets.contains(budget));
+        if let (Some(defines), Some(base_defines)) = (&mut project.defines, &base.defines) {
+            defines.retain(|name, value| base_defines.get(name) != Some(value));
+            if defines.is_empty() {
+                project.defines = None;
+            }
+        }
+
+        project
+    }
+
+    /// Serializes this project the way it should be written to its own file:
+    /// without anything merged into it from the project it extends, and with
+    /// variables in place of any values that were substituted for them. It's
+    /// written in the same format as the file it was loaded from.
+    pub fn to_file_contents(&self) -> anyhow::Result<Vec<u8>> {
+        let format = FileFormat::from_path(&self.file_location);
+        let project = self.without_base();
+        if project.substitutions.is_empty() {
+            return format.to_vec(&project);
+        }
+
+        let mut value = serde_json::to_value(&project)?;
+        for substitution in &project.substitutions {
+            let Some(field) = value.pointer_mut(&substitution.pointer) else {
+                continue;
+            };
+            let current = match field {
+                Value::String(string) => string.clone(),
+                Value::Number(number) => number.to_string(),
+                _ => continue,
+            };
+            // Values that have changed since the project was loaded, like a
+            // property updated by syncback, replace the variable instead.
+            if current == substitution.value {
+                *field = Value::String(substitution.original.clone());
+            }
+        }
+
+        format.to_vec(&value)
+    }
+
+    /// Returns the values substituted for variables in this project and the
+    /// projects it extends. Environment variables can change these without
+    /// any project file changing.
+    pub fn substituted_values(&self) -> Vec<&str> {
+        let mut values = Vec::new();
+        let mut project = Some(self);
+        while let Some(current) = project {
+            values.extend(
+                current
+                    .substitutions
+                    .iter()
+                    .map(|substitution| substitution.value.as_str()),
+            );
+            project = current.base.as_deref();
+        }
+
+        values
+    }
+
+    /// Returns the rules created from `globIgnorePaths`, including those of
+    /// the project this project extends. Each glob is relative to the folder
+    /// of the project that defined it.
+    pub fn path_ignore_rules(&self) -> Vec<PathIgnoreRule> {
+        let mut rules = match &self.base {
+            Some(base) => base.path_ignore_rules(),
+            None => Vec::new(),
+        };
+        rules.extend(self.glob_ignore_paths.iter().map(|glob| PathIgnoreRule {
+            glob: glob.clone(),
+            base_path: self.folder_location().to_path_buf(),
+        }));
+
+        rules
+    }
+
+    /// Returns the paths of every project file this project extends, directly
+    /// or not.
+    pub fn base_file_locations(&self) -> Vec<&Path> {
+        let mut paths = Vec::new();
+        let mut project = self;
+        while let Some(base) = &project.base {
+            paths.push(base.file_location.as_path());
+            project = base;
+        }
+
+        paths
+    }
+
+    /// Returns where the node found by following `node_names` down from the
+    /// root of the tree was defined, if it wasn't this project's own file: the
+    /// profile with the given name, or a project this project extends. The
+    /// same goes for the node's `properties`, which can be inherited from
+    /// those even when the node itself isn't.
+    ///
+    /// This project should be loaded without the profile applied to it.
+    pub fn node_defined_by(
+        &self,
+        profile: Option<&str>,
+        node_names: &[String],
+        properties: &[Ustr],
+    ) -> Option<String> {
+        let profile_tree =
+            profile.and_then(|name| Some((name, self.profiles.get(name)?.tree.as_ref()?)));
+        if let Some((name, profile_tree)) = profile_tree {
+            if let Some(profile_node) = profile_tree.descendant(node_names) {
+                let added = self.tree.descendant(node_names).is_none();
+                if added || properties.iter().any(|&key| profile_node.defines(key)) {
+                    return Some(format!("the '{name}' profile"));
+                }
+            }
+        }
+
+        let base = self.base.as_ref()?;
+        let base_node = base.tree.descendant(node_names)?;
+        let own_tree = self.tree.without_base(&base.tree);
+        let inherited = match own_tree.descendant(node_names) {
+            Some(own_node) => properties
+                .iter()
+                .any(|&key| base_node.defines(key) && !own_node.defines(key)),
+            None => true,
+        };
+
+        inherited.then(|| format!("the project it extends, {}", base.file_location.display()))
+    }
+
+    /// Applies the profile with the given name to this project, replacing the
+    /// settings and changing the nodes that the profile specifies.
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), ProjectError> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            return Err(Error::UnknownProfile {
+                name: name.to_owned(),
+                path: self.file_location.clone(),
+                available: self.profiles.keys().cloned().collect(),
+            }
+            .into());
+        };
+
+        macro_rules! replace {
+            ($($field:ident),*) => {
+                $(
+                    if profile.$field.is_some() {
+                        self.$field = profile.$field;
+                    }
+                )*
+            };
+        }
+        replace!(
+            serve_port,
+            serve_place_ids,
+            blocked_place_ids,
+            place_id,
+            game_id,
+            serve_address,
+            emit_legacy_scripts,
+            emit_luau_types,
+            defines,
+            strip_sources
+        );
+
+        for node in &profile.prune {
+            if !self.tree.prune(node) {
+                return Err(Error::UnknownPrunedNode {
+                    profile: name.to_owned(),
+                    node: node.clone(),
+                    path: self.file_location.clone(),
+                }
+                .into());
+            }
+        }
+
+        if let Some(mut tree) = profile.tree {
+            tree.merge_base(&self.tree);
+            self.tree = tree;
+        }
+
+        self.profile = Some(name.to_owned());
+
+        Ok(())
     }
 
     pub(crate) fn load_initial_project(vfs: &Vfs, path: &Path) -> Result<Self, ProjectError> {
@@ -327,7 +887,237 @@ impl Project {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+/// A string in a project file that had variables substituted into it. The
+/// pointer is a JSON pointer to the string in the file.
+#[derive(Debug, Clone, PartialEq)]
+struct Substitution {
+    pointer: String,
+    original: String,
+    value: String,
+}
+
+enum SubstitutionError {
+    Unresolved { name: String, pointer: String },
+    Invalid { pointer: String },
+}
+
+impl SubstitutionError {
+    fn into_error(self, path: &Path) -> Error {
+        // Turns a JSON pointer into a path that reads more like the keys in
+        // the project file, like `tree.Workspace.$properties.Name`.
+        let key = |pointer: String| {
+            pointer
+                .split('/')
+                .skip(1)
+                .map(|part| part.replace("~1", "/").replace("~0", "~"))
+                .collect::<Vec<_>>()
+                .join(".")
+        };
+
+        match self {
+            Self::Unresolved { name, pointer } => Error::UnresolvedVariable {
+                name,
+                key: key(pointer),
+                path: path.to_path_buf(),
+            },
+            Self::Invalid { pointer } => Error::InvalidVariable {
+                key: key(pointer),
+                path: path.to_path_buf(),
+            },
+        }
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Replaces every `${NAME}` in the strings in `value` with the value given by
+/// `lookup`. `$${` is left as a literal `${`.
+fn substitute_variables(
+    value: &mut Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    pointer: &mut String,
+    substitutions: &mut Vec<Substitution>,
+) -> Result<(), SubstitutionError> {
+    let mut recurse = |key: &str, value: &mut Value, pointer: &mut String| {
+        let len = pointer.len();
+        pointer.push('/');
+        pointer.push_str(&escape_pointer(key));
+        let result = substitute_variables(value, lookup, pointer, substitutions);
+        pointer.truncate(len);
+        result
+    };
+
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                recurse(key, field, pointer)?;
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                recurse(&index.to_string(), item, pointer)?;
+            }
+        }
+        Value::String(original) if original.contains("${") => {
+            let mut substituted = String::with_capacity(original.len());
+            let mut rest = original.as_str();
+            while let Some(start) = rest.find("${") {
+                if rest[..start].ends_with('$') {
+                    substituted.push_str(&rest[..start - 1]);
+                    substituted.push_str("${");
+                    rest = &rest[start + 2..];
+                    continue;
+                }
+                substituted.push_str(&rest[..start]);
+
+                let name = rest[start + 2..]
+                    .split_once('}')
+                    .map(|(name, _)| name)
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| SubstitutionError::Invalid {
+                        pointer: pointer.clone(),
+                    })?;
+                let variable = lookup(name).ok_or_else(|| SubstitutionError::Unresolved {
+                    name: name.to_owned(),
+                    pointer: pointer.clone(),
+                })?;
+                substituted.push_str(&variable);
+                rest = &rest[start + name.len() + 3..];
+            }
+            substituted.push_str(rest);
+
+            let original = std::mem::replace(original, substituted.clone());
+            substitutions.push(Substitution {
+                pointer: pointer.clone(),
+                original,
+                value: substituted,
+            });
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// A number in a project file, which can also be written as a string so that
+/// it can be given by a variable, like `"${PLACE_ID}"`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum NumberOrString<T> {
+    Number(T),
+    String(String),
+}
+
+impl<T: FromStr> NumberOrString<T> {
+    fn into_number<E: serde::de::Error>(self) -> Result<T, E> {
+        match self {
+            Self::Number(number) => Ok(number),
+            Self::String(string) => string
+                .trim()
+                .parse()
+                .map_err(|_| E::custom(format!("expected a number, found \"{string}\""))),
+        }
+    }
+}
+
+fn deserialize_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+{
+    Option::<NumberOrString<T>>::deserialize(deserializer)?
+        .map(NumberOrString::into_number)
+        .transpose()
+}
+
+fn deserialize_number_set<'de, D>(deserializer: D) -> Result<Option<HashSet<u64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Vec<NumberOrString<u64>>>::deserialize(deserializer)?
+        .map(|numbers| {
+            numbers
+                .into_iter()
+                .map(NumberOrString::into_number)
+                .collect()
+        })
+        .transpose()
+}
+
+/// A named variant of a project. Every setting given in a profile replaces the
+/// project's own when the profile is used.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct ProjectProfile {
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u16>>")]
+    pub serve_port: Option<u16>,
+
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number_set",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<Vec<NumberOrString<u64>>>")]
+    pub serve_place_ids: Option<HashSet<u64>>,
+
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number_set",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<Vec<NumberOrString<u64>>>")]
+    pub blocked_place_ids: Option<HashSet<u64>>,
+
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u64>>")]
+    pub place_id: Option<u64>,
+
+    #[serde(
+        default,
+        deserialize_with = "deserialize_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<NumberOrString<u64>>")]
+    pub game_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_address: Option<IpAddr>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emit_legacy_scripts: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emit_luau_types: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defines: Option<BTreeMap<String, DefineValue>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_sources: Option<StripSources>,
+
+    /// Nodes to remove from the project's tree, given as the names of each
+    /// node from the root separated by slashes, like `ServerStorage/Debug`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prune: Vec<String>,
+
+    /// A tree that's merged into the project's tree, used to add nodes to it or
+    /// to change the nodes it already has. Pruning happens before merging.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<ProjectNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
 pub struct OptionalPathNode {
     #[serde(serialize_with = "crate::path_serializer::serialize_absolute")]
     pub optional: PathBuf,
@@ -340,7 +1130,7 @@ impl OptionalPathNode {
 }
 
 /// Describes a path that is either optional or required
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum PathNode {
     Required(#[serde(serialize_with = "crate::path_serializer::serialize_absolute")] PathBuf),
@@ -366,7 +1156,7 @@ impl PathNode {
 }
 
 /// Describes an instance and its descendants in a project.
-#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ProjectNode {
     /// If set, defines the ClassName of the described instance.
     ///
@@ -375,6 +1165,7 @@ pub struct ProjectNode {
     /// `$className` CANNOT be set if `$path` is set and the instance described
     /// by that path has a ClassName other than Folder.
     #[serde(rename = "$className", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
     pub class_name: Option<Ustr>,
 
     /// If set, defines an ID for the described Instance that can be used
@@ -394,6 +1185,7 @@ pub struct ProjectNode {
         default,
         skip_serializing_if = "BTreeMap::is_empty"
     )]
+    #[schemars(schema_with = "crate::schema::properties_schema")]
     pub properties: BTreeMap<Ustr, UnresolvedValue>,
 
     #[serde(
@@ -401,6 +1193,7 @@ pub struct ProjectNode {
         default,
         skip_serializing_if = "BTreeMap::is_empty"
     )]
+    #[schemars(schema_with = "crate::schema::attributes_schema")]
     pub attributes: BTreeMap<String, UnresolvedValue>,
 
     /// Defines the behavior when Rojo encounters unknown instances in Roblox
@@ -432,6 +1225,145 @@ pub struct ProjectNode {
 }
 
 impl ProjectNode {
+    /// Merges `base` into this node and its children, keeping this node's
+    /// definitions wherever both define something.
+    fn merge_base(&mut self, base: &ProjectNode) {
+        if self.class_name.is_none() {
+            self.class_name = base.class_name;
+        }
+        if self.id.is_none() {
+            self.id.clone_from(&base.id);
+        }
+        if self.ignore_unknown_instances.is_none() {
+            self.ignore_unknown_instances = base.ignore_unknown_instances;
+        }
+        if self.path.is_none() {
+            self.path.clone_from(&base.path);
+        }
+
+        for (key, value) in &base.properties {
+            self.properties.entry(*key).or_insert_with(|| value.clone());
+        }
+        for (key, value) in &base.attributes {
+            self.attributes
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        for (name, base_child) in &base.children {
+            match self.children.get_mut(name) {
+                Some(child) => child.merge_base(base_child),
+                None => {
+                    self.children.insert(name.clone(), base_child.clone());
+                }
+            }
+        }
+    }
+
+    /// Returns the parts of this node and its children that differ from
+    /// `base`, which is the inverse of `merge_base`.
+    fn without_base(&self, base: &ProjectNode) -> ProjectNode {
+        fn differs<T: PartialEq + Clone>(value: &Option<T>, base: &Option<T>) -> Option<T> {
+            if value == base {
+                None
+            } else {
+                value.clone()
+            }
+        }
+
+        let children = self
+            .children
+            .iter()
+            .filter_map(|(name, child)| match base.children.get(name) {
+                Some(base_child) => {
+                    let child = child.without_base(base_child);
+                    (child != ProjectNode::default()).then(|| (name.clone(), child))
+                }
+                None => Some((name.clone(), child.clone())),
+            })
+            .collect();
+
+        ProjectNode {
+            class_name: differs(&self.class_name, &base.class_name),
+            id: differs(&self.id, &base.id),
+            children,
+            properties: self
+                .properties
+                .iter()
+                .filter(|(key, value)| base.properties.get(key) != Some(value))
+                .map(|(key, value)| (*key, value.clone()))
+                .collect(),
+            attributes: self
+                .attributes
+                .iter()
+                .filter(|(key, value)| base.attributes.get(*key) != Some(value))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            ignore_unknown_instances: differs(
+                &self.ignore_unknown_instances,
+                &base.ignore_unknown_instances,
+            ),
+            path: differs(&self.path, &base.path),
+        }
+    }
+
+    /// Returns the descendant of this node found by following `names` down
+    /// from it.
+    fn descendant(&self, names: &[String]) -> Option<&ProjectNode> {
+        names
+            .iter()
+            .try_fold(self, |node, name| node.children.get(name))
+    }
+
+    /// Returns whether this node sets the property `key`, where `Attributes`
+    /// stands for `$attributes`.
+    fn defines(&self, key: Ustr) -> bool {
+        if key == "Attributes" {
+            !self.attributes.is_empty()
+        } else {
+            self.properties.contains_key(&key)
+        }
+    }
+
+    /// Removes the descendant of this node at the given slash-separated path of
+    /// names. Returns whether there was a node to remove.
+    fn prune(&mut self, path: &str) -> bool {
+        let mut names = path.split('/').filter(|name| !name.is_empty());
+        let Some(mut name) = names.next() else {
+            return false;
+        };
+
+        let mut node = self;
+        for next in names {
+            match node.children.get_mut(name) {
+                Some(child) => node = child,
+                None => return false,
+            }
+            name = next;
+        }
+
+        node.children.remove(name).is_some()
+    }
+
+    /// Changes every relative `$path` in this node and its children from being
+    /// relative to `from` to being relative to `to`.
+    fn rebase_paths(&mut self, from: &Path, to: &Path) {
+        if let Some(path_node) = &mut self.path {
+            let path = match path_node {
+                PathNode::Required(path) => path,
+                PathNode::Optional(OptionalPathNode { optional }) => optional,
+            };
+            if path.is_relative() {
+                let absolute = from.join(&*path);
+                *path = pathdiff::diff_paths(&absolute, to).unwrap_or(absolute);
+            }
+        }
+
+        for child in self.children.values_mut() {
+            child.rebase_paths(from, to);
+        }
+    }
+
     fn validate_reserved_names(&self) {
         for (name, child) in &self.children {
             if name.starts_with('$') {
@@ -453,6 +1385,8 @@ impl ProjectNode {
 mod test {
     use super::*;
 
+    use rbx_dom_weak::ustr;
+
     #[test]
     fn path_node_required() {
         let path_node: PathNode = json::from_str(r#""src""#).unwrap();
@@ -689,4 +1623,565 @@ mod test {
         ));
         assert!(!crate::snapshot::is_path_ignored(&rules, "/test/plain.lua"));
     }
+
+    fn extends_vfs(files: Vec<(&'static str, &'static str)>) -> Vfs {
+        use memofs::{InMemoryFs, VfsSnapshot};
+
+        let mut imfs = InMemoryFs::new();
+        for (path, contents) in files {
+            imfs.load_snapshot(path, VfsSnapshot::file(contents))
+                .unwrap();
+        }
+
+        Vfs::new(imfs)
+    }
+
+    #[test]
+    fn extends_merges_base() {
+        let vfs = extends_vfs(vec![
+            (
+                "/root/shared/base.project.json",
+                r#"{
+                    "name": "base",
+                    "globIgnorePaths": ["**/*.spec.luau"],
+                    "syncbackRules": {
+                        "ignoreTrees": ["ServerStorage"],
+                        "ignorePaths": ["src/*.rbxm", "!src/keep.rbxm"]
+                    },
+                    "tree": {
+                        "$className": "DataModel",
+                        "ReplicatedStorage": {
+                            "$properties": { "Archivable": true },
+                            "Shared": { "$path": "src" }
+                        }
+                    }
+                }"#,
+            ),
+            (
+                "/root/default.project.json",
+                r#"{
+                    "name": "child",
+                    "$extends": "shared/base.project.json",
+                    "syncbackRules": { "ignoreTrees": ["Workspace"], "ignorePaths": ["build/**"] },
+                    "tree": {
+                        "ReplicatedStorage": {
+                            "$properties": { "Archivable": false }
+                        }
+                    }
+                }"#,
+            ),
+        ]);
+
+        let project =
+            Project::load_exact(&vfs, Path::new("/root/default.project.json"), None).unwrap();
+
+        assert_eq!(project.name.as_deref(), Some("child"));
+        assert_eq!(project.tree.class_name, Some("DataModel".into()));
+
+        let storage = &project.tree.children["ReplicatedStorage"];
+        assert_eq!(
+            serde_json::to_value(&storage.properties[&"Archivable".into()]).unwrap(),
+            serde_json::json!(false)
+        );
+        assert_eq!(
+            storage.children["Shared"].path,
+            Some(PathNode::Required(PathBuf::from("shared/src")))
+        );
+
+        assert_eq!(
+            serde_json::to_value(&project.syncback_rules).unwrap()["ignoreTrees"],
+            serde_json::json!(["Workspace", "ServerStorage"])
+        );
+        // The base's patterns are relative to its own folder.
+        assert_eq!(
+            serde_json::to_value(&project.syncback_rules).unwrap()["ignorePaths"],
+            serde_json::json!(["build/**", "shared/src/*.rbxm", "!shared/src/keep.rbxm"])
+        );
+        assert_eq!(
+            serde_json::to_value(&project.without_base().syncback_rules).unwrap()["ignorePaths"],
+            serde_json::json!(["build/**"])
+        );
+
+        let ignore_rules = project.path_ignore_rules();
+        assert_eq!(ignore_rules.len(), 1);
+        assert_eq!(ignore_rules[0].base_path, Path::new("/root/shared"));
+
+        assert_eq!(
+            project.base_file_locations(),
+            vec![Path::new("/root/shared/base.project.json")]
+        );
+    }
+
+    #[test]
+    fn extends_without_base_roundtrip() {
+        let child = r#"{
+            "name": "child",
+            "$extends": "base.project.json",
+            "tree": {
+                "Workspace": {
+                    "$properties": { "Gravity": 10.0 }
+                }
+            }
+        }"#;
+        let vfs = extends_vfs(vec![
+            (
+                "/root/base.project.json",
+                r#"{
+                    "name": "base",
+                    "tree": {
+                        "$className": "DataModel",
+                        "Workspace": {
+                            "$properties": { "Gravity": 196.2 },
+                            "Baseplate": { "$className": "Part" }
+                        }
+                    }
+                }"#,
+            ),
+            ("/root/default.project.json", child),
+        ]);
+
+        let project =
+            Project::load_exact(&vfs, Path::new("/root/default.project.json"), None).unwrap();
+        let own = project.without_base();
+
+        assert!(own.base.is_none());
+        assert_eq!(
+            own.tree,
+            Project::load_from_slice(child.as_bytes(), PathBuf::new(), None)
+                .unwrap()
+                .tree
+        );
+    }
+
+    #[test]
+    fn extends_cycle() {
+        let vfs = extends_vfs(vec![
+            (
+                "/root/a.project.json",
+                r#"{ "name": "a", "$extends": "b.project.json", "tree": {} }"#,
+            ),
+            (
+                "/root/b.project.json",
+                r#"{ "name": "b", "$extends": "a.project.json", "tree": {} }"#,
+            ),
+        ]);
+
+        let err = Project::load_exact(&vfs, Path::new("/root/a.project.json"), None).unwrap_err();
+        let message = format!("{:#}", anyhow::Error::from(err));
+        assert!(message.contains("extends itself"), "{message}");
+    }
+
+    #[test]
+    fn profile_overrides_settings() {
+        let mut project = Project::load_from_slice(
+            br#"{
+                "name": "profiles",
+                "placeId": 1,
+                "servePort": 8000,
+                "tree": {
+                    "$className": "DataModel",
+                    "ServerStorage": { "Debug": { "$className": "Folder" } }
+                },
+                "profiles": {
+                    "prod": { "placeId": 2, "prune": ["ServerStorage/Debug"] }
+                }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap();
+
+        project.apply_profile("prod").unwrap();
+
+        assert_eq!(project.place_id, Some(2));
+        assert_eq!(project.serve_port, Some(8000));
+        assert!(project.tree.children["ServerStorage"].children.is_empty());
+        assert_eq!(project.profile.as_deref(), Some("prod"));
+    }
+
+    #[test]
+    fn profile_errors() {
+        let mut project = Project::load_from_slice(
+            br#"{
+                "name": "profiles",
+                "tree": { "$className": "DataModel" },
+                "profiles": {
+                    "dev": {},
+                    "prod": { "prune": ["ServerStorage/Debug"] }
+                }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap();
+
+        let err = project.apply_profile("staging").unwrap_err().to_string();
+        assert!(err.contains("no profile named 'staging'"), "{err}");
+        assert!(err.contains("Available profiles: dev, prod"), "{err}");
+
+        let err = project.apply_profile("prod").unwrap_err().to_string();
+        assert!(err.contains("prunes 'ServerStorage/Debug'"), "{err}");
+    }
+
+    #[test]
+    fn node_defined_by() {
+        let vfs = extends_vfs(vec![
+            (
+                "/root/base.project.json",
+                r#"{
+                    "name": "base",
+                    "tree": {
+                        "$className": "Folder",
+                        "Shared": {
+                            "$className": "StringValue",
+                            "$properties": { "Value": "base" }
+                        },
+                        "Overridden": {
+                            "$className": "StringValue",
+                            "$properties": { "Value": "base", "Archivable": true }
+                        }
+                    }
+                }"#,
+            ),
+            (
+                "/root/default.project.json",
+                r#"{
+                    "name": "child",
+                    "$extends": "base.project.json",
+                    "tree": {
+                        "Overridden": { "$properties": { "Value": "child" } },
+                        "Own": {
+                            "$className": "StringValue",
+                            "$properties": { "Value": "own" }
+                        }
+                    },
+                    "profiles": {
+                        "dev": {
+                            "tree": {
+                                "Own": { "$properties": { "Value": "dev" } },
+                                "Debug": { "$className": "Folder" }
+                            }
+                        }
+                    }
+                }"#,
+            ),
+        ]);
+        let project =
+            Project::load_exact(&vfs, Path::new("/root/default.project.json"), None).unwrap();
+        let defined_by = |profile, node: &str, property: &str| {
+            project.node_defined_by(profile, &[node.to_owned()], &[ustr(property)])
+        };
+
+        assert_eq!(
+            defined_by(Some("dev"), "Own", "Value").unwrap(),
+            "the 'dev' profile"
+        );
+        assert_eq!(
+            defined_by(Some("dev"), "Debug", "Name").unwrap(),
+            "the 'dev' profile"
+        );
+        assert_eq!(defined_by(None, "Own", "Value"), None);
+        assert_eq!(defined_by(Some("dev"), "Own", "Attributes"), None);
+
+        let base = "the project it extends, /root/base.project.json";
+        assert_eq!(defined_by(None, "Shared", "Value").unwrap(), base);
+        assert_eq!(defined_by(None, "Overridden", "Archivable").unwrap(), base);
+        assert_eq!(defined_by(None, "Overridden", "Value"), None);
+    }
+
+    #[test]
+    fn init_file_names() {
+        let project = Project::load_from_slice(
+            br#"{
+                "initFileNames": [
+                    { "name": "_index.luau", "use": "moduleScript" },
+                    { "name": "_root.project.json", "use": "project" }
+                ],
+                "tree": { "$className": "Folder" }
+            }"#,
+            PathBuf::from("/foo/bar/_root.project.json"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(project.name.as_deref(), Some("bar"));
+        assert_eq!(
+            project.init_file_names[0].dir_middleware(),
+            Some(Middleware::ModuleScriptDir)
+        );
+
+        let err = Project::load_from_slice(
+            br#"{
+                "name": "foo",
+                "initFileNames": [{ "name": "_index.txt", "use": "text" }],
+                "tree": { "$className": "Folder" }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("'_index.txt'"), "{err}");
+        assert!(err.contains("Text middleware"), "{err}");
+    }
+
+    #[test]
+    fn invalid_syncback_middleware() {
+        let load = |rules: &str| {
+            Project::load_from_slice(
+                format!(r#"{{ "name": "foo", "syncbackRules": {rules}, "tree": {{}} }}"#)
+                    .as_bytes(),
+                PathBuf::from("/foo/default.project.json"),
+                None,
+            )
+            .map_err(|err| err.to_string())
+        };
+
+        load(
+            r#"{ "classMiddleware": { "Configuration": "jsonModel", "Script": "serverScript" } }"#,
+        )
+        .unwrap();
+
+        let cases = [
+            (
+                r#"{ "classMiddleware": { "Confguration": "jsonModel" } }"#,
+                "classMiddleware entry 'Confguration'",
+                "is not a class in the reflection database",
+            ),
+            (
+                r#"{ "classMiddleware": { "Folder": "serverScript" } }"#,
+                "classMiddleware entry 'Folder'",
+                "serverScript middleware, which can only sync back Script",
+            ),
+            (
+                r#"{ "pathMiddleware": { "Workspace/*": "project" } }"#,
+                "pathMiddleware entry 'Workspace/*'",
+                "can't be used to sync back new Instances",
+            ),
+        ];
+        for (rules, key, reason) in cases {
+            let err = load(rules).unwrap_err();
+            assert!(err.contains(key), "{err}");
+            assert!(err.contains(reason), "{err}");
+        }
+    }
+
+    #[test]
+    fn dir_sync_rules() {
+        let project = Project::load_from_slice(
+            br#"{
+                "name": "foo",
+                "syncRules": [
+                    { "pattern": "*.tool/", "use": "dir", "className": "Tool" },
+                    { "pattern": "*.ignored/", "use": "ignore" }
+                ],
+                "tree": { "$className": "Folder" }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap();
+
+        assert!(project.sync_rules[0].is_dir_rule());
+        assert_eq!(project.sync_rules[0].class_name.as_deref(), Some("Tool"));
+        assert!(project.sync_rules[0].matches_dir(Path::new("src/Sword.tool")));
+        assert!(!project.sync_rules[0].matches(Path::new("src/Sword.tool")));
+
+        for (rule, expected) in [
+            (
+                r#"{ "pattern": "*.tool/", "use": "moduleScript" }"#,
+                "can only use dir or ignore middleware",
+            ),
+            (
+                r#"{ "pattern": "*.tool", "use": "dir" }"#,
+                "must end with a '/'",
+            ),
+            (
+                r#"{ "pattern": "*.luau", "use": "moduleScript", "className": "Tool" }"#,
+                "only rules for directories",
+            ),
+        ] {
+            let contents = format!(
+                r#"{{ "name": "foo", "syncRules": [{rule}], "tree": {{ "$className": "Folder" }} }}"#
+            );
+            let err = Project::load_from_slice(
+                contents.as_bytes(),
+                PathBuf::from("/foo/default.project.json"),
+                None,
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(err.contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn external_sync_rules() {
+        let project = Project::load_from_slice(
+            br#"{
+                "name": "foo",
+                "syncRules": [
+                    {
+                        "pattern": "*.dialogue",
+                        "use": "external",
+                        "command": "dialogue-tool --to-model",
+                        "syncbackCommand": "dialogue-tool --from-model"
+                    }
+                ],
+                "tree": { "$className": "Folder" }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(project.sync_rules[0].middleware, Middleware::External);
+        assert_eq!(
+            project.sync_rules[0].command.as_deref(),
+            Some("dialogue-tool --to-model")
+        );
+
+        for (rule, expected) in [
+            (
+                r#"{ "pattern": "*.dialogue", "use": "external" }"#,
+                "must set a command",
+            ),
+            (
+                r#"{ "pattern": "*.dialogue", "use": "text", "command": "cat" }"#,
+                "only rules using external middleware",
+            ),
+        ] {
+            let contents = format!(
+                r#"{{ "name": "foo", "syncRules": [{rule}], "tree": {{ "$className": "Folder" }} }}"#
+            );
+            let err = Project::load_from_slice(
+                contents.as_bytes(),
+                PathBuf::from("/foo/default.project.json"),
+                None,
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(err.contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn yaml_and_toml_projects() {
+        let yaml = Project::load_from_slice(
+            b"name: foo\nservePort: 1234\ntree:\n  $className: DataModel\n  Workspace:\n    $path: src\n",
+            PathBuf::from("/foo/default.project.yaml"),
+            None,
+        )
+        .unwrap();
+        let toml = Project::load_from_slice(
+            b"name = \"foo\"\nservePort = 1234\n\n[tree]\n\"$className\" = \"DataModel\"\n\n[tree.Workspace]\n\"$path\" = \"src\"\n",
+            PathBuf::from("/foo/default.project.toml"),
+            None,
+        )
+        .unwrap();
+
+        for project in [&yaml, &toml] {
+            assert_eq!(project.serve_port, Some(1234));
+            assert_eq!(
+                project.tree.children["Workspace"]
+                    .path
+                    .as_ref()
+                    .unwrap()
+                    .path(),
+                Path::new("src")
+            );
+        }
+
+        // Projects are written back in the format they were loaded from.
+        let contents = yaml.to_file_contents().unwrap();
+        let reloaded =
+            Project::load_from_slice(&contents, PathBuf::from("/foo/default.project.yaml"), None)
+                .unwrap();
+        assert_eq!(reloaded, yaml);
+        assert!(serde_json::from_slice::<Value>(&contents).is_err());
+
+        let contents = toml.to_file_contents().unwrap();
+        let reloaded =
+            Project::load_from_slice(&contents, PathBuf::from("/foo/default.project.toml"), None)
+                .unwrap();
+        assert_eq!(reloaded, toml);
+    }
+
+    #[test]
+    fn variables_substituted() {
+        let env = |name: &str| (name == "ROJO_TEST_PROJECT_PLACE_ID").then(|| "1234".to_owned());
+
+        let project = Project::load_from_slice_with_env(
+            br#"{
+                "name": "${NAME}-$${NAME}",
+                "placeId": "${ROJO_TEST_PROJECT_PLACE_ID}",
+                "variables": {
+                    "NAME": "game",
+                    "ROJO_TEST_PROJECT_PLACE_ID": "1"
+                },
+                "tree": {
+                    "$className": "DataModel",
+                    "Version": {
+                        "$className": "StringValue",
+                        "$properties": { "Value": "v${NAME}" }
+                    }
+                }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(project.name.as_deref(), Some("game-${NAME}"));
+        assert_eq!(
+            project.substituted_values(),
+            ["game-${NAME}", "1234", "vgame"]
+        );
+        assert_eq!(project.place_id, Some(1234));
+        assert_eq!(
+            serde_json::to_value(&project.tree.children["Version"].properties[&"Value".into()])
+                .unwrap(),
+            serde_json::json!("vgame")
+        );
+
+        let contents: Value = serde_json::from_slice(&project.to_file_contents().unwrap()).unwrap();
+        assert_eq!(contents["name"], "${NAME}-$${NAME}");
+        assert_eq!(contents["placeId"], "${ROJO_TEST_PROJECT_PLACE_ID}");
+        assert_eq!(
+            contents["tree"]["Version"]["$properties"]["Value"],
+            "v${NAME}"
+        );
+    }
+
+    #[test]
+    fn variables_unresolved() {
+        let err = Project::load_from_slice(
+            br#"{
+                "name": "unresolved",
+                "tree": {
+                    "$className": "DataModel",
+                    "Build": { "$path": "${ROJO_TEST_PROJECT_MISSING}/src" }
+                }
+            }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("'ROJO_TEST_PROJECT_MISSING'"), "{err}");
+        assert!(err.contains("'tree.Build.$path'"), "{err}");
+        assert!(err.contains("/foo/default.project.json"), "{err}");
+
+        let err = Project::load_from_slice(
+            br#"{ "name": "${", "tree": {} }"#,
+            PathBuf::from("/foo/default.project.json"),
+            None,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("unterminated or empty variable"), "{err}");
+    }
 }
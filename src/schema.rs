@@ -0,0 +1,350 @@
+//! Generates JSON Schemas for Rojo's project, model, and meta files.
+//!
+//! The schemas are generated from the types these files are deserialized into,
+//! and then filled in with the classes, properties, and enums from the
+//! reflection database so that editors can complete and validate them.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use anyhow::format_err;
+use rbx_dom_weak::types::VariantType;
+use rbx_reflection::DataType;
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    project::Project,
+    resolution::UnresolvedValue,
+    snapshot_middleware::{AdjacentMetadata, DirectoryMetadata, JsonModel},
+};
+
+/// The kinds of files that Rojo can generate a schema for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// `*.project.json` files.
+    Project,
+
+    /// `*.model.json` files.
+    Model,
+
+    /// `*.meta.json` files, except for `init.meta.json`.
+    Meta,
+
+    /// `init.meta.json` files.
+    InitMeta,
+}
+
+impl FromStr for SchemaKind {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source {
+            "project" => Ok(SchemaKind::Project),
+            "model" => Ok(SchemaKind::Model),
+            "meta" => Ok(SchemaKind::Meta),
+            "init-meta" => Ok(SchemaKind::InitMeta),
+            _ => Err(format_err!(
+                "Invalid schema kind '{}'. Valid kinds are: project, model, meta, init-meta",
+                source
+            )),
+        }
+    }
+}
+
+/// Generates the JSON Schema for the given kind of file.
+pub fn generate_schema(kind: SchemaKind) -> Value {
+    let generator = SchemaSettings::draft07().into_generator();
+    let root = match kind {
+        SchemaKind::Project => generator.into_root_schema_for::<Project>(),
+        SchemaKind::Model => generator.into_root_schema_for::<JsonModel>(),
+        SchemaKind::Meta => generator.into_root_schema_for::<AdjacentMetadata>(),
+        SchemaKind::InitMeta => generator.into_root_schema_for::<DirectoryMetadata>(),
+    };
+    let mut schema = serde_json::to_value(root).expect("schemas can always be serialized");
+
+    let mut definitions = match schema["definitions"].take() {
+        Value::Object(definitions) => definitions,
+        _ => Map::new(),
+    };
+
+    let mut enriched = add_class_schemas(&mut schema);
+    for definition in definitions.values_mut() {
+        enriched |= add_class_schemas(definition);
+    }
+    if enriched {
+        add_reflection_definitions(&mut definitions);
+    }
+    definitions.insert("FullyQualifiedValue".to_owned(), fully_qualified_schema());
+
+    schema["definitions"] = Value::Object(definitions);
+    schema
+}
+
+/// The names of the keys that Instances use for their class and properties,
+/// in each of the files there are schemas for.
+const INSTANCE_KEYS: [(&str, &str); 2] =
+    [("$className", "$properties"), ("className", "properties")];
+
+/// Adds the known classes to the given schema if it describes an Instance, and
+/// makes its properties depend on which class it has. Returns whether the
+/// schema describes an Instance.
+fn add_class_schemas(schema: &mut Value) -> bool {
+    let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) else {
+        return false;
+    };
+    let Some((class_key, properties_key)) =
+        INSTANCE_KEYS
+            .into_iter()
+            .find(|(class_key, properties_key)| {
+                properties.contains_key(*class_key) && properties.contains_key(*properties_key)
+            })
+    else {
+        return false;
+    };
+
+    let database = rbx_reflection_database::get().unwrap();
+    let classes: BTreeSet<&str> = database.classes.keys().map(|name| name.as_ref()).collect();
+
+    let class_schema = &mut properties[class_key];
+    if let Some(any_of) = class_schema.get_mut("anyOf").and_then(Value::as_array_mut) {
+        // Optional fields are generated as `anyOf` the type and null.
+        any_of.retain(|schema| schema["type"] != "null");
+        *class_schema = any_of.pop().unwrap_or_default();
+    }
+    class_schema["enum"] = json!(classes);
+
+    let conditions: Vec<Value> = classes
+        .iter()
+        .map(|class_name| {
+            json!({
+                "if": {
+                    "properties": { class_key: { "const": class_name } },
+                    "required": [class_key],
+                },
+                "then": {
+                    "properties": {
+                        properties_key: {
+                            "$ref": format!("#/definitions/Properties.{class_name}")
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    schema["allOf"] = Value::Array(conditions);
+
+    true
+}
+
+/// Adds a definition for the properties of every class and for every enum to
+/// the given definitions.
+fn add_reflection_definitions(definitions: &mut Map<String, Value>) {
+    let database = rbx_reflection_database::get().unwrap();
+
+    let mut types = BTreeMap::new();
+    for (class_name, mut class) in &database.classes {
+        let mut properties = Map::new();
+        loop {
+            for (prop_name, descriptor) in &class.properties {
+                properties
+                    .entry(prop_name.to_string())
+                    .or_insert_with(|| property_schema(&descriptor.data_type, &mut types));
+            }
+            match class.superclass.and_then(|name| database.classes.get(name)) {
+                Some(superclass) => class = superclass,
+                None => break,
+            }
+        }
+
+        definitions.insert(
+            format!("Properties.{class_name}"),
+            json!({
+                "type": "object",
+                "properties": properties,
+                "additionalProperties": false,
+            }),
+        );
+    }
+
+    for (type_name, ty) in types {
+        definitions.insert(format!("Value.{type_name}"), value_schema(ty));
+    }
+
+    for (enum_name, descriptor) in &database.enums {
+        let items: BTreeSet<&str> = descriptor.items.keys().map(|name| name.as_ref()).collect();
+        definitions.insert(
+            format!("Enum.{enum_name}"),
+            json!({
+                "description": format!("A member of the {enum_name} enum"),
+                "anyOf": [
+                    { "type": "string", "enum": items },
+                    { "$ref": "#/definitions/FullyQualifiedValue" },
+                ]
+            }),
+        );
+    }
+}
+
+/// Returns the schema for a property with the given type, which refers to the
+/// definition for that type. Types that need a definition are added to `types`.
+fn property_schema(data_type: &DataType, types: &mut BTreeMap<String, VariantType>) -> Value {
+    match data_type {
+        DataType::Enum(enum_name) => {
+            json!({ "$ref": format!("#/definitions/Enum.{enum_name}") })
+        }
+        DataType::Value(ty) => {
+            let type_name = format!("{ty:?}");
+            let reference = json!({ "$ref": format!("#/definitions/Value.{type_name}") });
+            types.insert(type_name, *ty);
+            reference
+        }
+        _ => json!({ "$ref": "#/definitions/UnresolvedValue" }),
+    }
+}
+
+/// Returns the schema for a value of the given type. Values can always be
+/// given with their type, and some types can also be given without it.
+fn value_schema(ty: VariantType) -> Value {
+    let numbers = |count: usize| {
+        json!({
+            "type": "array",
+            "items": { "type": "number" },
+            "minItems": count,
+            "maxItems": count,
+        })
+    };
+    let ambiguous = match ty {
+        VariantType::Bool => json!({ "type": "boolean" }),
+        VariantType::Float32 | VariantType::Float64 | VariantType::Int32 | VariantType::Int64 => {
+            json!({ "type": "number" })
+        }
+        VariantType::String | VariantType::Content | VariantType::ContentId => {
+            json!({ "type": "string" })
+        }
+        VariantType::Tags => json!({ "type": "array", "items": { "type": "string" } }),
+        VariantType::Vector2 => numbers(2),
+        VariantType::Vector3 | VariantType::Color3 => numbers(3),
+        VariantType::CFrame => numbers(12),
+        VariantType::Attributes | VariantType::Font | VariantType::MaterialColors => {
+            json!({ "type": "object" })
+        }
+        _ => json!({ "$ref": "#/definitions/FullyQualifiedValue" }),
+    };
+
+    json!({
+        "description": format!("{ty:?}"),
+        "anyOf": [ambiguous, { "$ref": "#/definitions/FullyQualifiedValue" }],
+    })
+}
+
+fn fully_qualified_schema() -> Value {
+    json!({
+        "description": "A value with its type given explicitly, like { \"Vector3\": [1, 2, 3] }",
+        "type": "object",
+        "minProperties": 1,
+        "maxProperties": 1,
+    })
+}
+
+fn schema_from_json(value: Value) -> Schema {
+    serde_json::from_value(value).expect("schemas written as JSON should be valid")
+}
+
+impl JsonSchema for UnresolvedValue {
+    fn schema_name() -> String {
+        "UnresolvedValue".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        schema_from_json(json!({
+            "description": "The value of a property, which can be given with or without its type",
+            "anyOf": [
+                { "type": "boolean" },
+                { "type": "number" },
+                { "type": "string" },
+                { "type": "array", "items": { "type": ["number", "string"] } },
+                { "type": "object" },
+            ]
+        }))
+    }
+}
+
+/// The schema for a map of property names to values. Which properties are
+/// allowed is filled in separately, since it depends on the Instance's class.
+pub(crate) fn properties_schema(generator: &mut SchemaGenerator) -> Schema {
+    let value = generator.subschema_for::<UnresolvedValue>();
+    schema_from_json(json!({
+        "type": "object",
+        "additionalProperties": value,
+    }))
+}
+
+/// The schema for a map of attribute names to values. Attributes have to be
+/// given without their type only if it can be inferred from the value.
+pub(crate) fn attributes_schema(_: &mut SchemaGenerator) -> Schema {
+    schema_from_json(json!({
+        "type": "object",
+        "additionalProperties": {
+            "anyOf": [
+                { "type": "boolean" },
+                { "type": "number" },
+                { "type": "string" },
+                { "$ref": "#/definitions/FullyQualifiedValue" },
+            ]
+        },
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn project_schema() {
+        let schema = generate_schema(SchemaKind::Project);
+        let definitions = &schema["definitions"];
+
+        let node = &definitions["ProjectNode"];
+        let classes = node["properties"]["$className"]["enum"].as_array().unwrap();
+        assert!(classes.contains(&json!("Part")));
+        assert!(node["allOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|condition| condition["if"]["properties"]["$className"]["const"] == "Part"));
+
+        let part = &definitions["Properties.Part"]["properties"];
+        assert_eq!(part["Anchored"]["$ref"], "#/definitions/Value.Bool");
+        assert_eq!(definitions["Value.Bool"]["description"], "Bool");
+        assert_eq!(part["Material"]["$ref"], "#/definitions/Enum.Material");
+
+        let materials = definitions["Enum.Material"]["anyOf"][0]["enum"]
+            .as_array()
+            .unwrap();
+        assert!(materials.contains(&json!("Neon")));
+    }
+
+    #[test]
+    fn meta_schema_has_no_classes() {
+        let schema = generate_schema(SchemaKind::Meta);
+
+        assert!(schema["properties"]["properties"].is_object());
+        assert!(schema.get("allOf").is_none());
+        assert!(schema["definitions"].get("Properties.Part").is_none());
+    }
+
+    #[test]
+    fn model_schema() {
+        let schema = generate_schema(SchemaKind::Model);
+
+        assert!(schema["allOf"].is_array());
+        assert!(schema["definitions"]["Properties.Part"].is_object());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     io,
     net::IpAddr,
     path::Path,
@@ -14,6 +14,7 @@ use thiserror::Error;
 use crate::{
     change_processor::ChangeProcessor,
     message_queue::MessageQueue,
+    preprocess::DefineValue,
     project::{Project, ProjectError},
     session_id::SessionId,
     snapshot::{
@@ -95,23 +96,62 @@ impl ServeSession {
     /// currently loaded from the filesystem directly instead of through the
     /// in-memory filesystem layer.
     pub fn new<P: AsRef<Path>>(vfs: Vfs, start_path: P) -> Result<Self, ServeSessionError> {
+        Self::new_with_profile(vfs, start_path, None)
+    }
+
+    /// Start a new serve session like `ServeSession::new`, using the project
+    /// profile with the given name if there is one.
+    pub fn new_with_profile<P: AsRef<Path>>(
+        vfs: Vfs,
+        start_path: P,
+        profile: Option<&str>,
+    ) -> Result<Self, ServeSessionError> {
+        Self::new_with_defines(vfs, start_path, profile, &BTreeMap::new())
+    }
+
+    /// Start a new serve session like `ServeSession::new_with_profile`, with
+    /// names defined for preprocessing Lua sources that take priority over the
+    /// ones defined by projects.
+    pub fn new_with_defines<P: AsRef<Path>>(
+        vfs: Vfs,
+        start_path: P,
+        profile: Option<&str>,
+        defines: &BTreeMap<String, DefineValue>,
+    ) -> Result<Self, ServeSessionError> {
         let start_time = Instant::now();
         let start_path = vfs.canonicalize(start_path.as_ref())?;
         let start_path = start_path.as_path();
 
         log::trace!("Starting new ServeSession at path {}", start_path.display());
 
-        let root_project = Project::load_initial_project(&vfs, start_path)?;
+        let mut root_project = Project::load_initial_project(&vfs, start_path)?;
+        if let Some(profile) = profile {
+            root_project.apply_profile(profile)?;
+        }
+        let tree = Self::build_tree(&vfs, &root_project, start_path, defines)?;
 
+        Ok(Self::start(vfs, root_project, tree, start_time))
+    }
+
+    /// Builds the tree of instances for `root_project` from the in-memory
+    /// filesystem. `start_path` is expected to be canonical.
+    pub fn build_tree(
+        vfs: &Vfs,
+        root_project: &Project,
+        start_path: &Path,
+        defines: &BTreeMap<String, DefineValue>,
+    ) -> Result<RojoTree, ServeSessionError> {
         let mut tree = RojoTree::new(InstanceSnapshot::new());
 
         let root_id = tree.get_root_id();
 
-        let instance_context =
+        let mut instance_context =
             InstanceContext::with_emit_legacy_scripts(root_project.emit_legacy_scripts);
+        instance_context.set_profile(root_project.profile.clone());
+        instance_context.set_command_line_defines(defines.clone());
 
         log::trace!("Generating snapshot of instances from VFS");
-        let snapshot = snapshot_from_vfs(&instance_context, &vfs, start_path)?;
+        let snapshot = snapshot_from_vfs(&instance_context, vfs, start_path)?;
 
         log::trace!("Computing initial patch set");
         let patch_set = compute_patch_set(snapshot, &tree, root_id);
@@ -119,6 +159,10 @@ impl ServeSession {
         log::trace!("Applying initial patch set");
         apply_patch_set(&mut tree, patch_set);
 
+        Ok(tree)
+    }
+
+    fn start(vfs: Vfs, root_project: Project, tree: RojoTree, start_time: Instant) -> Self {
         let session_id = SessionId::new();
         let message_queue = MessageQueue::new();
 
@@ -134,9 +178,10 @@ impl ServeSession {
             Arc::clone(&vfs),
             Arc::clone(&message_queue),
             tree_mutation_receiver,
+            root_project.clone(),
         );
 
-        Ok(Self {
+        Self {
             change_processor,
             start_time,
             session_id,
@@ -145,7 +190,7 @@ impl ServeSession {
             message_queue,
             tree_mutation_sender,
             vfs,
-        })
+        }
     }
 
     pub fn tree_handle(&self) -> Arc<Mutex<RojoTree>> {
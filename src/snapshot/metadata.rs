@@ -1,15 +1,18 @@
 use std::{
+    collections::BTreeMap,
     fmt,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::Context;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     glob::{Glob, IgnorableGlob},
     path_serializer,
+    preprocess::{apply_defines, DefineValue},
     project::ProjectNode,
     snapshot_middleware::{emit_legacy_scripts_default, Middleware},
     RojoRef,
@@ -65,6 +68,7 @@ pub struct InstanceMetadata {
 
     /// The Middleware that was used to create this Instance. Should generally
     /// not be `None` except if the snapshotting process is not completed.
+    #[serde(default, with = "crate::snapshot_middleware::any_middleware")]
     pub middleware: Option<Middleware>,
 
     /// A schema provided via a JSON file, if one exists. Will be `None` for
@@ -140,11 +144,27 @@ impl Default for InstanceMetadata {
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InstanceContext {
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub path_ignore_rules: Arc<Vec<PathIgnoreRule>>,
     pub emit_legacy_scripts: bool,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub emit_luau_types: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub sync_rules: Vec<SyncRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub init_file_names: Vec<InitFileName>,
+    /// The name of the profile selected for the root project. Nested projects
+    /// that define a profile with the same name use it too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// The names defined for preprocessing Lua sources, along with their
+    /// values. Sources are only preprocessed if this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defines: Option<BTreeMap<String, String>>,
+    /// Names defined outside of any project, like on the command line. These
+    /// take priority over the defines of every project.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub command_line_defines: BTreeMap<String, DefineValue>,
 }
 
 impl InstanceContext {
@@ -152,7 +172,12 @@ impl InstanceContext {
         Self {
             path_ignore_rules: Arc::new(Vec::new()),
             emit_legacy_scripts: emit_legacy_scripts_default().unwrap(),
+            emit_luau_types: false,
             sync_rules: Vec::new(),
+            init_file_names: Vec::new(),
+            profile: None,
+            defines: None,
+            command_line_defines: BTreeMap::new(),
         }
     }
 
@@ -196,14 +221,62 @@ impl InstanceContext {
         self.sync_rules.clear();
     }
 
+    /// Replaces the init file names in the context with the given ones.
+    pub fn set_init_file_names(&mut self, init_file_names: Vec<InitFileName>) {
+        self.init_file_names = init_file_names;
+    }
+
     pub fn set_emit_legacy_scripts(&mut self, emit_legacy_scripts: bool) {
         self.emit_legacy_scripts = emit_legacy_scripts;
     }
 
+    pub fn set_emit_luau_types(&mut self, emit_luau_types: bool) {
+        self.emit_luau_types = emit_luau_types;
+    }
+
+    pub fn set_profile(&mut self, profile: Option<String>) {
+        self.profile = profile;
+    }
+
+    /// Sets the names defined outside of any project. If there are any, Lua
+    /// sources are preprocessed even if no project defines anything.
+    pub fn set_command_line_defines(&mut self, defines: BTreeMap<String, DefineValue>) {
+        if !defines.is_empty() {
+            apply_defines(self.defines.get_or_insert_with(BTreeMap::new), &defines);
+        }
+        self.command_line_defines = defines;
+    }
+
+    /// Adds the names defined by a project, which turns on preprocessing for
+    /// Lua sources. Names defined outside of any project keep their values.
+    pub fn add_project_defines(&mut self, defines: &BTreeMap<String, DefineValue>) {
+        let current = self.defines.get_or_insert_with(BTreeMap::new);
+        apply_defines(current, defines);
+        apply_defines(current, &self.command_line_defines);
+    }
+
     /// Returns the middleware specified by the first sync rule that
     /// matches the provided path. This does not handle default syncing rules.
     pub fn get_user_sync_rule(&self, path: &Path) -> Option<&SyncRule> {
-        self.sync_rules.iter().find(|&rule| rule.matches(path))
+        self.sync_rules
+            .iter()
+            .find(|&rule| !rule.is_dir_rule() && rule.matches(path))
+    }
+
+    /// Returns the first sync rule for directories that matches the provided
+    /// directory path.
+    pub fn get_user_dir_sync_rule(&self, path: &Path) -> Option<&SyncRule> {
+        self.sync_rules
+            .iter()
+            .find(|&rule| rule.is_dir_rule() && rule.matches_dir(path))
+    }
+
+    /// Returns whether the given file name is one of the user-specified init
+    /// file names. This does not handle the default init file names.
+    pub fn is_user_init_file_name(&self, file_name: &str) -> bool {
+        self.init_file_names
+            .iter()
+            .any(|init_file_name| init_file_name.name == file_name)
     }
 }
 
@@ -314,9 +387,12 @@ impl From<&Path> for InstigatingSource {
 
 /// Represents an user-specified rule for transforming files
 /// into Instances using a given middleware.
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+///
+/// Rules whose pattern ends with a `/` apply to directories instead of files.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
 pub struct SyncRule {
-    /// A pattern used to determine if a file is included in this SyncRule
+    /// A pattern used to determine if a file is included in this SyncRule.
+    /// If it ends with a `/`, it matches directories instead of files.
     #[serde(rename = "pattern")]
     pub include: Glob,
     /// A pattern used to determine if a file is excluded from this SyncRule.
@@ -329,6 +405,25 @@ pub struct SyncRule {
     /// If not specified, the file extension is the only thing cut off.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suffix: Option<String>,
+    /// The class of the Instances made from directories matching this rule.
+    /// Only directory rules that use the `dir` middleware can set this.
+    #[serde(rename = "className", default, skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    /// The command that files matching this rule are piped to when they're
+    /// read. It must write a model to stdout. Only rules that use the
+    /// `external` middleware can set this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// The command that models are piped to when Instances are written back
+    /// to files matching this rule with syncback. What it writes to stdout is
+    /// used as the file's contents. Only rules that use the `external`
+    /// middleware can set this.
+    #[serde(
+        rename = "syncbackCommand",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub syncback_command: Option<String>,
     /// The 'base' of the glob above, allowing it to be used
     /// relative to a path instead of absolute.
     #[serde(skip)]
@@ -336,21 +431,38 @@ pub struct SyncRule {
 }
 
 impl SyncRule {
+    /// Returns whether this rule applies to directories rather than files,
+    /// which is the case when its pattern ends with a `/`.
+    pub fn is_dir_rule(&self) -> bool {
+        self.include.as_str().ends_with('/')
+    }
+
     /// Returns whether the given path matches this rule.
     pub fn matches(&self, path: &Path) -> bool {
         match path.strip_prefix(&self.base_path) {
-            Ok(suffix) => {
-                if let Some(pattern) = &self.exclude {
-                    if pattern.is_match(suffix) {
-                        return false;
-                    }
-                }
-                self.include.is_match(suffix)
-            }
+            Ok(suffix) => self.matches_suffix(suffix),
             Err(_) => false,
         }
     }
 
+    /// Returns whether the given directory path matches this rule. The path is
+    /// matched with a trailing `/`, so only directory rules can match it.
+    pub fn matches_dir(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.base_path) {
+            Ok(suffix) => self.matches_suffix(Path::new(&format!("{}/", suffix.display()))),
+            Err(_) => false,
+        }
+    }
+
+    fn matches_suffix(&self, suffix: &Path) -> bool {
+        if let Some(pattern) = &self.exclude {
+            if pattern.is_match(suffix) {
+                return false;
+            }
+        }
+        self.include.is_match(suffix)
+    }
+
     pub fn file_name_for_path<'a>(&self, path: &'a Path) -> anyhow::Result<&'a str> {
         if let Some(suffix) = &self.suffix {
             let file_name = path
@@ -372,3 +484,31 @@ impl SyncRule {
         }
     }
 }
+
+/// Represents a user-specified file name that makes the directory containing
+/// it take on the identity of that file, like `init.luau` does by default.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct InitFileName {
+    /// The name of the file, like `_index.luau`.
+    pub name: String,
+    /// The middleware used to read the file. Only script, CSV, and project
+    /// middleware can be used for init files.
+    #[serde(rename = "use")]
+    pub middleware: Middleware,
+}
+
+impl InitFileName {
+    /// Returns the middleware used for a directory containing this file, or
+    /// `None` if this file's middleware can't be used for init files.
+    pub fn dir_middleware(&self) -> Option<Middleware> {
+        match self.middleware {
+            Middleware::ModuleScript => Some(Middleware::ModuleScriptDir),
+            Middleware::ServerScript => Some(Middleware::ServerScriptDir),
+            Middleware::ClientScript => Some(Middleware::ClientScriptDir),
+            Middleware::PluginScript => Some(Middleware::PluginScriptDir),
+            Middleware::Csv => Some(Middleware::CsvDir),
+            Middleware::Project => Some(Middleware::Project),
+            _ => None,
+        }
+    }
+}
@@ -65,6 +65,23 @@ impl RojoTree {
         tree
     }
 
+    /// Creates a tree from an existing `WeakDom` and the metadata for each of
+    /// its instances. Every instance in `inner` is expected to have metadata.
+    pub fn from_parts(inner: WeakDom, metadata: HashMap<Ref, InstanceMetadata>) -> RojoTree {
+        let mut tree = RojoTree {
+            inner,
+            metadata_map: HashMap::with_capacity(metadata.len()),
+            path_to_ids: MultiMap::new(),
+            specified_id_to_refs: MultiMap::new(),
+        };
+
+        for (id, metadata) in metadata {
+            tree.insert_metadata(id, metadata);
+        }
+
+        tree
+    }
+
     pub fn inner(&self) -> &WeakDom {
         &self.inner
     }
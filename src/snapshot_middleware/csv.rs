@@ -11,13 +11,13 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
-    syncback::{FsSnapshot, SyncbackReturn, SyncbackSnapshot},
+    syncback::{init_name_for_middleware, FsSnapshot, SyncbackReturn, SyncbackSnapshot},
 };
 
 use super::{
     dir::{snapshot_dir_no_meta, syncback_dir_no_meta},
-    meta_file::{AdjacentMetadata, DirectoryMetadata},
-    PathExt as _,
+    meta_file::{dir_meta_path, file_meta_path, meta_to_vec, AdjacentMetadata, DirectoryMetadata},
+    Middleware,
 };
 
 pub fn snapshot_csv(
@@ -108,11 +108,9 @@ pub fn syncback_csv<'sync>(
         meta.properties.shift_remove(&ustr("Contents"));
 
         if !meta.is_empty() {
-            let parent = snapshot.path.parent_err()?;
-            fs_snapshot.add_file(
-                parent.join(format!("{}.meta.json", new_inst.name)),
-                serde_json::to_vec_pretty(&meta).context("cannot serialize metadata")?,
-            )
+            let meta_path = file_meta_path(snapshot.vfs(), &snapshot.path, &new_inst.name)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            fs_snapshot.add_file(meta_path, contents);
         }
     }
 
@@ -136,8 +134,13 @@ pub fn syncback_csv_init<'sync>(
         };
 
     let mut dir_syncback = syncback_dir_no_meta(snapshot)?;
+    let init_name = init_name_for_middleware(
+        Middleware::CsvDir,
+        &snapshot.project().init_file_names,
+        "init.csv",
+    );
     dir_syncback.fs_snapshot.add_file(
-        snapshot.path.join("init.csv"),
+        snapshot.path.join(init_name),
         localization_to_csv(contents)?,
     );
 
@@ -147,11 +150,9 @@ pub fn syncback_csv_init<'sync>(
         // about, so shifting is fine.
         meta.properties.shift_remove(&ustr("Contents"));
         if !meta.is_empty() {
-            dir_syncback.fs_snapshot.add_file(
-                snapshot.path.join("init.meta.json"),
-                serde_json::to_vec_pretty(&meta)
-                    .context("could not serialize new init.meta.json")?,
-            );
+            let meta_path = dir_meta_path(snapshot.vfs(), &snapshot.path)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            dir_syncback.fs_snapshot.add_file(meta_path, contents);
         }
     }
 
@@ -211,7 +212,7 @@ fn convert_localization_csv(contents: &[u8]) -> anyhow::Result<String> {
     for record in &records {
         let mut entry = LocalizationEntry::default();
 
-        for (header, value) in headers.iter().zip(record.into_iter()) {
+        for (header, value) in headers.iter().zip(record) {
             if header.is_empty() || value.is_empty() {
                 continue;
             }
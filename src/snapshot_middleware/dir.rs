@@ -3,17 +3,20 @@ use std::{
     path::Path,
 };
 
-use anyhow::Context;
 use memofs::{DirEntry, Vfs};
 
 use crate::{
     snapshot::{
         is_path_ignored, InstanceContext, InstanceMetadata, InstanceSnapshot, InstigatingSource,
+        SyncRule,
     },
     syncback::{hash_instance, FsSnapshot, SyncbackReturn, SyncbackSnapshot},
 };
 
-use super::{meta_file::DirectoryMetadata, snapshot_from_vfs};
+use super::{
+    meta_file::{dir_meta_path, meta_to_vec, DirectoryMetadata},
+    snapshot_from_vfs,
+};
 
 const EMPTY_DIR_KEEP_NAME: &str = ".gitkeep";
 
@@ -61,7 +64,7 @@ pub fn snapshot_dir_no_meta(
     }
 
     let normalized_path = vfs.canonicalize(path)?;
-    let relevant_paths = vec![
+    let mut relevant_paths = vec![
         normalized_path.clone(),
         // TODO: We shouldn't need to know about Lua existing in this
         // middleware. Should we figure out a way for that function to add
@@ -76,6 +79,12 @@ pub fn snapshot_dir_no_meta(
         normalized_path.join("init.plugin.luau"),
         normalized_path.join("init.csv"),
     ];
+    relevant_paths.extend(
+        context
+            .init_file_names
+            .iter()
+            .map(|init_file_name| normalized_path.join(&init_file_name.name)),
+    );
 
     let snapshot = InstanceSnapshot::new()
         .name(name)
@@ -98,18 +107,12 @@ pub fn syncback_dir<'sync>(
 
     let mut dir_syncback = syncback_dir_no_meta(snapshot)?;
 
-    let mut meta = DirectoryMetadata::from_syncback_snapshot(snapshot, snapshot.path.clone())?;
-    if let Some(meta) = &mut meta {
-        if new_inst.class != "Folder" {
-            meta.class_name = Some(new_inst.class);
-        }
-
+    let meta = dir_metadata(snapshot, true)?;
+    if let Some(meta) = &meta {
         if !meta.is_empty() {
-            dir_syncback.fs_snapshot.add_file(
-                snapshot.path.join("init.meta.json"),
-                serde_json::to_vec_pretty(&meta)
-                    .context("could not serialize new init.meta.json")?,
-            );
+            let meta_path = dir_meta_path(snapshot.vfs(), &snapshot.path)?;
+            let contents = meta_to_vec(&meta_path, meta)?;
+            dir_syncback.fs_snapshot.add_file(meta_path, contents);
         }
     }
 
@@ -126,6 +129,65 @@ pub fn syncback_dir<'sync>(
     Ok(dir_syncback)
 }
 
+/// Writes only the `init.meta` file for a directory-backed Instance,
+/// without visiting any of its children.
+///
+/// `with_class_name` should only be set for plain directories, since other
+/// directory middleware (like init scripts) decide their own class.
+pub fn syncback_dir_meta(
+    snapshot: &SyncbackSnapshot,
+    with_class_name: bool,
+) -> anyhow::Result<FsSnapshot> {
+    let mut fs_snapshot = FsSnapshot::new();
+
+    if let Some(meta) = dir_metadata(snapshot, with_class_name)? {
+        if !meta.is_empty() {
+            let meta_path = dir_meta_path(snapshot.vfs(), &snapshot.path)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            fs_snapshot.add_file(meta_path, contents);
+        }
+    }
+
+    Ok(fs_snapshot)
+}
+
+fn dir_metadata(
+    snapshot: &SyncbackSnapshot,
+    with_class_name: bool,
+) -> anyhow::Result<Option<DirectoryMetadata>> {
+    let new_inst = snapshot.new_inst();
+
+    let mut meta = DirectoryMetadata::from_syncback_snapshot(snapshot, snapshot.path.clone())?;
+    if let Some(meta) = &mut meta {
+        if with_class_name
+            && new_inst.class != "Folder"
+            && dir_rule_class_name(snapshot) != Some(new_inst.class.as_str())
+        {
+            meta.class_name = Some(new_inst.class);
+        }
+    }
+
+    Ok(meta)
+}
+
+/// Returns the class given to the directory being written by the project's
+/// first sync rule for directories that matches it, if there is one.
+fn dir_rule_class_name<'a>(snapshot: &'a SyncbackSnapshot) -> Option<&'a str> {
+    let project = snapshot.project();
+    let base_path = project.folder_location();
+    project
+        .sync_rules
+        .iter()
+        .find(|rule| {
+            let rule = SyncRule {
+                base_path: base_path.to_path_buf(),
+                ..(*rule).clone()
+            };
+            rule.is_dir_rule() && rule.matches_dir(&snapshot.path)
+        })
+        .and_then(|rule| rule.class_name.as_deref())
+}
+
 pub fn syncback_dir_no_meta<'sync>(
     snapshot: &SyncbackSnapshot<'sync>,
 ) -> anyhow::Result<SyncbackReturn<'sync>> {
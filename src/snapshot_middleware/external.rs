@@ -0,0 +1,329 @@
+//! Middleware that hands files off to a command specified by a sync rule, so
+//! that formats Rojo doesn't know about can still be turned into Instances.
+//!
+//! The command is given the contents of the file on stdin and must write a
+//! model to stdout, in the same shape as a `.model.json` file. Syncback works
+//! the other way around, but only if the rule has a `syncbackCommand`.
+//!
+//! Since projects can come from anywhere, commands are only run if the user
+//! allows it with `--allow-external-commands`.
+
+use std::{
+    io::{Read, Write as _},
+    path::Path,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, format_err, Context as _};
+use memofs::Vfs;
+
+use crate::{
+    json,
+    snapshot::{InstanceContext, InstanceSnapshot, SyncRule},
+    syncback::{FsSnapshot, SyncbackReturn, SyncbackSnapshot},
+};
+
+use super::{
+    json_model::{snapshot_model, syncback_model, JsonModel},
+    Middleware,
+};
+
+/// The name of the environment variable that commands are given the path of
+/// the file they're reading or writing in.
+const PATH_VAR: &str = "ROJO_FILE_PATH";
+
+/// How long commands are given to finish before they're killed.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+static COMMANDS_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether sync rules are allowed to run their commands. Until this is
+/// called, any file that needs a command to be run fails to sync.
+pub fn set_external_commands_allowed(allowed: bool) {
+    COMMANDS_ALLOWED.store(allowed, Ordering::Relaxed);
+}
+
+pub fn snapshot_external(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    name: &str,
+) -> anyhow::Result<Option<InstanceSnapshot>> {
+    let rule = context
+        .get_user_sync_rule(path)
+        .filter(|rule| rule.middleware == Middleware::External)
+        .with_context(|| format!("no sync rule with a command matches {}", path.display()))?;
+    let command = rule
+        .command
+        .as_deref()
+        .with_context(|| format!("the sync rule for {} has no command", path.display()))?;
+
+    let contents = vfs.read(path)?;
+    let output = run_command(command, rule, path, &contents)?;
+
+    let instance: JsonModel = json::from_slice_with_context(&output, || {
+        format!(
+            "The output of `{command}` for {} is not a valid model",
+            path.display()
+        )
+    })?;
+
+    snapshot_model(context, vfs, path, name, instance).map(Some)
+}
+
+pub fn syncback_external<'sync>(
+    snapshot: &SyncbackSnapshot<'sync>,
+) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let rule = syncback_rule(snapshot).with_context(|| {
+        format!(
+            "no sync rule with a command matches {}",
+            snapshot.path.display()
+        )
+    })?;
+    let command = rule.syncback_command.as_deref().with_context(|| {
+        format!(
+            "the sync rule for {} has no syncbackCommand",
+            snapshot.path.display()
+        )
+    })?;
+
+    let model = syncback_model(snapshot);
+    let input = serde_json::to_vec(&model).context("failed to serialize new JSON Model")?;
+    let contents = run_command(command, &rule, &snapshot.path, &input)?;
+
+    Ok(SyncbackReturn {
+        fs_snapshot: FsSnapshot::new().with_added_file(&snapshot.path, contents),
+        children: Vec::new(),
+        removed_children: Vec::new(),
+    })
+}
+
+/// Returns the first of the project's sync rules that uses external
+/// middleware and matches the path of the snapshot, with its base path set.
+pub fn syncback_rule(snapshot: &SyncbackSnapshot) -> Option<SyncRule> {
+    let base_path = snapshot.project().folder_location();
+    snapshot
+        .project()
+        .sync_rules
+        .iter()
+        .filter(|rule| rule.middleware == Middleware::External)
+        .map(|rule| SyncRule {
+            base_path: base_path.to_path_buf(),
+            ..rule.clone()
+        })
+        .find(|rule| rule.matches(&snapshot.path))
+}
+
+/// Runs `command` from the folder of the project that defined `rule`, writing
+/// `input` to its stdin and returning what it wrote to stdout.
+fn run_command(
+    command: &str,
+    rule: &SyncRule,
+    path: &Path,
+    input: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    if !COMMANDS_ALLOWED.load(Ordering::Relaxed) {
+        bail!(
+            "{} needs the command `{command}` from a sync rule to be run, but running commands \
+            from projects is disabled. Pass --allow-external-commands to allow it",
+            path.display()
+        );
+    }
+
+    run_command_with_timeout(command, rule, path, input, COMMAND_TIMEOUT)
+}
+
+fn run_command_with_timeout(
+    command: &str,
+    rule: &SyncRule,
+    path: &Path,
+    input: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<Vec<u8>> {
+    let args = shlex::split(command)
+        .filter(|args| !args.is_empty())
+        .with_context(|| format!("the command `{command}` could not be parsed"))?;
+
+    log::debug!("Running `{command}` for {}", path.display());
+
+    let mut process = Command::new(&args[0]);
+    process
+        .args(&args[1..])
+        .env(PATH_VAR, path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if !rule.base_path.as_os_str().is_empty() {
+        process.current_dir(&rule.base_path);
+    }
+
+    let mut child = process
+        .spawn()
+        .with_context(|| format!("could not run `{command}` for {}", path.display()))?;
+
+    // The input is written from another thread so that commands which write
+    // output before reading all of their input can't deadlock with us.
+    let mut stdin = child.stdin.take().expect("stdin should be piped");
+    let input = input.to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let stdout = read_in_background(child.stdout.take().expect("stdout should be piped"));
+    let stderr = read_in_background(child.stderr.take().expect("stderr should be piped"));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        let status = child
+            .try_wait()
+            .with_context(|| format!("could not run `{command}` for {}", path.display()))?;
+        if let Some(status) = status {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            // The command may have already exited by now, which is fine.
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "`{command}` did not finish for {} within {} seconds",
+                path.display(),
+                timeout.as_secs_f32()
+            );
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let read_output = |reader: thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .expect("reading output should not panic")
+            .with_context(|| format!("could not read the output of `{command}`"))
+    };
+    let stdout = read_output(stdout)?;
+    let stderr = read_output(stderr)?;
+
+    if let Err(err) = writer.join().expect("writing to stdin should not panic") {
+        // Commands are allowed to exit without reading everything they're
+        // given, which closes the pipe early.
+        if err.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(format_err!(err).context(format!("could not write to `{command}`")));
+        }
+    }
+
+    if !status.success() {
+        bail!(
+            "`{command}` failed for {} ({}):\n{}",
+            path.display(),
+            status,
+            String::from_utf8_lossy(&stderr).trim_end()
+        );
+    }
+
+    Ok(stdout)
+}
+
+/// Reads everything from `reader` on another thread, so that a command can't
+/// block on one of its pipes filling up while we wait for it to exit.
+fn read_in_background(
+    mut reader: impl Read + Send + 'static,
+) -> thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        Ok(contents)
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use memofs::{InMemoryFs, VfsSnapshot};
+
+    use crate::glob::Glob;
+
+    fn context_with_command(command: &str) -> InstanceContext {
+        set_external_commands_allowed(true);
+
+        let mut context = InstanceContext::new();
+        context.add_sync_rules([SyncRule {
+            include: Glob::new("*.dialogue").unwrap(),
+            exclude: None,
+            middleware: Middleware::External,
+            suffix: None,
+            class_name: None,
+            command: Some(command.to_owned()),
+            syncback_command: None,
+            base_path: PathBuf::new(),
+        }]);
+        context
+    }
+
+    #[test]
+    fn snapshot_from_command() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo.dialogue",
+            VfsSnapshot::file(r#"{ "className": "Folder", "children": [{ "name": "Line", "className": "StringValue", "properties": { "Value": "Hello" } }] }"#),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let snapshot = snapshot_external(
+            &context_with_command("cat"),
+            &vfs,
+            Path::new("/foo.dialogue"),
+            "foo",
+        )
+        .unwrap()
+        .unwrap();
+
+        insta::assert_yaml_snapshot!(snapshot);
+    }
+
+    #[test]
+    fn failing_command() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.dialogue", VfsSnapshot::file("hello"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let err = snapshot_external(
+            &context_with_command("sh -c 'echo bad dialogue >&2; exit 3'"),
+            &vfs,
+            Path::new("/foo.dialogue"),
+            "foo",
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("bad dialogue"), "{err}");
+    }
+
+    #[test]
+    fn command_timeout() {
+        let context = context_with_command("sleep 10");
+        let rule = &context.sync_rules[0];
+
+        let start = Instant::now();
+        let err = run_command_with_timeout(
+            "sleep 10",
+            rule,
+            Path::new("/foo.dialogue"),
+            b"",
+            Duration::from_millis(100),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("did not finish"), "{err}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,15 +1,22 @@
 use std::path::Path;
 
+use anyhow::Context as _;
 use memofs::Vfs;
 use rbx_dom_weak::ustr;
 
 use crate::{
     json,
-    lua_ast::{Expression, Statement},
+    lua_ast::{parse_return, Expression},
     snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    syncback::{SyncbackReturn, SyncbackSnapshot},
 };
 
-use super::meta_file::AdjacentMetadata;
+use super::{
+    lua::{script_source, syncback_script_file},
+    meta_file::AdjacentMetadata,
+    util::data_module_source,
+    ScriptType,
+};
 
 pub fn snapshot_json(
     context: &InstanceContext,
@@ -23,7 +30,7 @@ pub fn snapshot_json(
         format!("File contains malformed JSON: {}", path.display())
     })?;
 
-    let as_lua = json_to_lua(value).to_string();
+    let as_lua = data_module_source(context, name, json_to_lua_value(value));
 
     let mut snapshot = InstanceSnapshot::new()
         .name(name)
@@ -41,10 +48,6 @@ pub fn snapshot_json(
     Ok(Some(snapshot))
 }
 
-fn json_to_lua(value: serde_json::Value) -> Statement {
-    Statement::Return(json_to_lua_value(value))
-}
-
 fn json_to_lua_value(value: serde_json::Value) -> Expression {
     use serde_json::Value;
 
@@ -65,6 +68,56 @@ fn json_to_lua_value(value: serde_json::Value) -> Expression {
     }
 }
 
+pub fn syncback_json<'sync>(
+    snapshot: &SyncbackSnapshot<'sync>,
+) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let source = script_source(snapshot.new_inst())?;
+    let value = parse_return(source)
+        .context("the ModuleScript does not only return a literal value")
+        .and_then(lua_to_json_value)?;
+
+    let mut contents = serde_json::to_vec_pretty(&value).context("cannot serialize JSON")?;
+    contents.push(b'\n');
+
+    syncback_script_file(ScriptType::Module, snapshot, contents)
+}
+
+fn lua_to_json_value(value: Expression) -> anyhow::Result<serde_json::Value> {
+    use serde_json::{Number, Value};
+
+    Ok(match value {
+        Expression::Nil => Value::Null,
+        Expression::Bool(value) => Value::Bool(value),
+        Expression::Number(value) => {
+            if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+                Value::Number(Number::from(value as i64))
+            } else {
+                Value::Number(
+                    Number::from_f64(value)
+                        .with_context(|| format!("JSON cannot represent the number {value}"))?,
+                )
+            }
+        }
+        Expression::String(value) => Value::String(value),
+        Expression::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(lua_to_json_value)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Expression::Table(table) => Value::Object(
+            table
+                .entries
+                .into_iter()
+                .map(|(key, value)| match key {
+                    Expression::String(key) => Ok((key, lua_to_json_value(value)?)),
+                    _ => anyhow::bail!("JSON objects can only have string keys"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -144,4 +197,36 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn with_luau_types() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/game-config.json",
+            VfsSnapshot::file(
+                r#"{
+                  "maxPlayers": 8,
+                  "maps": ["Desert", "Forest"],
+                  "teams": [{ "name": "Red" }, { "name": "Blue" }]
+                }"#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let mut context = InstanceContext::default();
+        context.set_emit_luau_types(true);
+
+        let instance_snapshot = snapshot_json(
+            &context,
+            &vfs,
+            Path::new("/game-config.json"),
+            "game-config",
+        )
+        .unwrap()
+        .unwrap();
+
+        insta::assert_yaml_snapshot!(instance_snapshot);
+    }
 }
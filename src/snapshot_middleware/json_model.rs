@@ -7,10 +7,11 @@ use rbx_dom_weak::{
     types::{Attributes, Ref, Variant},
     HashMapExt as _, Ustr, UstrMap,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    json,
+    file_format::{self, FileFormat},
     resolution::UnresolvedValue,
     snapshot::{InstanceContext, InstanceSnapshot},
     syncback::{filter_properties_preallocated, FsSnapshot, SyncbackReturn, SyncbackSnapshot},
@@ -31,12 +32,17 @@ pub fn snapshot_json_model(
         return Ok(None);
     }
 
-    let mut instance: JsonModel = json::from_str_with_context(contents_str, || {
-        format!("File is not a valid JSON model: {}", path.display())
-    })?;
+    let instance: JsonModel =
+        file_format::from_slice_with_context(path, contents_str.as_bytes(), || {
+            format!("File is not a valid model: {}", path.display())
+        })?;
 
     if let Some(top_level_name) = &instance.name {
-        let new_name = format!("{}.model.json", top_level_name);
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("json");
+        let new_name = format!("{top_level_name}.model.{extension}");
 
         log::warn!(
             "Model at path {} had a top-level Name field. \
@@ -47,6 +53,18 @@ pub fn snapshot_json_model(
         );
     }
 
+    snapshot_model(context, vfs, path, name, instance).map(Some)
+}
+
+/// Turns a model that was read from the file at `path` into a snapshot with
+/// the given name.
+pub(super) fn snapshot_model(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    name: &str,
+    mut instance: JsonModel,
+) -> anyhow::Result<InstanceSnapshot> {
     instance.name = Some(name.to_owned());
 
     let id = instance.id.take().map(RojoRef::new);
@@ -64,12 +82,29 @@ pub fn snapshot_json_model(
         .specified_id(id)
         .schema(schema);
 
-    Ok(Some(snapshot))
+    Ok(snapshot)
 }
 
 pub fn syncback_json_model<'sync>(
     snapshot: &SyncbackSnapshot<'sync>,
 ) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let model = syncback_model(snapshot);
+
+    Ok(SyncbackReturn {
+        fs_snapshot: FsSnapshot::new().with_added_file(
+            &snapshot.path,
+            FileFormat::from_path(&snapshot.path)
+                .to_vec(&model)
+                .context("failed to serialize new JSON Model")?,
+        ),
+        children: Vec::new(),
+        removed_children: Vec::new(),
+    })
+}
+
+/// Returns the model that the new Instance in the snapshot and its
+/// descendants should be written as.
+pub(super) fn syncback_model(snapshot: &SyncbackSnapshot) -> JsonModel {
     let mut property_buffer = Vec::with_capacity(snapshot.new_inst().properties.len());
 
     let mut model = json_model_from_pair(snapshot, &mut property_buffer, snapshot.new);
@@ -83,14 +118,7 @@ pub fn syncback_json_model<'sync>(
         model.schema = old_inst.metadata().schema.clone();
     }
 
-    Ok(SyncbackReturn {
-        fs_snapshot: FsSnapshot::new().with_added_file(
-            &snapshot.path,
-            serde_json::to_vec_pretty(&model).context("failed to serialize new JSON Model")?,
-        ),
-        children: Vec::new(),
-        removed_children: Vec::new(),
-    })
+    model
 }
 
 fn json_model_from_pair<'sync>(
@@ -147,9 +175,9 @@ fn json_model_from_pair<'sync>(
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-struct JsonModel {
+pub(crate) struct JsonModel {
     #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
     schema: Option<String>,
 
@@ -157,16 +185,13 @@ struct JsonModel {
     name: Option<String>,
 
     #[serde(alias = "ClassName")]
+    #[schemars(with = "String")]
     class_name: Ustr,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     id: Option<String>,
 
-    #[serde(
-        alias = "Children",
-        default = "Vec::new",
-        skip_serializing_if = "Vec::is_empty"
-    )]
+    #[serde(alias = "Children", default, skip_serializing_if = "Vec::is_empty")]
     children: Vec<JsonModel>,
 
     #[serde(
@@ -174,9 +199,11 @@ struct JsonModel {
         default,
         skip_serializing_if = "IndexMap::is_empty"
     )]
+    #[schemars(schema_with = "crate::schema::properties_schema")]
     properties: IndexMap<Ustr, UnresolvedValue>,
 
-    #[serde(default = "IndexMap::new", skip_serializing_if = "IndexMap::is_empty")]
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "crate::schema::attributes_schema")]
     attributes: IndexMap<String, UnresolvedValue>,
 }
 
@@ -299,4 +326,70 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn model_from_vfs_yaml() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo.model.yaml",
+            VfsSnapshot::file(
+                r#"
+className: IntValue
+properties:
+  Value: 5
+children:
+  - name: The Child
+    className: StringValue
+"#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot = snapshot_json_model(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.model.yaml"),
+            "foo",
+        )
+        .unwrap()
+        .unwrap();
+
+        insta::assert_yaml_snapshot!(instance_snapshot);
+    }
+
+    #[test]
+    fn model_from_vfs_toml() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo.model.toml",
+            VfsSnapshot::file(
+                r#"
+className = "IntValue"
+
+[properties]
+Value = 5
+
+[[children]]
+name = "The Child"
+className = "StringValue"
+"#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot = snapshot_json_model(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo.model.toml"),
+            "foo",
+        )
+        .unwrap()
+        .unwrap();
+
+        insta::assert_yaml_snapshot!(instance_snapshot);
+    }
 }
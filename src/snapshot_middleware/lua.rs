@@ -1,24 +1,29 @@
-use std::{path::Path, str};
+use std::{borrow::Cow, path::Path, str};
 
 use anyhow::Context as _;
-use memofs::Vfs;
+use indexmap::IndexMap;
+use memofs::{IoResultExt as _, Vfs};
 use rbx_dom_weak::{
     types::{Enum, Variant},
-    ustr, HashMapExt as _, UstrMap,
+    ustr, HashMapExt as _, Instance, Ustr, UstrMap,
 };
 
 use crate::{
-    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
-    syncback::{FsSnapshot, SyncbackReturn, SyncbackSnapshot},
+    preprocess::{is_preprocessed, preprocess},
+    resolution::UnresolvedValue,
+    snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot, SyncRule},
+    syncback::{init_name_for_middleware, FsSnapshot, SyncbackReturn, SyncbackSnapshot},
 };
 
 use super::{
+    default_sync_rules,
     dir::{snapshot_dir_no_meta, syncback_dir_no_meta},
-    meta_file::{AdjacentMetadata, DirectoryMetadata},
-    PathExt as _,
+    emit_legacy_scripts_default,
+    meta_file::{dir_meta_path, file_meta_path, meta_to_vec, AdjacentMetadata, DirectoryMetadata},
+    Middleware,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScriptType {
     Server,
     Client,
@@ -30,6 +35,77 @@ pub enum ScriptType {
     RunContextClient,
 }
 
+impl ScriptType {
+    /// Returns the type of script the given middleware produces, if it
+    /// produces a script at all.
+    pub fn from_middleware(middleware: Middleware) -> Option<Self> {
+        Some(match middleware {
+            Middleware::ServerScript | Middleware::ServerScriptDir => Self::Server,
+            Middleware::ClientScript | Middleware::ClientScriptDir => Self::Client,
+            Middleware::ModuleScript | Middleware::ModuleScriptDir => Self::Module,
+            Middleware::PluginScript | Middleware::PluginScriptDir => Self::Plugin,
+            Middleware::LegacyServerScript => Self::LegacyServer,
+            Middleware::LegacyClientScript => Self::LegacyClient,
+            Middleware::RunContextServerScript => Self::RunContextServer,
+            Middleware::RunContextClientScript => Self::RunContextClient,
+            _ => return None,
+        })
+    }
+
+    /// Returns the class and the name of the `RunContext` that scripts of
+    /// this type are given when they're snapshotted.
+    fn class_and_run_context(
+        self,
+        emit_legacy_scripts: bool,
+    ) -> (&'static str, Option<&'static str>) {
+        match self {
+            Self::Server => {
+                if emit_legacy_scripts {
+                    ("Script", Some("Legacy"))
+                } else {
+                    ("Script", Some("Server"))
+                }
+            }
+            Self::Client => {
+                if emit_legacy_scripts {
+                    ("LocalScript", None)
+                } else {
+                    ("Script", Some("Client"))
+                }
+            }
+            Self::Module => ("ModuleScript", None),
+            Self::Plugin => ("Script", Some("Plugin")),
+            Self::LegacyServer => ("Script", Some("Legacy")),
+            Self::LegacyClient => ("LocalScript", None),
+            Self::RunContextServer => ("Script", Some("Server")),
+            Self::RunContextClient => ("Script", Some("Client")),
+        }
+    }
+}
+
+fn run_context_value(name: &str) -> u32 {
+    *rbx_reflection_database::get()
+        .unwrap()
+        .enums
+        .get("RunContext")
+        .expect("Unable to get RunContext enums!")
+        .items
+        .get(name)
+        .unwrap_or_else(|| panic!("Unable to get RunContext {name}!"))
+}
+
+fn default_run_context(class_name: &str) -> Option<u32> {
+    let class = rbx_reflection_database::get()
+        .unwrap()
+        .classes
+        .get(class_name)?;
+
+    match class.default_properties.get("RunContext") {
+        Some(Variant::Enum(value)) => Some(value.to_u32()),
+        _ => None,
+    }
+}
+
 /// Core routine for turning Lua files into snapshots.
 pub fn snapshot_lua(
     context: &InstanceContext,
@@ -38,38 +114,15 @@ pub fn snapshot_lua(
     name: &str,
     script_type: ScriptType,
 ) -> anyhow::Result<Option<InstanceSnapshot>> {
-    let run_context_enums = &rbx_reflection_database::get()
-        .unwrap()
-        .enums
-        .get("RunContext")
-        .expect("Unable to get RunContext enums!")
-        .items;
-
-    let (class_name, run_context) = match script_type {
-        ScriptType::Server => {
-            if context.emit_legacy_scripts {
-                ("Script", run_context_enums.get("Legacy"))
-            } else {
-                ("Script", run_context_enums.get("Server"))
-            }
-        }
-        ScriptType::Client => {
-            if context.emit_legacy_scripts {
-                ("LocalScript", None)
-            } else {
-                ("Script", run_context_enums.get("Client"))
-            }
-        }
-        ScriptType::Module => ("ModuleScript", None),
-        ScriptType::Plugin => ("Script", run_context_enums.get("Plugin")),
-        ScriptType::LegacyServer => ("Script", run_context_enums.get("Legacy")),
-        ScriptType::LegacyClient => ("LocalScript", None),
-        ScriptType::RunContextServer => ("Script", run_context_enums.get("Server")),
-        ScriptType::RunContextClient => ("Script", run_context_enums.get("Client")),
-    };
+    let (class_name, run_context) = script_type.class_and_run_context(context.emit_legacy_scripts);
 
     let contents = vfs.read_to_string_lf_normalized(path)?;
-    let contents_str = contents.as_str();
+    let contents_str = match &context.defines {
+        Some(defines) => preprocess(&contents, defines)
+            .with_context(|| format!("Could not preprocess {}", path.display()))?,
+        None => Cow::Borrowed(contents.as_str()),
+    };
+    let contents_str = contents_str.as_ref();
 
     let mut properties = UstrMap::with_capacity(2);
     properties.insert(ustr("Source"), contents_str.into());
@@ -77,7 +130,7 @@ pub fn snapshot_lua(
     if let Some(run_context) = run_context {
         properties.insert(
             ustr("RunContext"),
-            Enum::from_u32(run_context.to_owned()).into(),
+            Enum::from_u32(run_context_value(run_context)).into(),
         );
     }
 
@@ -138,15 +191,55 @@ pub fn snapshot_lua_init(
 }
 
 pub fn syncback_lua<'sync>(
+    script_type: ScriptType,
     snapshot: &SyncbackSnapshot<'sync>,
 ) -> anyhow::Result<SyncbackReturn<'sync>> {
-    let new_inst = snapshot.new_inst();
+    check_not_preprocessed(snapshot, &snapshot.path)?;
+    let contents = script_source(snapshot.new_inst())?.as_bytes().to_vec();
+    syncback_script_file(script_type, snapshot, contents)
+}
 
-    let contents = if let Some(Variant::String(source)) = new_inst.properties.get(&ustr("Source")) {
-        source.as_bytes().to_vec()
-    } else {
-        anyhow::bail!("Scripts must have a `Source` property that is a String")
+/// Returns an error if the script in the snapshot was read from a file that
+/// was changed by preprocessing, since writing its `Source` to the file would
+/// lose the directives and any code they left out.
+fn check_not_preprocessed(snapshot: &SyncbackSnapshot, path: &Path) -> anyhow::Result<()> {
+    let Some(old_inst) = snapshot.old_inst() else {
+        return Ok(());
+    };
+    let Some(defines) = &old_inst.metadata().context.defines else {
+        return Ok(());
     };
+    let Some(contents) = snapshot
+        .vfs()
+        .read_to_string_lf_normalized(path)
+        .with_not_found()?
+    else {
+        return Ok(());
+    };
+
+    if is_preprocessed(&contents, defines) {
+        anyhow::bail!(
+            "{} can't be synced back because it has preprocessor directives",
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes `contents` to the path of a script's SyncbackSnapshot, along with a
+/// meta file containing any properties other than `Source` that need to be
+/// preserved.
+///
+/// This is shared by the middleware that turn files into scripts, whether the
+/// file contains the script's source or something it's generated from.
+pub fn syncback_script_file<'sync>(
+    script_type: ScriptType,
+    snapshot: &SyncbackSnapshot<'sync>,
+    contents: Vec<u8>,
+) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let new_inst = snapshot.new_inst();
+
     let mut fs_snapshot = FsSnapshot::new();
     fs_snapshot.add_file(&snapshot.path, contents);
 
@@ -155,13 +248,17 @@ pub fn syncback_lua<'sync>(
         // Scripts have relatively few properties that we care about, so shifting
         // is fine.
         meta.properties.shift_remove(&ustr("Source"));
+        reconcile_run_context(
+            snapshot,
+            script_type,
+            Some(&snapshot.path),
+            &mut meta.properties,
+        );
 
         if !meta.is_empty() {
-            let parent_location = snapshot.path.parent_err()?;
-            fs_snapshot.add_file(
-                parent_location.join(format!("{}.meta.json", new_inst.name)),
-                serde_json::to_vec_pretty(&meta).context("cannot serialize metadata")?,
-            );
+            let meta_path = file_meta_path(snapshot.vfs(), &snapshot.path, &new_inst.name)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            fs_snapshot.add_file(meta_path, contents);
         }
     }
 
@@ -173,24 +270,34 @@ pub fn syncback_lua<'sync>(
     })
 }
 
+/// Returns the `Source` of a script, or an error if it doesn't have one.
+pub fn script_source(inst: &Instance) -> anyhow::Result<&str> {
+    match inst.properties.get(&ustr("Source")) {
+        Some(Variant::String(source)) => Ok(source),
+        _ => anyhow::bail!("Scripts must have a `Source` property that is a String"),
+    }
+}
+
 pub fn syncback_lua_init<'sync>(
     script_type: ScriptType,
     snapshot: &SyncbackSnapshot<'sync>,
 ) -> anyhow::Result<SyncbackReturn<'sync>> {
     let new_inst = snapshot.new_inst();
-    let path = snapshot.path.join(match script_type {
-        ScriptType::Server => "init.server.luau",
-        ScriptType::Client => "init.client.luau",
-        ScriptType::Module => "init.luau",
-        ScriptType::Plugin => "init.plugin.luau",
-        _ => anyhow::bail!("syncback is not yet implemented for {script_type:?}"),
-    });
-
-    let contents = if let Some(Variant::String(source)) = new_inst.properties.get(&ustr("Source")) {
-        source.as_bytes().to_vec()
-    } else {
-        anyhow::bail!("Scripts must have a `Source` property that is a String")
+    let (middleware, default_name) = match script_type {
+        ScriptType::Server => (Middleware::ServerScriptDir, "init.server.luau"),
+        ScriptType::Client => (Middleware::ClientScriptDir, "init.client.luau"),
+        ScriptType::Module => (Middleware::ModuleScriptDir, "init.luau"),
+        ScriptType::Plugin => (Middleware::PluginScriptDir, "init.plugin.luau"),
+        _ => anyhow::bail!("{script_type:?} scripts cannot be used as init scripts"),
     };
+    let path = snapshot.path.join(init_name_for_middleware(
+        middleware,
+        &snapshot.project().init_file_names,
+        default_name,
+    ));
+
+    check_not_preprocessed(snapshot, &path)?;
+    let contents = script_source(new_inst)?.as_bytes().to_vec();
 
     let mut dir_syncback = syncback_dir_no_meta(snapshot)?;
     dir_syncback.fs_snapshot.add_file(&path, contents);
@@ -200,19 +307,103 @@ pub fn syncback_lua_init<'sync>(
         // Scripts have relatively few properties that we care about, so shifting
         // is fine.
         meta.properties.shift_remove(&ustr("Source"));
+        reconcile_run_context(snapshot, script_type, None, &mut meta.properties);
 
         if !meta.is_empty() {
-            dir_syncback.fs_snapshot.add_file(
-                snapshot.path.join("init.meta.json"),
-                serde_json::to_vec_pretty(&meta)
-                    .context("could not serialize new init.meta.json")?,
-            );
+            let meta_path = dir_meta_path(snapshot.vfs(), &snapshot.path)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            dir_syncback.fs_snapshot.add_file(meta_path, contents);
         }
     }
 
     Ok(dir_syncback)
 }
 
+/// Makes sure that a script's `RunContext` is only written to its metadata
+/// when snapshotting it again wouldn't already produce the right one.
+///
+/// The script type that the file will be read back as is determined using the
+/// Instance's existing middleware if it has one and, if `file_path` is
+/// provided, the project's sync rules. Otherwise, `script_type` is used.
+fn reconcile_run_context(
+    snapshot: &SyncbackSnapshot,
+    script_type: ScriptType,
+    file_path: Option<&Path>,
+    properties: &mut IndexMap<Ustr, UnresolvedValue>,
+) {
+    let new_inst = snapshot.new_inst();
+    let project = snapshot.project();
+
+    let mut read_as = None;
+    let mut emit_legacy_scripts = project
+        .emit_legacy_scripts
+        .or_else(emit_legacy_scripts_default)
+        .unwrap();
+
+    if let Some(old_inst) = snapshot.old_inst() {
+        let metadata = old_inst.metadata();
+        emit_legacy_scripts = metadata.context.emit_legacy_scripts;
+
+        let same_source = metadata
+            .instigating_source
+            .as_ref()
+            .is_some_and(|source| source.path() == snapshot.path);
+        if same_source {
+            read_as = metadata.middleware.and_then(ScriptType::from_middleware);
+        }
+    }
+
+    if read_as.is_none() {
+        if let Some(file_path) = file_path {
+            let base_path = project.folder_location();
+            let project_rules = project.sync_rules.iter().map(|rule| SyncRule {
+                base_path: base_path.to_path_buf(),
+                ..rule.clone()
+            });
+            read_as = project_rules
+                .chain(default_sync_rules().iter().cloned())
+                .find(|rule| rule.matches(file_path))
+                .and_then(|rule| ScriptType::from_middleware(rule.middleware));
+        }
+    }
+
+    let read_as = read_as.unwrap_or(script_type);
+    let (class_name, expected) = read_as.class_and_run_context(emit_legacy_scripts);
+
+    if new_inst.class != class_name {
+        log::warn!(
+            "{} is a {} but will be read back as a {class_name}",
+            snapshot.get_new_inst_path(snapshot.new),
+            new_inst.class,
+        );
+    }
+
+    // Scripts that aren't given a RunContext when they're snapshotted are left
+    // with their class's default one.
+    let run_context_name = ustr("RunContext");
+    let expected = match expected {
+        Some(name) => Some(run_context_value(name)),
+        None => default_run_context(class_name),
+    };
+    let actual = match new_inst.properties.get(&run_context_name) {
+        Some(Variant::Enum(value)) => Some(value.to_u32()),
+        _ => default_run_context(&new_inst.class),
+    };
+
+    if actual == expected {
+        properties.shift_remove(&run_context_name);
+    } else if let Some(actual) = actual {
+        properties.insert(
+            run_context_name,
+            UnresolvedValue::from_variant(
+                Enum::from_u32(actual).into(),
+                &new_inst.class,
+                "RunContext",
+            ),
+        );
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -7,18 +7,25 @@ use rbx_dom_weak::{
     types::{Attributes, Variant},
     Ustr,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    json, resolution::UnresolvedValue, snapshot::InstanceSnapshot, syncback::SyncbackSnapshot,
+    file_format::{self, FileFormat},
+    resolution::UnresolvedValue,
+    snapshot::InstanceSnapshot,
+    syncback::SyncbackSnapshot,
     RojoRef,
 };
 
+use super::PathExt as _;
+
 /// Represents metadata in a sibling file with the same basename.
 ///
 /// As an example, hello.meta.json next to hello.lua would allow assigning
-/// additional metadata to the instance resulting from hello.lua.
-#[derive(Debug, Serialize, Deserialize)]
+/// additional metadata to the instance resulting from hello.lua. Meta files can
+/// also be written as `.meta.jsonc`, `.meta.yaml`, or `.meta.toml`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AdjacentMetadata {
     #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
@@ -31,9 +38,11 @@ pub struct AdjacentMetadata {
     pub ignore_unknown_instances: Option<bool>,
 
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "crate::schema::properties_schema")]
     pub properties: IndexMap<Ustr, UnresolvedValue>,
 
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "crate::schema::attributes_schema")]
     pub attributes: IndexMap<String, UnresolvedValue>,
 
     #[serde(skip)]
@@ -52,30 +61,25 @@ impl AdjacentMetadata {
         name: &str,
         snapshot: &mut InstanceSnapshot,
     ) -> anyhow::Result<()> {
-        let meta_path_json = path.with_file_name(format!("{name}.meta.json"));
-        let meta_path_jsonc = path.with_file_name(format!("{name}.meta.jsonc"));
+        for extension in file_format::EXTENSIONS {
+            let meta_path = path.with_file_name(format!("{name}.meta.{extension}"));
 
-        if let Some(meta_contents) = vfs.read(&meta_path_json).with_not_found()? {
-            let mut metadata = Self::from_slice(&meta_contents, meta_path_json.clone())?;
-            metadata.apply_all(snapshot)?;
-        }
+            if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
+                let mut metadata = Self::from_slice(&meta_contents, meta_path.clone())?;
+                metadata.apply_all(snapshot)?;
+            }
 
-        if let Some(meta_contents) = vfs.read(&meta_path_jsonc).with_not_found()? {
-            let mut metadata = Self::from_slice(&meta_contents, meta_path_json.clone())?;
-            metadata.apply_all(snapshot)?;
+            // Rather than pushing these in the snapshot middleware, we can just do it here.
+            snapshot.metadata.relevant_paths.push(meta_path);
         }
 
-        // Rather than pushing these in the snapshot middleware, we can just do it here.
-        snapshot.metadata.relevant_paths.push(meta_path_json);
-        snapshot.metadata.relevant_paths.push(meta_path_jsonc);
-
         Ok(())
     }
 
     fn from_slice(slice: &[u8], path: PathBuf) -> anyhow::Result<Self> {
-        let mut meta: Self = json::from_slice_with_context(slice, || {
+        let mut meta: Self = file_format::from_slice_with_context(&path, slice, || {
             format!(
-                "File contained malformed .meta.json data: {}",
+                "File contained malformed meta file data: {}",
                 path.display()
             )
         })?;
@@ -241,7 +245,7 @@ impl AdjacentMetadata {
 /// folder.
 ///
 /// This is always sourced from a file named init.meta.json.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DirectoryMetadata {
     #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
@@ -254,12 +258,15 @@ pub struct DirectoryMetadata {
     pub ignore_unknown_instances: Option<bool>,
 
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "crate::schema::properties_schema")]
     pub properties: IndexMap<Ustr, UnresolvedValue>,
 
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(schema_with = "crate::schema::attributes_schema")]
     pub attributes: IndexMap<String, UnresolvedValue>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
     pub class_name: Option<Ustr>,
 
     #[serde(skip)]
@@ -277,30 +284,25 @@ impl DirectoryMetadata {
         path: &Path,
         snapshot: &mut InstanceSnapshot,
     ) -> anyhow::Result<()> {
-        let meta_path_json = path.join("init.meta.json");
-        let meta_path_jsonc = path.join("init.meta.jsonc");
+        for extension in file_format::EXTENSIONS {
+            let meta_path = path.join(format!("init.meta.{extension}"));
 
-        if let Some(meta_contents) = vfs.read(&meta_path_json).with_not_found()? {
-            let mut metadata = Self::from_slice(&meta_contents, meta_path_json.clone())?;
-            metadata.apply_all(snapshot)?;
-        }
+            if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
+                let mut metadata = Self::from_slice(&meta_contents, meta_path.clone())?;
+                metadata.apply_all(snapshot)?;
+            }
 
-        if let Some(meta_contents) = vfs.read(&meta_path_jsonc).with_not_found()? {
-            let mut metadata = Self::from_slice(&meta_contents, meta_path_jsonc.clone())?;
-            metadata.apply_all(snapshot)?;
+            // Rather than pushing these in the snapshot middleware, we can just do it here.
+            snapshot.metadata.relevant_paths.push(meta_path);
         }
 
-        // Rather than pushing these in the snapshot middleware, we can just do it here.
-        snapshot.metadata.relevant_paths.push(meta_path_json);
-        snapshot.metadata.relevant_paths.push(meta_path_jsonc);
-
         Ok(())
     }
 
     fn from_slice(slice: &[u8], path: PathBuf) -> anyhow::Result<Self> {
-        let mut meta: Self = json::from_slice_with_context(slice, || {
+        let mut meta: Self = file_format::from_slice_with_context(&path, slice, || {
             format!(
-                "File contained malformed init.meta.json data: {}",
+                "File contained malformed init.meta file data: {}",
                 path.display()
             )
         })?;
@@ -484,16 +486,19 @@ impl DirectoryMetadata {
 }
 
 /// Retrieves the meta file that should be applied for the provided directory,
-/// if it exists.
+/// if it exists. If there are meta files in several formats, the first one in
+/// [`file_format::EXTENSIONS`] is used.
 pub fn dir_meta(vfs: &Vfs, path: &Path) -> anyhow::Result<Option<DirectoryMetadata>> {
-    let meta_path = path.join("init.meta.json");
+    for extension in file_format::EXTENSIONS {
+        let meta_path = path.join(format!("init.meta.{extension}"));
 
-    if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
-        let metadata = DirectoryMetadata::from_slice(&meta_contents, meta_path)?;
-        Ok(Some(metadata))
-    } else {
-        Ok(None)
+        if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
+            let metadata = DirectoryMetadata::from_slice(&meta_contents, meta_path)?;
+            return Ok(Some(metadata));
+        }
     }
+
+    Ok(None)
 }
 
 /// Retrieves the meta file that should be applied for the provided file,
@@ -501,15 +506,53 @@ pub fn dir_meta(vfs: &Vfs, path: &Path) -> anyhow::Result<Option<DirectoryMetada
 ///
 /// The `name` field should be the name the metadata should have.
 pub fn file_meta(vfs: &Vfs, path: &Path, name: &str) -> anyhow::Result<Option<AdjacentMetadata>> {
-    let mut meta_path = path.with_file_name(name);
-    meta_path.set_extension("meta.json");
-
-    if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
-        let metadata = AdjacentMetadata::from_slice(&meta_contents, meta_path)?;
-        Ok(Some(metadata))
-    } else {
-        Ok(None)
+    for extension in file_format::EXTENSIONS {
+        let meta_path = path.with_file_name(format!("{name}.meta.{extension}"));
+
+        if let Some(meta_contents) = vfs.read(&meta_path).with_not_found()? {
+            let metadata = AdjacentMetadata::from_slice(&meta_contents, meta_path)?;
+            return Ok(Some(metadata));
+        }
     }
+
+    Ok(None)
+}
+
+/// Returns the path syncback should write the meta file for the provided
+/// directory to: the existing `init.meta` file in whichever format it's in, or
+/// `init.meta.json` if there isn't one.
+pub fn dir_meta_path(vfs: &Vfs, path: &Path) -> anyhow::Result<PathBuf> {
+    existing_meta_path(vfs, |extension| path.join(format!("init.meta.{extension}")))
+}
+
+/// Returns the path syncback should write the meta file for the provided file
+/// to: the existing meta file in whichever format it's in, or a `.meta.json`
+/// file if there isn't one.
+///
+/// The `name` field should be the name the metadata should have.
+pub fn file_meta_path(vfs: &Vfs, path: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let parent = path.parent_err()?;
+    existing_meta_path(vfs, |extension| {
+        parent.join(format!("{name}.meta.{extension}"))
+    })
+}
+
+fn existing_meta_path(vfs: &Vfs, meta_path: impl Fn(&str) -> PathBuf) -> anyhow::Result<PathBuf> {
+    for extension in file_format::EXTENSIONS {
+        let path = meta_path(extension);
+        if vfs.metadata(&path).with_not_found()?.is_some() {
+            return Ok(path);
+        }
+    }
+
+    Ok(meta_path("json"))
+}
+
+/// Serializes metadata in the format of the meta file at `path`.
+pub fn meta_to_vec<T: Serialize>(path: &Path, meta: &T) -> anyhow::Result<Vec<u8>> {
+    FileFormat::from_path(path)
+        .to_vec(meta)
+        .with_context(|| format!("could not serialize {}", path.display()))
 }
 
 #[cfg(test)]
@@ -589,4 +632,42 @@ mod test {
 
         insta::assert_yaml_snapshot!(snapshot);
     }
+
+    #[test]
+    fn adjacent_read_yaml() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo/bar.meta.yaml",
+            VfsSnapshot::file("id: manually specified\nproperties:\n  Value: 5\n"),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let path = Path::new("/foo/bar.rojo");
+        let mut snapshot = InstanceSnapshot::new().class_name("IntValue");
+
+        AdjacentMetadata::read_and_apply_all(&vfs, path, "bar", &mut snapshot).unwrap();
+
+        insta::assert_yaml_snapshot!(snapshot);
+    }
+
+    #[test]
+    fn directory_read_toml() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo/init.meta.toml",
+            VfsSnapshot::file(
+                "id = \"manually specified\"\nclassName = \"Configuration\"\n\n[attributes]\nEnabled = true\n",
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let path = Path::new("/foo/");
+        let mut snapshot = InstanceSnapshot::new().class_name("Folder");
+
+        DirectoryMetadata::read_and_apply_all(&vfs, path, &mut snapshot).unwrap();
+
+        insta::assert_yaml_snapshot!(snapshot);
+    }
 }
@@ -7,6 +7,7 @@
 
 mod csv;
 mod dir;
+mod external;
 mod json;
 mod json_model;
 mod lua;
@@ -26,34 +27,41 @@ use std::{
 
 use anyhow::Context;
 use memofs::{IoResultExt, Vfs};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     glob::Glob,
     project::DEFAULT_PROJECT_NAMES,
-    syncback::{SyncbackReturn, SyncbackSnapshot},
+    syncback::{FsSnapshot, SyncbackReturn, SyncbackSnapshot},
 };
 use crate::{
-    snapshot::{InstanceContext, InstanceSnapshot, SyncRule},
+    snapshot::{InitFileName, InstanceContext, InstanceSnapshot, SyncRule},
     syncback::validate_file_name,
 };
 
 use self::{
     csv::{snapshot_csv, snapshot_csv_init, syncback_csv, syncback_csv_init},
-    dir::{snapshot_dir, syncback_dir},
-    json::snapshot_json,
+    dir::{snapshot_dir, snapshot_dir_no_meta, syncback_dir, syncback_dir_meta},
+    external::{snapshot_external, syncback_external},
+    json::{snapshot_json, syncback_json},
     json_model::{snapshot_json_model, syncback_json_model},
     lua::{snapshot_lua, snapshot_lua_init, syncback_lua, syncback_lua_init},
     project::{snapshot_project, syncback_project},
     rbxm::{snapshot_rbxm, syncback_rbxm},
     rbxmx::{snapshot_rbxmx, syncback_rbxmx},
-    toml::snapshot_toml,
+    toml::{snapshot_toml, syncback_toml},
     txt::{snapshot_txt, syncback_txt},
-    yaml::snapshot_yaml,
+    yaml::{snapshot_yaml, syncback_yaml},
 };
 
+pub(crate) use self::json_model::JsonModel;
 pub use self::{
-    lua::ScriptType, project::snapshot_project_node, util::emit_legacy_scripts_default,
+    external::{set_external_commands_allowed, syncback_rule},
+    lua::ScriptType,
+    meta_file::{AdjacentMetadata, DirectoryMetadata},
+    project::{snapshot_project_node, syncback_project_node_properties},
+    util::emit_legacy_scripts_default,
     util::PathExt,
 };
 
@@ -73,11 +81,12 @@ pub fn snapshot_from_vfs(
     };
 
     if meta.is_dir() {
-        let (middleware, dir_name, init_path) = get_dir_middleware(vfs, path)?;
-        // TODO: Support user defined init paths
-        // If and when we do, make sure to go support it in
-        // `Project::set_file_name`, as right now it special-cases
-        // `default.project.json` as an `init` path.
+        if let Some(rule) = context.get_user_dir_sync_rule(path) {
+            return snapshot_dir_from_rule(context, vfs, path, rule);
+        }
+
+        let (middleware, dir_name, init_path) =
+            get_dir_middleware(&context.init_file_names, vfs, path)?;
         match middleware {
             Middleware::Dir => middleware.snapshot(context, vfs, path, dir_name),
             _ => middleware.snapshot(context, vfs, &init_path, dir_name),
@@ -94,6 +103,7 @@ pub fn snapshot_from_vfs(
             | "init.plugin.luau" | "init.plugin.lua" | "init.luau" | "init.lua" | "init.csv" => {
                 return Ok(None)
             }
+            _ if context.is_user_init_file_name(file_name) => return Ok(None),
             _ => {}
         }
 
@@ -101,13 +111,37 @@ pub fn snapshot_from_vfs(
     }
 }
 
+/// Returns the init file that the directory at `path` is snapshotted from, like
+/// `init.luau`, or `None` if it isn't a directory with one.
+pub fn dir_init_file(
+    init_file_names: &[InitFileName],
+    vfs: &Vfs,
+    path: &Path,
+) -> anyhow::Result<Option<PathBuf>> {
+    let is_dir = vfs
+        .metadata(path)
+        .with_not_found()?
+        .is_some_and(|meta| meta.is_dir());
+    if !is_dir {
+        return Ok(None);
+    }
+
+    let (middleware, _, init_path) = get_dir_middleware(init_file_names, vfs, path)?;
+    Ok(match middleware {
+        Middleware::Dir | Middleware::Project => None,
+        _ => Some(init_path),
+    })
+}
+
 /// Gets the appropriate middleware for a directory by checking for `init`
-/// files. This uses an intrinsic priority list and for compatibility,
-/// that order should be left unchanged.
+/// files. The init file names from the project are checked first, in the order
+/// they're listed. After that, this uses an intrinsic priority list and for
+/// compatibility, that order should be left unchanged.
 ///
 /// Returns the middleware, the name of the directory, and the path to
 /// the init location.
 fn get_dir_middleware<'path>(
+    init_file_names: &[InitFileName],
     vfs: &Vfs,
     dir_path: &'path Path,
 ) -> anyhow::Result<(Middleware, &'path str, PathBuf)> {
@@ -132,6 +166,16 @@ fn get_dir_middleware<'path>(
         ]
     });
 
+    for init_file_name in init_file_names {
+        let Some(middleware) = init_file_name.dir_middleware() else {
+            continue;
+        };
+        let init_path = dir_path.join(&init_file_name.name);
+        if vfs.metadata(&init_path).with_not_found()?.is_some() {
+            return Ok((middleware, dir_name, init_path));
+        }
+    }
+
     for default_project_name in DEFAULT_PROJECT_NAMES {
         let project_path = dir_path.join(default_project_name);
         if vfs.metadata(&project_path).with_not_found()?.is_some() {
@@ -149,6 +193,44 @@ fn get_dir_middleware<'path>(
     Ok((Middleware::Dir, dir_name, dir_path.to_path_buf()))
 }
 
+/// Gets a snapshot for a directory matched by a user specified sync rule for
+/// directories. The directory is named with the rule's suffix trimmed off and
+/// given the rule's class, instead of being read based on its init file.
+fn snapshot_dir_from_rule(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    path: &Path,
+    rule: &SyncRule,
+) -> anyhow::Result<Option<InstanceSnapshot>> {
+    if rule.middleware == Middleware::Ignore {
+        return Ok(None);
+    }
+
+    let (middleware, _, init_path) = get_dir_middleware(&context.init_file_names, vfs, path)?;
+    if middleware != Middleware::Dir {
+        anyhow::bail!(
+            "{} matches the sync rule '{}', which can't be used for directories \
+            with init files like {}",
+            path.display(),
+            rule.include.as_str(),
+            init_path.display()
+        );
+    }
+
+    let name = rule.file_name_for_path(path)?;
+    let mut snapshot = match snapshot_dir_no_meta(context, vfs, path, name)? {
+        Some(snapshot) => snapshot,
+        None => return Ok(None),
+    };
+    if let Some(class_name) = &rule.class_name {
+        snapshot.class_name = class_name.clone().into();
+    }
+    DirectoryMetadata::read_and_apply_all(vfs, path, &mut snapshot)?;
+    snapshot.metadata.middleware = Some(Middleware::Dir);
+
+    Ok(Some(snapshot))
+}
+
 /// Gets a snapshot for a path given an InstanceContext and Vfs, taking
 /// user specified sync rules into account.
 fn snapshot_from_path(
@@ -179,8 +261,9 @@ fn snapshot_from_path(
 /// item into a Roblox Instance. Missing from this list is metadata.
 /// This is deliberate, as metadata is not a snapshot middleware.
 ///
-/// Directories cannot be used for sync rules so they're ignored by Serde.
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+/// Other than `Dir`, which sync rules for directories use, the directory
+/// middleware are picked based on init files, so they're ignored by Serde.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum Middleware {
     Csv,
@@ -201,8 +284,8 @@ pub enum Middleware {
     Text,
     Yaml,
     Ignore,
+    External,
 
-    #[serde(skip_deserializing)]
     Dir,
     #[serde(skip_deserializing)]
     ServerScriptDir,
@@ -216,6 +299,62 @@ pub enum Middleware {
     CsvDir,
 }
 
+/// Serde functions for an `Option<Middleware>` that, unlike `Middleware`'s own
+/// `Deserialize` implementation, also accept the directory middleware. This is
+/// used for instance metadata, which can have any middleware.
+pub mod any_middleware {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Middleware;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(remote = "Middleware", rename_all = "camelCase")]
+    enum AnyMiddleware {
+        Csv,
+        JsonModel,
+        Json,
+        ServerScript,
+        ClientScript,
+        ModuleScript,
+        PluginScript,
+        LegacyClientScript,
+        LegacyServerScript,
+        RunContextServerScript,
+        RunContextClientScript,
+        Project,
+        Rbxm,
+        Rbxmx,
+        Toml,
+        Text,
+        Yaml,
+        Ignore,
+        External,
+        Dir,
+        ServerScriptDir,
+        ClientScriptDir,
+        PluginScriptDir,
+        ModuleScriptDir,
+        CsvDir,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct Wrapper(#[serde(with = "AnyMiddleware")] Middleware);
+
+    pub fn serialize<S: Serializer>(
+        middleware: &Option<Middleware>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        middleware.map(Wrapper).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Middleware>, D::Error> {
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(middleware)| middleware))
+    }
+}
+
 impl Middleware {
     /// Creates a snapshot for the given path from the Middleware with
     /// the provided name.
@@ -253,6 +392,7 @@ impl Middleware {
             Self::Text => snapshot_txt(context, vfs, path, name),
             Self::Yaml => snapshot_yaml(context, vfs, path, name),
             Self::Ignore => Ok(None),
+            Self::External => snapshot_external(context, vfs, path, name),
 
             Self::Dir => snapshot_dir(context, vfs, path, name),
             Self::ServerScriptDir => {
@@ -290,33 +430,103 @@ impl Middleware {
         match self {
             Middleware::Csv => syncback_csv(snapshot),
             Middleware::JsonModel => syncback_json_model(snapshot),
-            Middleware::Json => anyhow::bail!("cannot syncback Json middleware"),
+            Middleware::Json => syncback_json(snapshot),
             // Projects are only generated from files that already exist on the
             // file system, so we don't need to pass a file name.
             Middleware::Project => syncback_project(snapshot),
-            Middleware::ServerScript => syncback_lua(snapshot),
-            Middleware::ClientScript => syncback_lua(snapshot),
-            Middleware::ModuleScript => syncback_lua(snapshot),
+            Middleware::ServerScript => syncback_lua(ScriptType::Server, snapshot),
+            Middleware::ClientScript => syncback_lua(ScriptType::Client, snapshot),
+            Middleware::ModuleScript => syncback_lua(ScriptType::Module, snapshot),
+            Middleware::PluginScript => syncback_lua(ScriptType::Plugin, snapshot),
+            Middleware::LegacyServerScript => syncback_lua(ScriptType::LegacyServer, snapshot),
+            Middleware::LegacyClientScript => syncback_lua(ScriptType::LegacyClient, snapshot),
+            Middleware::RunContextServerScript => {
+                syncback_lua(ScriptType::RunContextServer, snapshot)
+            }
+            Middleware::RunContextClientScript => {
+                syncback_lua(ScriptType::RunContextClient, snapshot)
+            }
             Middleware::Rbxm => syncback_rbxm(snapshot),
             Middleware::Rbxmx => syncback_rbxmx(snapshot),
-            Middleware::Toml => anyhow::bail!("cannot syncback Toml middleware"),
+            Middleware::Toml => syncback_toml(snapshot),
             Middleware::Text => syncback_txt(snapshot),
-            Middleware::Yaml => anyhow::bail!("cannot syncback Yaml middleware"),
+            Middleware::Yaml => syncback_yaml(snapshot),
             Middleware::Ignore => anyhow::bail!("cannot syncback Ignore middleware"),
+            Middleware::External => syncback_external(snapshot),
             Middleware::Dir => syncback_dir(snapshot),
             Middleware::ServerScriptDir => syncback_lua_init(ScriptType::Server, snapshot),
             Middleware::ClientScriptDir => syncback_lua_init(ScriptType::Client, snapshot),
             Middleware::PluginScriptDir => syncback_lua_init(ScriptType::Plugin, snapshot),
             Middleware::ModuleScriptDir => syncback_lua_init(ScriptType::Module, snapshot),
             Middleware::CsvDir => syncback_csv_init(snapshot),
+        }
+    }
 
-            Middleware::PluginScript
+    /// Runs syncback for only the Instance in the provided SyncbackSnapshot,
+    /// ignoring its children. This is used to persist property changes made
+    /// by clients while serving.
+    ///
+    /// Only middleware whose properties live in a model file, a meta file, or
+    /// the file itself are supported.
+    pub fn syncback_properties(&self, snapshot: &SyncbackSnapshot) -> anyhow::Result<FsSnapshot> {
+        match self {
+            Middleware::Csv
+            | Middleware::JsonModel
+            | Middleware::ServerScript
+            | Middleware::ClientScript
+            | Middleware::ModuleScript
+            | Middleware::PluginScript
             | Middleware::LegacyServerScript
             | Middleware::LegacyClientScript
             | Middleware::RunContextServerScript
-            | Middleware::RunContextClientScript => {
-                anyhow::bail!("syncback is not implemented for {self:?} yet")
+            | Middleware::RunContextClientScript
+            | Middleware::Json
+            | Middleware::Toml
+            | Middleware::Yaml
+            | Middleware::Text => Ok(self.syncback(snapshot)?.fs_snapshot),
+            Middleware::Dir => syncback_dir_meta(snapshot, true),
+            Middleware::ServerScriptDir
+            | Middleware::ClientScriptDir
+            | Middleware::PluginScriptDir
+            | Middleware::ModuleScriptDir
+            | Middleware::CsvDir => syncback_dir_meta(snapshot, false),
+            _ => anyhow::bail!("cannot persist property changes for {self:?} middleware"),
+        }
+    }
+
+    /// Returns whether new Instances can be synced back with this middleware.
+    /// Projects and external middleware only sync back files that already
+    /// exist, and ignored files are never written.
+    pub fn can_syncback_new(&self) -> bool {
+        !matches!(
+            self,
+            Middleware::Project | Middleware::Ignore | Middleware::External
+        )
+    }
+
+    /// Returns the classes that Instances synced back with this middleware
+    /// have to be, or `None` if they can be any class.
+    pub fn syncback_classes(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Middleware::Csv | Middleware::CsvDir => Some(&["LocalizationTable"]),
+            Middleware::Text => Some(&["StringValue"]),
+            Middleware::ModuleScript
+            | Middleware::ModuleScriptDir
+            | Middleware::Json
+            | Middleware::Toml
+            | Middleware::Yaml => Some(&["ModuleScript"]),
+            Middleware::ClientScript | Middleware::ClientScriptDir => {
+                Some(&["Script", "LocalScript"])
             }
+            Middleware::LegacyClientScript => Some(&["LocalScript"]),
+            Middleware::ServerScript
+            | Middleware::ServerScriptDir
+            | Middleware::PluginScript
+            | Middleware::PluginScriptDir
+            | Middleware::LegacyServerScript
+            | Middleware::RunContextServerScript
+            | Middleware::RunContextClientScript => Some(&["Script"]),
+            _ => None,
         }
     }
 
@@ -345,7 +555,11 @@ impl Middleware {
     pub fn handles_own_properties(&self) -> bool {
         matches!(
             self,
-            Middleware::JsonModel | Middleware::Project | Middleware::Rbxm | Middleware::Rbxmx
+            Middleware::JsonModel
+                | Middleware::Project
+                | Middleware::Rbxm
+                | Middleware::Rbxmx
+                | Middleware::External
         )
     }
 
@@ -355,6 +569,7 @@ impl Middleware {
     pub fn middleware_for_path(
         vfs: &Vfs,
         sync_rules: &[SyncRule],
+        init_file_names: &[InitFileName],
         path: &Path,
     ) -> anyhow::Result<Option<Self>> {
         let meta = match vfs.metadata(path).with_not_found()? {
@@ -363,11 +578,16 @@ impl Middleware {
         };
 
         if meta.is_dir() {
-            let (middleware, _, _) = get_dir_middleware(vfs, path)?;
+            for rule in sync_rules {
+                if rule.is_dir_rule() && rule.matches_dir(path) {
+                    return Ok(Some(rule.middleware));
+                }
+            }
+            let (middleware, _, _) = get_dir_middleware(init_file_names, vfs, path)?;
             Ok(Some(middleware))
         } else {
             for rule in sync_rules.iter().chain(default_sync_rules()) {
-                if rule.matches(path) {
+                if !rule.is_dir_rule() && rule.matches(path) {
                     return Ok(Some(rule.middleware));
                 }
             }
@@ -390,6 +610,9 @@ macro_rules! sync_rule {
             include: Glob::new($pattern).unwrap(),
             exclude: None,
             suffix: None,
+            class_name: None,
+            command: None,
+            syncback_command: None,
             base_path: PathBuf::new(),
         }
     };
@@ -399,6 +622,9 @@ macro_rules! sync_rule {
             include: Glob::new($pattern).unwrap(),
             exclude: None,
             suffix: Some($suffix.into()),
+            class_name: None,
+            command: None,
+            syncback_command: None,
             base_path: PathBuf::new(),
         }
     };
@@ -408,6 +634,9 @@ macro_rules! sync_rule {
             include: Glob::new($pattern).unwrap(),
             exclude: Some(Glob::new($exclude).unwrap()),
             suffix: Some($suffix.into()),
+            class_name: None,
+            command: None,
+            syncback_command: None,
             base_path: PathBuf::new(),
         }
     };
@@ -430,16 +659,21 @@ pub fn default_sync_rules() -> &'static [SyncRule] {
             sync_rule!("*.{lua,luau}", ModuleScript),
             sync_rule!("*.project.json", Project, ".project.json"),
             sync_rule!("*.project.jsonc", Project, ".project.jsonc"),
+            sync_rule!("*.project.yaml", Project, ".project.yaml"),
+            sync_rule!("*.project.toml", Project, ".project.toml"),
             sync_rule!("*.model.json", JsonModel, ".model.json"),
             sync_rule!("*.model.jsonc", JsonModel, ".model.jsonc"),
+            sync_rule!("*.model.yaml", JsonModel, ".model.yaml"),
+            sync_rule!("*.model.toml", JsonModel, ".model.toml"),
             sync_rule!("*.json", Json, ".json", "*.meta.json"),
             sync_rule!("*.jsonc", Json, ".jsonc", "*.meta.jsonc"),
-            sync_rule!("*.toml", Toml),
+            sync_rule!("*.toml", Toml, ".toml", "*.meta.toml"),
             sync_rule!("*.csv", Csv),
             sync_rule!("*.txt", Text),
             sync_rule!("*.rbxmx", Rbxmx),
             sync_rule!("*.rbxm", Rbxm),
-            sync_rule!("*.{yml,yaml}", Yaml),
+            sync_rule!("*.yml", Yaml),
+            sync_rule!("*.yaml", Yaml, ".yaml", "*.meta.yaml"),
         ]
     })
 }
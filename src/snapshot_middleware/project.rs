@@ -17,7 +17,6 @@ use crate::{
     resolution::UnresolvedValue,
     snapshot::{
         InstanceContext, InstanceMetadata, InstanceSnapshot, InstanceWithMeta, InstigatingSource,
-        PathIgnoreRule, SyncRule,
     },
     snapshot_middleware::Middleware,
     syncback::{filter_properties, FsSnapshot, SyncbackReturn, SyncbackSnapshot},
@@ -33,8 +32,13 @@ pub fn snapshot_project(
     path: &Path,
     name: &str,
 ) -> anyhow::Result<Option<InstanceSnapshot>> {
-    let project = Project::load_exact(vfs, path, Some(name))
+    let mut project = Project::load_exact(vfs, path, Some(name))
         .with_context(|| format!("File was not a valid Rojo project: {}", path.display()))?;
+    if let Some(profile) = &context.profile {
+        if project.profiles.contains_key(profile) {
+            project.apply_profile(profile)?;
+        }
+    }
     let project_name = match project.name.as_deref() {
         Some(name) => name,
         None => panic!("Project is missing a name"),
@@ -43,17 +47,20 @@ pub fn snapshot_project(
     let mut context = context.clone();
     context.clear_sync_rules();
 
-    let rules = project.glob_ignore_paths.iter().map(|glob| PathIgnoreRule {
-        glob: glob.clone(),
-        base_path: project.folder_location().to_path_buf(),
-    });
+    let rules = project.path_ignore_rules();
 
-    let sync_rules = project.sync_rules.iter().map(|rule| SyncRule {
-        base_path: project.folder_location().to_path_buf(),
-        ..rule.clone()
+    // Rules merged in from an extended project already have their base path
+    // set to that project's folder.
+    let sync_rules = project.sync_rules.iter().map(|rule| {
+        let mut rule = rule.clone();
+        if rule.base_path.as_os_str().is_empty() {
+            rule.base_path = project.folder_location().to_path_buf();
+        }
+        rule
     });
 
     context.add_sync_rules(sync_rules);
+    context.set_init_file_names(project.init_file_names.clone());
     context.add_path_ignore_rules(rules);
     context.set_emit_legacy_scripts(
         project
@@ -61,6 +68,10 @@ pub fn snapshot_project(
             .or_else(emit_legacy_scripts_default)
             .unwrap(),
     );
+    context.set_emit_luau_types(project.emit_luau_types.unwrap_or_default());
+    if let Some(defines) = &project.defines {
+        context.add_project_defines(defines);
+    }
 
     match snapshot_project_node(&context, path, project_name, &project.tree, vfs, None)? {
         Some(found_snapshot) => {
@@ -81,6 +92,15 @@ pub fn snapshot_project(
             // We SHOULD NOT mark the project file as a relevant path for any
             // nodes that aren't roots. They'll be updated as part of the project
             // file being updated.
+            //
+            // Any project files this one extends are marked the same way, before
+            // this one since syncback expects the project's own path to be last.
+            snapshot.metadata.relevant_paths.extend(
+                project
+                    .base_file_locations()
+                    .into_iter()
+                    .map(Path::to_path_buf),
+            );
             snapshot.metadata.relevant_paths.push(path.to_path_buf());
 
             Ok(Some(snapshot))
@@ -333,7 +353,9 @@ pub fn syncback_project<'sync>(
     // Sync rules for this project do not have their base rule set but it is
     // important when performing syncback on other projects.
     for rule in &mut project.sync_rules {
-        rule.base_path.clone_from(&base_path)
+        if rule.base_path.as_os_str().is_empty() {
+            rule.base_path.clone_from(&base_path)
+        }
     }
 
     let mut descendant_snapshots = Vec::new();
@@ -386,6 +408,7 @@ pub fn syncback_project<'sync>(
             let middleware = match Middleware::middleware_for_path(
                 snapshot.vfs(),
                 &project.sync_rules,
+                &project.init_file_names,
                 &full_path,
             )? {
                 Some(middleware) => middleware,
@@ -493,9 +516,13 @@ pub fn syncback_project<'sync>(
             // syncback on the project node path above (or is itself a node).
             // So the only things we need to run seperately is new children.
             if old_child_map.remove(name.as_str()).is_none() {
-                let parent_middleware =
-                    Middleware::middleware_for_path(vfs, &project.sync_rules, &parent_path)?
-                        .expect("project nodes should have a middleware if they have children.");
+                let parent_middleware = Middleware::middleware_for_path(
+                    vfs,
+                    &project.sync_rules,
+                    &project.init_file_names,
+                    &parent_path,
+                )?
+                .expect("project nodes should have a middleware if they have children.");
                 // If this node points directly to a project, it may still have
                 // children but they'll be handled by syncback. This isn't a
                 // concern with directories because they're singular things,
@@ -524,7 +551,7 @@ pub fn syncback_project<'sync>(
         }
     }
     if needs_reserialize {
-        fs_snapshot.add_file(project_path, serde_json::to_vec_pretty(&project)?);
+        fs_snapshot.add_file(project_path, project.to_file_contents()?);
     }
 
     Ok(SyncbackReturn {
@@ -534,6 +561,142 @@ pub fn syncback_project<'sync>(
     })
 }
 
+/// Writes the given properties of the Instance in `snapshot` into the node
+/// of the project at `project_path` that created it. The node is found by
+/// following `node_names` down from the root of the project's tree.
+///
+/// Properties that are no longer set on the Instance (or are at their
+/// default) are removed from the node. Other properties on the node are left
+/// untouched. If the node already has every changed value, the project file
+/// isn't rewritten, so that its comments and formatting are kept.
+///
+/// Nodes and properties that come from the active profile or from a project
+/// that this one extends aren't written, since the project file would then
+/// change them for every profile or stop inheriting them.
+pub fn syncback_project_node_properties(
+    snapshot: &SyncbackSnapshot,
+    project_path: &Path,
+    node_names: &[String],
+    changed_properties: impl IntoIterator<Item = Ustr>,
+) -> anyhow::Result<FsSnapshot> {
+    let new_inst = snapshot.new_inst();
+    let changed_properties: Vec<Ustr> = changed_properties.into_iter().collect();
+
+    log::debug!("Reloading project {} from vfs", project_path.display());
+    let mut project = Project::load_exact(snapshot.vfs(), project_path, None)?;
+
+    // Nested projects use the profile too, if they define one with its name.
+    let profile = snapshot.project().profile.as_deref();
+    if let Some(source) = project.node_defined_by(profile, node_names, &changed_properties) {
+        log::warn!(
+            "Not writing the changed properties of {} to {}, because they come from {source}. \
+             Change them there instead.",
+            new_inst.name,
+            project_path.display()
+        );
+        return Ok(FsSnapshot::new());
+    }
+
+    let mut node = &mut project.tree;
+    for name in node_names {
+        node = node.children.get_mut(name).with_context(|| {
+            format!(
+                "project {} has no node for {}",
+                project_path.display(),
+                new_inst.name
+            )
+        })?;
+    }
+
+    let filtered_properties = if node.path.is_some() {
+        snapshot.get_path_filtered_properties(snapshot.new).unwrap()
+    } else {
+        filter_properties(snapshot.project(), new_inst)
+    };
+
+    let mut changed = false;
+    for name in changed_properties {
+        match filtered_properties.get(&name) {
+            Some(Variant::Attributes(attrs)) => {
+                let attributes = node_attributes(attrs);
+                if !attributes_eq(&node.attributes, &attributes) {
+                    node.attributes = attributes;
+                    changed = true;
+                }
+            }
+            Some(&value) => {
+                let unchanged = node.properties.get(&name).is_some_and(|existing| {
+                    existing
+                        .clone()
+                        .resolve(&new_inst.class, &name)
+                        .is_ok_and(|existing| variant_eq(&existing, value))
+                });
+                if !unchanged {
+                    node.properties.insert(
+                        name,
+                        UnresolvedValue::from_variant(value.clone(), &new_inst.class, &name),
+                    );
+                    changed = true;
+                }
+            }
+            None if name == "Attributes" => {
+                changed |= !node.attributes.is_empty();
+                node.attributes.clear();
+            }
+            None => changed |= node.properties.remove(&name).is_some(),
+        }
+    }
+
+    if !changed {
+        log::debug!(
+            "Project {} already has the changed properties of {}",
+            project_path.display(),
+            new_inst.name
+        );
+        return Ok(FsSnapshot::new());
+    }
+
+    Ok(FsSnapshot::new().with_added_file(project_path, project.to_file_contents()?))
+}
+
+/// Returns whether two sets of `$attributes` hold the same values, even if
+/// they're written differently.
+fn attributes_eq(
+    a: &BTreeMap<String, UnresolvedValue>,
+    b: &BTreeMap<String, UnresolvedValue>,
+) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(name, a_value)| {
+            let Some(b_value) = b.get(name) else {
+                return false;
+            };
+            match (
+                a_value.clone().resolve_unambiguous(),
+                b_value.clone().resolve_unambiguous(),
+            ) {
+                (Ok(a_value), Ok(b_value)) => variant_eq(&a_value, &b_value),
+                _ => false,
+            }
+        })
+}
+
+/// Converts the user defined attributes of an Instance into the form used by
+/// the `$attributes` field of project nodes.
+fn node_attributes(attrs: &Attributes) -> BTreeMap<String, UnresolvedValue> {
+    attrs
+        .iter()
+        // We (probably) don't want to preserve internal attributes, only user
+        // defined ones.
+        .filter(|(attr_name, _)| !attr_name.starts_with("RBX"))
+        .map(|(attr_name, attr_value)| {
+            (
+                attr_name.clone(),
+                UnresolvedValue::from_variant_unambiguous(attr_value.clone()),
+            )
+        })
+        .collect()
+}
+
 /// Syncs properties from the new instance into the project node.
 /// Returns `true` if any stale properties were removed (i.e. properties
 /// that existed in the project node but are now at their engine default).
@@ -547,19 +710,7 @@ fn project_node_property_syncback(
     let mut attributes = BTreeMap::new();
     for (&name, &value) in &filtered_properties {
         match value {
-            Variant::Attributes(attrs) => {
-                for (attr_name, attr_value) in attrs.iter() {
-                    // We (probably) don't want to preserve internal attributes,
-                    // only user defined ones.
-                    if attr_name.starts_with("RBX") {
-                        continue;
-                    }
-                    attributes.insert(
-                        attr_name.clone(),
-                        UnresolvedValue::from_variant_unambiguous(attr_value.clone()),
-                    );
-                }
-            }
+            Variant::Attributes(attrs) => attributes = node_attributes(attrs),
             _ => {
                 properties.insert(
                     name,
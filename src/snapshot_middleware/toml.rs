@@ -5,11 +5,17 @@ use memofs::Vfs;
 use rbx_dom_weak::ustr;
 
 use crate::{
-    lua_ast::{Expression, Statement},
+    lua_ast::{parse_return, Expression},
     snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    syncback::{SyncbackReturn, SyncbackSnapshot},
 };
 
-use super::meta_file::AdjacentMetadata;
+use super::{
+    lua::{script_source, syncback_script_file},
+    meta_file::AdjacentMetadata,
+    util::data_module_source,
+    ScriptType,
+};
 
 pub fn snapshot_toml(
     context: &InstanceContext,
@@ -22,7 +28,7 @@ pub fn snapshot_toml(
     let value: toml::Value = toml::from_slice(&contents)
         .with_context(|| format!("File contains malformed TOML: {}", path.display()))?;
 
-    let as_lua = toml_to_lua(value).to_string();
+    let as_lua = data_module_source(context, name, toml_to_lua_value(value));
 
     let mut snapshot = InstanceSnapshot::new()
         .name(name)
@@ -40,10 +46,6 @@ pub fn snapshot_toml(
     Ok(Some(snapshot))
 }
 
-fn toml_to_lua(value: toml::Value) -> Statement {
-    Statement::Return(toml_to_lua_value(value))
-}
-
 fn toml_to_lua_value(value: toml::Value) -> Expression {
     use toml::Value;
 
@@ -65,6 +67,60 @@ fn toml_to_lua_value(value: toml::Value) -> Expression {
     }
 }
 
+pub fn syncback_toml<'sync>(
+    snapshot: &SyncbackSnapshot<'sync>,
+) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let source = script_source(snapshot.new_inst())?;
+    let value = parse_return(source)
+        .context("the ModuleScript does not only return a literal value")
+        .and_then(lua_to_toml_value)?;
+
+    // TOML documents have to be tables, but an empty table can't be told
+    // apart from an empty array once it's been turned into Lua.
+    let value = match value {
+        toml::Value::Table(_) => value,
+        toml::Value::Array(values) if values.is_empty() => toml::Value::Table(Default::default()),
+        _ => anyhow::bail!("TOML files must contain a table"),
+    };
+
+    let contents = toml::to_string_pretty(&value).context("cannot serialize TOML")?;
+
+    syncback_script_file(ScriptType::Module, snapshot, contents.into_bytes())
+}
+
+fn lua_to_toml_value(value: Expression) -> anyhow::Result<toml::Value> {
+    use toml::Value;
+
+    Ok(match value {
+        Expression::Nil => anyhow::bail!("TOML cannot represent nil"),
+        Expression::Bool(value) => Value::Boolean(value),
+        Expression::Number(value) => {
+            if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+                Value::Integer(value as i64)
+            } else {
+                Value::Float(value)
+            }
+        }
+        Expression::String(value) => Value::String(value),
+        Expression::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(lua_to_toml_value)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Expression::Table(table) => Value::Table(
+            table
+                .entries
+                .into_iter()
+                .map(|(key, value)| match key {
+                    Expression::String(key) => Ok((key, lua_to_toml_value(value)?)),
+                    _ => anyhow::bail!("TOML tables can only have string keys"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
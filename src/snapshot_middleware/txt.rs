@@ -1,6 +1,5 @@
 use std::{path::Path, str};
 
-use anyhow::Context as _;
 use memofs::Vfs;
 use rbx_dom_weak::types::Variant;
 use rbx_dom_weak::ustr;
@@ -10,7 +9,7 @@ use crate::{
     syncback::{FsSnapshot, SyncbackReturn, SyncbackSnapshot},
 };
 
-use super::{meta_file::AdjacentMetadata, PathExt as _};
+use super::meta_file::{file_meta_path, meta_to_vec, AdjacentMetadata};
 
 pub fn snapshot_txt(
     context: &InstanceContext,
@@ -57,11 +56,9 @@ pub fn syncback_txt<'sync>(
         meta.properties.shift_remove(&ustr("Value"));
 
         if !meta.is_empty() {
-            let parent = snapshot.path.parent_err()?;
-            fs_snapshot.add_file(
-                parent.join(format!("{}.meta.json", new_inst.name)),
-                serde_json::to_vec_pretty(&meta).context("could not serialize metadata")?,
-            );
+            let meta_path = file_meta_path(snapshot.vfs(), &snapshot.path, &new_inst.name)?;
+            let contents = meta_to_vec(&meta_path, &meta)?;
+            fs_snapshot.add_file(meta_path, contents);
         }
     }
 
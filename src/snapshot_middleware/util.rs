@@ -2,6 +2,11 @@ use std::path::Path;
 
 use anyhow::Context;
 
+use crate::{
+    lua_ast::{type_name_for, Expression, Statement, Type},
+    snapshot::InstanceContext,
+};
+
 /// If the given string ends up with the given suffix, returns the portion of
 /// the string before the suffix.
 pub fn match_trailing<'a>(input: &'a str, suffix: &str) -> Option<&'a str> {
@@ -49,6 +54,20 @@ where
     }
 }
 
+/// Generates the Source of a ModuleScript that returns the given value, as
+/// used for data files like JSON. If the context asks for it, an exported
+/// type named after the Instance is inferred from the value and the returned
+/// value is asserted to be that type.
+pub fn data_module_source(context: &InstanceContext, name: &str, value: Expression) -> String {
+    if context.emit_luau_types {
+        let type_name = type_name_for(name);
+        let declaration = Statement::ExportType(type_name.clone(), Type::infer(&value));
+        format!("{declaration}\n\n{}", Statement::ReturnAs(value, type_name))
+    } else {
+        Statement::Return(value).to_string()
+    }
+}
+
 // TEMP function until rojo 8.0, when it can be replaced with bool::default (aka false)
 pub fn emit_legacy_scripts_default() -> Option<bool> {
     Some(true)
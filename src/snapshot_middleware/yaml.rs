@@ -3,14 +3,20 @@ use std::path::Path;
 use anyhow::Context as _;
 use memofs::Vfs;
 use rbx_dom_weak::ustr;
-use yaml_rust2::{Yaml, YamlLoader};
+use yaml_rust2::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
 
 use crate::{
-    lua_ast::{Expression, Statement},
+    lua_ast::{parse_return, Expression},
     snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot},
+    syncback::{SyncbackReturn, SyncbackSnapshot},
 };
 
-use super::meta_file::AdjacentMetadata;
+use super::{
+    lua::{script_source, syncback_script_file},
+    meta_file::AdjacentMetadata,
+    util::data_module_source,
+    ScriptType,
+};
 
 pub fn snapshot_yaml(
     context: &InstanceContext,
@@ -28,12 +34,12 @@ pub fn snapshot_yaml(
         anyhow::bail!("Rojo does not currently support multiple documents in a YAML file")
     }
 
-    let as_lua = Statement::Return(yaml_to_luau(value)?);
+    let as_lua = data_module_source(context, name, yaml_to_luau(value)?);
 
     let mut snapshot = InstanceSnapshot::new()
         .name(name)
         .class_name("ModuleScript")
-        .property(ustr("Source"), as_lua.to_string())
+        .property(ustr("Source"), as_lua)
         .metadata(
             InstanceMetadata::new()
                 .instigating_source(path)
@@ -91,6 +97,50 @@ fn yaml_to_luau(value: Yaml) -> anyhow::Result<Expression> {
     })
 }
 
+pub fn syncback_yaml<'sync>(
+    snapshot: &SyncbackSnapshot<'sync>,
+) -> anyhow::Result<SyncbackReturn<'sync>> {
+    let source = script_source(snapshot.new_inst())?;
+    let value =
+        parse_return(source).context("the ModuleScript does not only return a literal value")?;
+
+    let mut contents = String::new();
+    YamlEmitter::new(&mut contents)
+        .dump(&luau_to_yaml(value))
+        .context("cannot serialize YAML")?;
+    contents.push('\n');
+
+    syncback_script_file(ScriptType::Module, snapshot, contents.into_bytes())
+}
+
+fn luau_to_yaml(value: Expression) -> Yaml {
+    match value {
+        Expression::Nil => Yaml::Null,
+        Expression::Bool(bool) => Yaml::Boolean(bool),
+        Expression::Number(number) => {
+            if number.fract() == 0.0 && number.abs() < (1u64 << 53) as f64 {
+                Yaml::Integer(number as i64)
+            } else if number.is_nan() {
+                Yaml::Real(".nan".to_owned())
+            } else if number.is_infinite() {
+                let sign = if number.is_sign_negative() { "-" } else { "" };
+                Yaml::Real(format!("{sign}.inf"))
+            } else {
+                Yaml::Real(number.to_string())
+            }
+        }
+        Expression::String(str) => Yaml::String(str),
+        Expression::Array(values) => Yaml::Array(values.into_iter().map(luau_to_yaml).collect()),
+        Expression::Table(table) => Yaml::Hash(
+            table
+                .entries
+                .into_iter()
+                .map(|(key, value)| (luau_to_yaml(key), luau_to_yaml(value)))
+                .collect::<Hash>(),
+        ),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,489 @@
+//! Stripping comments, blank lines, and unneeded whitespace from Lua sources
+//! when they're built, which makes places smaller and shipped code harder to
+//! read.
+//!
+//! Lines of code are never joined together, since that can change what a
+//! Luau program means, so every line in a stripped source came from exactly
+//! one line in the original. The line each one came from is kept so that
+//! stack traces can be translated back.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How the sources of scripts are stripped when building.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum StripSources {
+    /// Sources are left as they are.
+    None,
+    /// Comments, blank lines, and trailing whitespace are removed.
+    Comments,
+    /// Comments and blank lines are removed, along with indentation and any
+    /// spaces that aren't needed to separate tokens.
+    Minify,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct StrippedSource {
+    pub source: String,
+    /// The line in the original source that each line of the stripped source
+    /// came from, starting from 1.
+    pub lines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    /// An identifier, keyword, or number.
+    Word,
+    /// A string, including long strings and interpolated strings.
+    String,
+    Comment,
+    /// A comment like `--!strict` that changes how the script is run, which
+    /// is kept like code is.
+    Directive,
+    Space,
+    Newline,
+    /// Any other character.
+    Symbol,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    line: usize,
+}
+
+/// Strips the given source. Sources that can't be tokenized, like ones with
+/// unfinished strings, are stripped as far as they can be and the rest is
+/// kept as it is.
+pub fn strip_source(source: &str, mode: StripSources) -> StrippedSource {
+    let tokens = tokenize(source);
+    let mut output = Output::default();
+
+    if mode == StripSources::None {
+        output.push_line(&tokens, |tokens, output| {
+            for token in tokens {
+                output.push(token);
+            }
+        });
+        return output.finish();
+    }
+
+    for line in split_lines(&tokens) {
+        let has_code = line
+            .iter()
+            .any(|token| !matches!(token.kind, TokenKind::Space | TokenKind::Comment));
+        if !has_code {
+            continue;
+        }
+
+        output.push_line(line, |tokens, output| match mode {
+            StripSources::Minify => push_minified(tokens, output),
+            _ => push_without_comments(tokens, output),
+        });
+    }
+
+    output.finish()
+}
+
+/// Splits tokens into lines of code. Comments that span several lines end the
+/// line they start on, like a newline would.
+fn split_lines<'a, 'b>(tokens: &'b [Token<'a>]) -> Vec<&'b [Token<'a>]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let ends_line = match token.kind {
+            TokenKind::Newline => true,
+            TokenKind::Comment => token.text.contains('\n'),
+            _ => false,
+        };
+        if ends_line {
+            lines.push(&tokens[start..index]);
+            start = index + 1;
+        }
+    }
+    lines.push(&tokens[start..]);
+
+    lines
+}
+
+fn push_without_comments<'a>(tokens: &[Token<'a>], output: &mut Output) {
+    let end = tokens
+        .iter()
+        .rposition(|token| !matches!(token.kind, TokenKind::Space | TokenKind::Comment))
+        .map_or(0, |index| index + 1);
+
+    for token in &tokens[..end] {
+        match token.kind {
+            // Comments in the middle of a line could be all that separates
+            // two tokens.
+            TokenKind::Comment => output.push_str(" "),
+            _ => output.push(token),
+        }
+    }
+}
+
+fn push_minified<'a>(tokens: &[Token<'a>], output: &mut Output) {
+    let mut previous: Option<&Token<'a>> = None;
+    let mut separated = false;
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Space | TokenKind::Comment => separated = true,
+            _ => {
+                if let Some(previous) = previous {
+                    if separated && needs_space(previous.text, token.text) {
+                        output.push_str(" ");
+                    }
+                }
+                output.push(token);
+                previous = Some(token);
+                separated = false;
+            }
+        }
+    }
+}
+
+/// Returns whether two tokens that were separated by whitespace need to stay
+/// separated so that they aren't read as different tokens.
+fn needs_space(previous: &str, next: &str) -> bool {
+    let (Some(last), Some(first)) = (previous.chars().last(), next.chars().next()) else {
+        return false;
+    };
+    let is_word = |char: char| char.is_alphanumeric() || char == '_';
+    // Numbers can start with a `.`, like `.5`.
+    let starts_number = previous.starts_with(|char: char| char.is_ascii_digit() || char == '.');
+
+    // Symbols that would be read as one token, or as the start of a comment
+    // or long string, if they were put together.
+    let joins_symbols = matches!(
+        (last, first),
+        ('-', '-' | '>' | '=')
+            | ('.', '.' | '=')
+            | ('=' | '<' | '>' | '~' | '+' | '*' | '/' | '%' | '^', '=')
+            | (':', ':')
+            | ('[', '[' | '=')
+            | ('/', '/')
+    );
+
+    (is_word(last) && is_word(first))
+        || joins_symbols
+        || (starts_number && first == '.')
+        || (last == '.' && first.is_ascii_digit())
+}
+
+/// Builds a stripped source along with the line each of its lines came from.
+#[derive(Default)]
+struct Output {
+    source: String,
+    lines: Vec<usize>,
+    /// The original line that the next character pushed comes from.
+    line: usize,
+}
+
+impl Output {
+    fn push_line<'a, F>(&mut self, tokens: &[Token<'a>], push: F)
+    where
+        F: FnOnce(&[Token<'a>], &mut Output),
+    {
+        let Some(first) = tokens.first() else {
+            return;
+        };
+        if !self.lines.is_empty() {
+            self.source.push('\n');
+        }
+        self.line = first.line;
+        self.lines.push(first.line);
+        push(tokens, self);
+    }
+
+    fn push(&mut self, token: &Token) {
+        self.line = token.line;
+        for char in token.text.chars() {
+            self.source.push(char);
+            if char == '\n' {
+                self.line += 1;
+                self.lines.push(self.line);
+            }
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        self.source.push_str(text);
+    }
+
+    fn finish(self) -> StrippedSource {
+        StrippedSource {
+            source: self.source,
+            lines: self.lines,
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    let mut line = 1;
+
+    while index < bytes.len() {
+        let start = index;
+        let kind = match bytes[index] {
+            b'\n' => {
+                index += 1;
+                TokenKind::Newline
+            }
+            b' ' | b'\t' | b'\r' => {
+                while index < bytes.len() && matches!(bytes[index], b' ' | b'\t' | b'\r') {
+                    index += 1;
+                }
+                TokenKind::Space
+            }
+            b'-' if bytes.get(index + 1) == Some(&b'-') => {
+                match long_bracket_level(bytes, index + 2) {
+                    Some(level) => {
+                        index = skip_long_bracket(bytes, index + 2, level);
+                        TokenKind::Comment
+                    }
+                    None => {
+                        let is_directive = bytes.get(index + 2) == Some(&b'!');
+                        index = find_byte(bytes, index, b'\n');
+                        if is_directive {
+                            TokenKind::Directive
+                        } else {
+                            TokenKind::Comment
+                        }
+                    }
+                }
+            }
+            b'[' if long_bracket_level(bytes, index).is_some() => {
+                let level = long_bracket_level(bytes, index).unwrap();
+                index = skip_long_bracket(bytes, index, level);
+                TokenKind::String
+            }
+            b'"' | b'\'' => {
+                index = skip_quoted(bytes, index);
+                TokenKind::String
+            }
+            b'`' => {
+                index = skip_interpolated(bytes, index);
+                TokenKind::String
+            }
+            b'.' if bytes.get(index + 1).is_some_and(u8::is_ascii_digit) => {
+                index = skip_number(bytes, index);
+                TokenKind::Word
+            }
+            byte if byte.is_ascii_digit() => {
+                index = skip_number(bytes, index);
+                TokenKind::Word
+            }
+            byte if is_word_byte(byte) => {
+                while index < bytes.len() && is_word_byte(bytes[index]) {
+                    index += 1;
+                }
+                TokenKind::Word
+            }
+            _ => {
+                index += source[index..].chars().next().unwrap().len_utf8();
+                TokenKind::Symbol
+            }
+        };
+
+        let text = &source[start..index];
+        tokens.push(Token { kind, text, line });
+        line += text.matches('\n').count();
+    }
+
+    tokens
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+fn find_byte(bytes: &[u8], start: usize, byte: u8) -> usize {
+    bytes[start..]
+        .iter()
+        .position(|&other| other == byte)
+        .map_or(bytes.len(), |offset| start + offset)
+}
+
+/// Returns the number of `=` in the long bracket that starts at `start`, like
+/// `[==[`, if there is one.
+fn long_bracket_level(bytes: &[u8], start: usize) -> Option<usize> {
+    if bytes.get(start) != Some(&b'[') {
+        return None;
+    }
+    let level = bytes[start + 1..]
+        .iter()
+        .take_while(|&&byte| byte == b'=')
+        .count();
+
+    (bytes.get(start + 1 + level) == Some(&b'[')).then_some(level)
+}
+
+/// Returns the index just past the end of the long bracket at `start`.
+fn skip_long_bracket(bytes: &[u8], start: usize, level: usize) -> usize {
+    let mut index = start + level + 2;
+    while index < bytes.len() {
+        if bytes[index] == b']'
+            && bytes[index + 1..]
+                .iter()
+                .take(level)
+                .all(|&byte| byte == b'=')
+            && bytes.get(index + 1 + level) == Some(&b']')
+        {
+            return index + level + 2;
+        }
+        index += 1;
+    }
+
+    bytes.len()
+}
+
+fn skip_quoted(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            // `\z` skips the whitespace after it, including newlines.
+            b'\\' if bytes.get(index + 1) == Some(&b'z') => {
+                index += 2;
+                while index < bytes.len() && bytes[index].is_ascii_whitespace() {
+                    index += 1;
+                }
+            }
+            b'\\' => index += 2,
+            b'\n' => return index,
+            byte if byte == quote => return index + 1,
+            _ => index += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+fn skip_interpolated(bytes: &[u8], start: usize) -> usize {
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'`' => return index + 1,
+            b'{' => index = skip_expression(bytes, index + 1),
+            _ => index += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// Returns the index just past the `}` that ends the expression in an
+/// interpolated string, which starts at `start`.
+fn skip_expression(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut index = start;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'{' => {
+                depth += 1;
+                index += 1;
+            }
+            b'}' if depth == 0 => return index + 1,
+            b'}' => {
+                depth -= 1;
+                index += 1;
+            }
+            b'"' | b'\'' => index = skip_quoted(bytes, index),
+            b'`' => index = skip_interpolated(bytes, index),
+            b'[' if long_bracket_level(bytes, index).is_some() => {
+                let level = long_bracket_level(bytes, index).unwrap();
+                index = skip_long_bracket(bytes, index, level);
+            }
+            _ => index += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+fn skip_number(bytes: &[u8], start: usize) -> usize {
+    let is_hex = bytes[start..].starts_with(b"0x") || bytes[start..].starts_with(b"0X");
+    let mut index = start;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'e' | b'E' if !is_hex => {
+                index += 1;
+                if matches!(bytes.get(index), Some(b'+' | b'-')) {
+                    index += 1;
+                }
+            }
+            byte if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.' => index += 1,
+            _ => break,
+        }
+    }
+
+    index
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "\
+--!strict
+-- A module that does things.
+local Module = {}
+
+--[[
+    Adds two numbers.
+]]
+function Module.add(a: number, b: number): number
+    return a + b -- the sum
+end
+
+local text = [[
+-- not a comment
+]]
+local escaped = \"--\\\" not a comment\"
+local interpolated = `{Module.add(1, 2)} -- {\"}\"}`
+print(1 .. 2, a - -b, x--[[inline]]y)
+
+return Module
+";
+
+    #[test]
+    fn strip_comments() {
+        let stripped = strip_source(SOURCE, StripSources::Comments);
+
+        insta::assert_snapshot!(stripped.source);
+        insta::assert_debug_snapshot!(stripped.lines);
+    }
+
+    #[test]
+    fn minify() {
+        let stripped = strip_source(SOURCE, StripSources::Minify);
+
+        insta::assert_snapshot!(stripped.source);
+        insta::assert_debug_snapshot!(stripped.lines);
+    }
+
+    #[test]
+    fn none_keeps_source() {
+        let stripped = strip_source(SOURCE, StripSources::None);
+
+        assert_eq!(stripped.source, SOURCE);
+        assert_eq!(
+            stripped.lines,
+            (1..=SOURCE.lines().count() + 1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let stripped = strip_source("local x = 1e-3 + 0x1F + .5 .. 2", StripSources::Minify);
+
+        assert_eq!(stripped.source, "local x=1e-3+0x1F+.5 .. 2");
+    }
+}
@@ -0,0 +1,491 @@
+//! A cache of the project's tree that lets syncback skip reading and hashing
+//! files that haven't changed since the last time it was run.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+use blake3::Hash;
+use memofs::Vfs;
+use rayon::prelude::*;
+use rbx_dom_weak::{
+    types::{Ref, Variant},
+    InstanceBuilder, Ustr, UstrMap, WeakDom,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change_processor::{compute_and_apply_changes, ids_affected_by_path},
+    snapshot::{InstanceMetadata, RojoTree},
+    Project,
+};
+
+use super::descendants;
+
+/// The name of the file the cache is stored in, inside of the project's folder.
+pub const CACHE_FILE_NAME: &str = ".rojo-syncback-cache";
+
+/// The version of the cache's format. Caches with a different version are
+/// ignored rather than read.
+const CACHE_VERSION: u32 = 2;
+
+/// A snapshot of a project's `RojoTree` along with the hash of each of its
+/// Instances, keyed by the modification time of every file the tree was built
+/// from.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncbackCache {
+    version: u32,
+    rojo_version: String,
+
+    /// When the files in the tree started being read. Files that were modified
+    /// in the same second or later are always treated as changed, since it's
+    /// not possible to tell if they changed before or after being read.
+    read_at: SystemTime,
+
+    /// Every path relevant to the tree and when it was last modified, or
+    /// `None` if it didn't exist.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+
+    /// Every project file relevant to the tree and the values that were
+    /// substituted for its variables. These can change with the environment
+    /// even when the file itself doesn't.
+    substitutions: Vec<(PathBuf, Vec<String>)>,
+
+    /// Every Instance in the tree, ordered so that parents always come before
+    /// their children.
+    instances: Vec<CachedInstance>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedInstance {
+    referent: Ref,
+    parent: Ref,
+    name: String,
+    class: Ustr,
+    properties: UstrMap<Variant>,
+    metadata: InstanceMetadata,
+    hash: Option<[u8; 32]>,
+}
+
+impl SyncbackCache {
+    /// Returns the path that the cache for `project` is stored at.
+    pub fn path_for(project: &Project) -> PathBuf {
+        project.folder_location().join(CACHE_FILE_NAME)
+    }
+
+    /// Creates a cache of `tree` and the `hashes` of its Instances. `read_at`
+    /// should be the time `tree` started being built at.
+    pub fn new(
+        vfs: &Vfs,
+        tree: &RojoTree,
+        hashes: &HashMap<Ref, Hash>,
+        read_at: SystemTime,
+    ) -> Self {
+        let dom = tree.inner();
+        let order = descendants(dom, dom.root_ref());
+
+        let mut paths = HashSet::new();
+        let mut instances = Vec::with_capacity(order.len());
+        for referent in order {
+            let inst = tree.get_instance(referent).unwrap();
+            let metadata = inst.metadata();
+            paths.extend(metadata.relevant_paths.iter().map(PathBuf::as_path));
+
+            instances.push(CachedInstance {
+                referent,
+                parent: inst.parent(),
+                name: inst.name().to_owned(),
+                class: inst.class_name(),
+                properties: inst.properties().clone(),
+                metadata: metadata.clone(),
+                hash: hashes.get(&referent).map(|hash| *hash.as_bytes()),
+            });
+        }
+
+        let substitutions = paths
+            .iter()
+            .filter(|path| Project::is_project_file(path))
+            .filter_map(|path| Some((path.to_path_buf(), substituted_values(vfs, path)?)))
+            .collect();
+
+        let files = paths
+            .into_par_iter()
+            .map(|path| (path.to_path_buf(), modified_time(path)))
+            .collect();
+
+        Self {
+            version: CACHE_VERSION,
+            rojo_version: env!("CARGO_PKG_VERSION").to_owned(),
+            read_at,
+            files,
+            substitutions,
+            instances,
+        }
+    }
+
+    /// Reads the cache stored at `path`. Returns `None` if there's no cache
+    /// there or it was written by a different version of Rojo.
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        let contents = match fs_err::read(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut deserializer =
+            rmp_serde::Deserializer::new(contents.as_slice()).with_human_readable();
+        let cache = Self::deserialize(&mut deserializer)
+            .with_context(|| format!("Could not read syncback cache {}", path.display()))?;
+
+        if cache.version != CACHE_VERSION || cache.rojo_version != env!("CARGO_PKG_VERSION") {
+            log::debug!("Ignoring syncback cache from a different version of Rojo");
+            return Ok(None);
+        }
+
+        Ok(Some(cache))
+    }
+
+    /// Writes the cache to `path`.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut serialized = Vec::new();
+        let mut serializer = rmp_serde::Serializer::new(&mut serialized)
+            .with_human_readable()
+            .with_struct_map();
+        self.serialize(&mut serializer)?;
+
+        fs_err::write(path, serialized)?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the tree for `project` from the cache, snapshotting any files
+    /// that have changed since it was written again.
+    ///
+    /// Along with the tree, this returns the hashes of every Instance whose
+    /// subtree is unchanged. If the project file itself has changed, the cache
+    /// can't be used and this returns `None`.
+    pub fn restore(
+        self,
+        vfs: &Vfs,
+        project: &Project,
+    ) -> anyhow::Result<Option<(RojoTree, HashMap<Ref, Hash>)>> {
+        let read_at = self.read_at;
+        let mut changed: Vec<PathBuf> = self
+            .files
+            .into_par_iter()
+            .filter(|(path, modified)| !is_unchanged(path, *modified, read_at))
+            .map(|(path, _)| path)
+            .collect();
+
+        for (path, values) in self.substitutions {
+            if changed.contains(&path) {
+                continue;
+            }
+
+            let current = if path == project.file_location {
+                Some(
+                    project
+                        .substituted_values()
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect(),
+                )
+            } else {
+                substituted_values(vfs, &path)
+            };
+            if current.as_ref() != Some(&values) {
+                log::debug!("Variables in {} have changed", path.display());
+                changed.push(path);
+            }
+        }
+
+        if changed.contains(&project.file_location) {
+            log::debug!("Ignoring syncback cache because the project file has changed");
+            return Ok(None);
+        }
+
+        let mut metadata = HashMap::with_capacity(self.instances.len());
+        let mut hashes = HashMap::with_capacity(self.instances.len());
+        let mut instances = self.instances.into_iter();
+
+        let root = instances.next().context("Syncback cache is empty")?;
+        let mut dom = WeakDom::new(root.into_builder(&mut metadata, &mut hashes));
+        for inst in instances {
+            let parent = inst.parent;
+            if dom.get_by_ref(parent).is_none() {
+                bail!("Syncback cache is corrupt: an Instance was listed before its parent");
+            }
+            dom.insert(parent, inst.into_builder(&mut metadata, &mut hashes));
+        }
+
+        let mut tree = RojoTree::from_parts(dom, metadata);
+
+        log::debug!(
+            "{} files have changed since the syncback cache was written",
+            changed.len()
+        );
+        for path in changed {
+            for id in ids_affected_by_path(&tree, &path) {
+                // An earlier change may have already removed this Instance.
+                if tree.get_instance(id).is_none() {
+                    continue;
+                }
+
+                // Hashes include descendants, so the hashes of every ancestor
+                // are out of date too.
+                let mut ancestor = id;
+                while let Some(inst) = tree.get_instance(ancestor) {
+                    hashes.remove(&ancestor);
+                    ancestor = inst.parent();
+                }
+
+                compute_and_apply_changes(&mut tree, vfs, id);
+
+                if tree.get_instance(id).is_some() {
+                    for descendant in tree.descendants(id) {
+                        hashes.remove(&descendant.id());
+                    }
+                }
+            }
+        }
+
+        Ok(Some((tree, hashes)))
+    }
+}
+
+impl CachedInstance {
+    fn into_builder(
+        self,
+        metadata: &mut HashMap<Ref, InstanceMetadata>,
+        hashes: &mut HashMap<Ref, Hash>,
+    ) -> InstanceBuilder {
+        metadata.insert(self.referent, self.metadata);
+        if let Some(hash) = self.hash {
+            hashes.insert(self.referent, Hash::from(hash));
+        }
+
+        InstanceBuilder::new(self.class)
+            .with_referent(self.referent)
+            .with_name(self.name)
+            .with_properties(self.properties)
+    }
+}
+
+/// Loads the project file at `path` and returns the values substituted for its
+/// variables, or `None` if it can't be loaded.
+fn substituted_values(vfs: &Vfs, path: &Path) -> Option<Vec<String>> {
+    let fallback_name = path.file_stem().and_then(|stem| stem.to_str());
+    let project = Project::load_exact(vfs, path, fallback_name).ok()?;
+
+    Some(
+        project
+            .substituted_values()
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+    )
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Returns whether the file at `path` is unchanged since it was read, given
+/// when it was `modified` at the time.
+fn is_unchanged(path: &Path, modified: Option<SystemTime>, read_at: SystemTime) -> bool {
+    let seconds = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    };
+
+    match (modified_time(path), modified) {
+        (None, None) => true,
+        (Some(current), Some(modified)) => {
+            current == modified && seconds(modified) < seconds(read_at)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{collections::BTreeMap, time::Duration};
+
+    use memofs::StdBackend;
+
+    use crate::{serve_session::ServeSession, syncback::hash_tree};
+
+    fn open(start_path: &Path) -> (Vfs, Project) {
+        let vfs = Vfs::new(StdBackend::new().unwrap());
+        vfs.set_watch_enabled(false);
+        let project = Project::load_initial_project(&vfs, start_path).unwrap();
+
+        (vfs, project)
+    }
+
+    fn ref_named(tree: &RojoTree, name: &str) -> Ref {
+        tree.descendants(tree.get_root_id())
+            .find(|inst| inst.name() == name)
+            .unwrap()
+            .id()
+    }
+
+    #[test]
+    fn restore_unchanged_and_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let start_path = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::write(
+            start_path.join("default.project.json"),
+            r#"{ "name": "test", "tree": { "$path": "src" } }"#,
+        )
+        .unwrap();
+        std::fs::create_dir(start_path.join("src")).unwrap();
+        std::fs::write(start_path.join("src/a.luau"), "return 1").unwrap();
+        std::fs::write(start_path.join("src/b.luau"), "return 2").unwrap();
+
+        let (vfs, project) = open(&start_path);
+        let tree = ServeSession::build_tree(&vfs, &project, &start_path, &BTreeMap::new()).unwrap();
+        let hashes = hash_tree(&project, tree.inner(), tree.get_root_id());
+
+        // Files are only trusted if they were modified before the cache was
+        // read, so the cache has to be from the future for this test.
+        let read_at = SystemTime::now() + Duration::from_secs(60);
+        let cache_path = SyncbackCache::path_for(&project);
+        SyncbackCache::new(&vfs, &tree, &hashes, read_at)
+            .save(&cache_path)
+            .unwrap();
+
+        let (vfs, project) = open(&start_path);
+        let cache = SyncbackCache::load(&cache_path).unwrap().unwrap();
+        let (restored, restored_hashes) = cache.restore(&vfs, &project).unwrap().unwrap();
+        assert_eq!(restored_hashes, hashes);
+        assert_eq!(
+            hash_tree(&project, restored.inner(), restored.get_root_id()),
+            hashes
+        );
+
+        let a_path = start_path.join("src/a.luau");
+        std::fs::write(&a_path, "return 3").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&a_path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+
+        let (vfs, project) = open(&start_path);
+        let cache = SyncbackCache::load(&cache_path).unwrap().unwrap();
+        let (restored, restored_hashes) = cache.restore(&vfs, &project).unwrap().unwrap();
+
+        let a = ref_named(&restored, "a");
+        let b = ref_named(&restored, "b");
+        assert!(!restored_hashes.contains_key(&a));
+        assert!(!restored_hashes.contains_key(&restored.get_root_id()));
+        assert_eq!(restored_hashes.get(&b), hashes.get(&b));
+        assert_eq!(
+            restored
+                .get_instance(a)
+                .unwrap()
+                .properties()
+                .get(&"Source".into()),
+            Some(&Variant::String("return 3".into()))
+        );
+    }
+
+    #[test]
+    fn project_file_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let start_path = std::fs::canonicalize(dir.path()).unwrap();
+        let project_path = start_path.join("default.project.json");
+        std::fs::write(
+            &project_path,
+            r#"{ "name": "test", "tree": { "$className": "Folder" } }"#,
+        )
+        .unwrap();
+
+        let (vfs, project) = open(&start_path);
+        let tree = ServeSession::build_tree(&vfs, &project, &start_path, &BTreeMap::new()).unwrap();
+        let read_at = SystemTime::now() + Duration::from_secs(60);
+        let cache = SyncbackCache::new(&vfs, &tree, &HashMap::new(), read_at);
+
+        std::fs::File::options()
+            .write(true)
+            .open(&project_path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+
+        assert!(cache.restore(&vfs, &project).unwrap().is_none());
+    }
+
+    #[test]
+    fn substitutions_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let start_path = std::fs::canonicalize(dir.path()).unwrap();
+        let project_path = start_path.join("default.project.json");
+        let nested_path = start_path.join("nested.project.json");
+        std::fs::write(
+            &project_path,
+            r#"{
+                "name": "test",
+                "tree": {
+                    "$className": "Folder",
+                    "Nested": { "$path": "nested.project.json" }
+                }
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &nested_path,
+            r#"{
+                "name": "nested",
+                "variables": { "VALUE": "value" },
+                "tree": {
+                    "$className": "StringValue",
+                    "$properties": { "Value": "${VALUE}" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let (vfs, project) = open(&start_path);
+        let tree = ServeSession::build_tree(&vfs, &project, &start_path, &BTreeMap::new()).unwrap();
+        let hashes = hash_tree(&project, tree.inner(), tree.get_root_id());
+        let read_at = SystemTime::now() + Duration::from_secs(60);
+        let cache_with = |path: &Path, values: &[&str]| {
+            let mut cache = SyncbackCache::new(&vfs, &tree, &hashes, read_at);
+            let (_, stored) = cache
+                .substitutions
+                .iter_mut()
+                .find(|(stored_path, _)| stored_path == path)
+                .unwrap();
+            *stored = values.iter().map(|value| value.to_string()).collect();
+            cache
+        };
+
+        let cache = cache_with(&nested_path, &["value"]);
+        let (_, restored_hashes) = cache.restore(&vfs, &project).unwrap().unwrap();
+        assert_eq!(restored_hashes, hashes);
+
+        // Pretend the variables came from an environment variable that has
+        // changed since the cache was written.
+        let cache = cache_with(&nested_path, &["old"]);
+        let (restored, restored_hashes) = cache.restore(&vfs, &project).unwrap().unwrap();
+        assert!(!restored_hashes.contains_key(&ref_named(&restored, "Nested")));
+        assert!(!restored_hashes.contains_key(&restored.get_root_id()));
+
+        let cache = cache_with(&project_path, &["old"]);
+        assert!(cache.restore(&vfs, &project).unwrap().is_none());
+    }
+}
@@ -6,12 +6,30 @@ use std::borrow::Cow;
 use anyhow::Context;
 use rbx_dom_weak::Instance;
 
-use crate::{snapshot::InstanceWithMeta, snapshot_middleware::Middleware};
+use crate::{
+    snapshot::{InitFileName, InstanceWithMeta, SyncRule},
+    snapshot_middleware::{default_sync_rules, Middleware},
+};
 
+/// Returns the file name an Instance should be written to.
+///
+/// Middleware that no default sync rule uses (like `LegacyServerScript`) can
+/// only be read back if the project has a sync rule for them, so the suffix of
+/// the first such rule in `sync_rules` is used for their new files if one
+/// exists.
+///
+/// New directories are given the suffix of the first sync rule for directories
+/// in `sync_rules` that has both a suffix and the Instance's class, so that
+/// they're read back with the same class.
+///
+/// New files can't be given one of the names in `init_file_names`, since they
+/// would be read back as their parent directory instead.
 pub fn name_for_inst<'old>(
     middleware: Middleware,
     new_inst: &Instance,
     old_inst: Option<InstanceWithMeta<'old>>,
+    sync_rules: &[SyncRule],
+    init_file_names: &[InitFileName],
 ) -> anyhow::Result<Cow<'old, str>> {
     if let Some(old_inst) = old_inst {
         if let Some(source) = old_inst.metadata().relevant_paths.first() {
@@ -31,32 +49,97 @@ pub fn name_for_inst<'old>(
         }
     } else {
         Ok(match middleware {
-            Middleware::Dir
-            | Middleware::CsvDir
+            Middleware::Dir => match dir_rule_suffix_for_class(&new_inst.class, sync_rules) {
+                Some(suffix) => Cow::Owned(format!("{}{suffix}", new_inst.name)),
+                None => Cow::Owned(new_inst.name.clone()),
+            },
+            Middleware::CsvDir
             | Middleware::ServerScriptDir
             | Middleware::ClientScriptDir
             | Middleware::PluginScriptDir
             | Middleware::ModuleScriptDir => Cow::Owned(new_inst.name.clone()),
             _ => {
-                let extension = extension_for_middleware(middleware);
                 let name = &new_inst.name;
                 validate_file_name(name).with_context(|| {
                     format!("name '{name}' is not legal to write to the file system")
                 })?;
-                Cow::Owned(format!("{name}.{extension}"))
+                let file_name = match user_suffix_for_middleware(middleware, sync_rules) {
+                    Some(suffix) => format!("{name}{suffix}"),
+                    None if middleware == Middleware::External => anyhow::bail!(
+                        "name '{name}' can't be written with external middleware, since no \
+                        sync rule using it has a suffix"
+                    ),
+                    None => {
+                        let extension = extension_for_middleware(middleware)?;
+                        format!("{name}.{extension}")
+                    }
+                };
+                if init_file_names
+                    .iter()
+                    .any(|init_file_name| init_file_name.name == file_name)
+                {
+                    anyhow::bail!(
+                        "name '{name}' would be written to {file_name}, which the project \
+                        uses as an init file name"
+                    );
+                }
+                Cow::Owned(file_name)
             }
         })
     }
 }
 
+/// Returns the name of the init file for a directory Instance with the given
+/// middleware. The first of `init_file_names` that uses the middleware is
+/// used if there is one, and `default` otherwise.
+pub fn init_name_for_middleware<'a>(
+    middleware: Middleware,
+    init_file_names: &'a [InitFileName],
+    default: &'a str,
+) -> &'a str {
+    init_file_names
+        .iter()
+        .find(|init_file_name| init_file_name.dir_middleware() == Some(middleware))
+        .map_or(default, |init_file_name| init_file_name.name.as_str())
+}
+
+/// Returns the suffix of the first sync rule in `sync_rules` that uses
+/// `middleware`, as long as none of the default sync rules use it.
+fn user_suffix_for_middleware(middleware: Middleware, sync_rules: &[SyncRule]) -> Option<&str> {
+    if default_sync_rules()
+        .iter()
+        .any(|rule| rule.middleware == middleware)
+    {
+        return None;
+    }
+
+    sync_rules
+        .iter()
+        .filter(|rule| rule.middleware == middleware)
+        .find_map(|rule| rule.suffix.as_deref())
+}
+
+/// Returns the suffix of the first sync rule for directories in `sync_rules`
+/// that gives directories the class `class_name`.
+fn dir_rule_suffix_for_class<'a>(class_name: &str, sync_rules: &'a [SyncRule]) -> Option<&'a str> {
+    sync_rules
+        .iter()
+        .filter(|rule| rule.is_dir_rule() && rule.class_name.as_deref() == Some(class_name))
+        .find_map(|rule| rule.suffix.as_deref())
+}
+
 /// Returns the extension a provided piece of middleware is supposed to use.
-pub fn extension_for_middleware(middleware: Middleware) -> &'static str {
-    match middleware {
+pub fn extension_for_middleware(middleware: Middleware) -> anyhow::Result<&'static str> {
+    Ok(match middleware {
         Middleware::Csv => "csv",
         Middleware::JsonModel => "model.json",
         Middleware::Json => "json",
-        Middleware::ServerScript => "server.luau",
-        Middleware::ClientScript => "client.luau",
+        Middleware::ServerScript
+        | Middleware::LegacyServerScript
+        | Middleware::RunContextServerScript => "server.luau",
+        Middleware::ClientScript
+        | Middleware::LegacyClientScript
+        | Middleware::RunContextClientScript => "client.luau",
         Middleware::ModuleScript => "luau",
         Middleware::PluginScript => "plugin.luau",
         Middleware::Project => "project.json",
@@ -66,24 +149,21 @@ pub fn extension_for_middleware(middleware: Middleware) -> &'static str {
         Middleware::Text => "txt",
         Middleware::Yaml => "yml",
 
-        Middleware::LegacyServerScript
-        | Middleware::LegacyClientScript
-        | Middleware::RunContextServerScript
-        | Middleware::RunContextClientScript => {
-            todo!("syncback does not work on the middleware {middleware:?} yet")
-        }
         // These are manually specified and not `_` to guard against future
         // middleware additions missing this function.
-        Middleware::Ignore => unimplemented!("syncback does not work on Ignore middleware"),
+        Middleware::Ignore => anyhow::bail!("syncback does not work on Ignore middleware"),
+        Middleware::External => {
+            anyhow::bail!("external middleware has no extension, only its sync rule's suffix")
+        }
         Middleware::Dir
         | Middleware::CsvDir
         | Middleware::ServerScriptDir
         | Middleware::ClientScriptDir
         | Middleware::PluginScriptDir
         | Middleware::ModuleScriptDir => {
-            unimplemented!("directory middleware requires special treatment")
+            anyhow::bail!("directory middleware has no extension")
         }
-    }
+    })
 }
 
 /// A list of file names that are not valid on Windows.
@@ -21,21 +21,40 @@ use super::{descendants, filter_properties_preallocated};
 /// The hashes **do** include the descendants of the Instances in them,
 /// so they should only be used for comparing subtrees directly.
 pub fn hash_tree(project: &Project, dom: &WeakDom, root_ref: Ref) -> HashMap<Ref, Hash> {
+    let mut map = HashMap::new();
+    hash_tree_incremental(project, dom, root_ref, &mut map);
+
+    map
+}
+
+/// Adds the hash of every `Instance` in the `WeakDom` to `map`, the same as
+/// `hash_tree` would return them.
+///
+/// Instances that are already in `map` aren't hashed again, so `map` must only
+/// contain up-to-date hashes. Because hashes include descendants, this means
+/// whenever an Instance is in `map`, its descendants should be too.
+pub fn hash_tree_incremental(
+    project: &Project,
+    dom: &WeakDom,
+    root_ref: Ref,
+    map: &mut HashMap<Ref, Hash>,
+) {
     let mut order = descendants(dom, root_ref);
-    let mut map: HashMap<Ref, Hash> = HashMap::with_capacity(order.len());
+    map.reserve(order.len().saturating_sub(map.len()));
 
     let mut prop_list = Vec::with_capacity(2);
     let mut child_hashes = Vec::new();
 
     while let Some(referent) = order.pop() {
+        if map.contains_key(&referent) {
+            continue;
+        }
         let inst = dom.get_by_ref(referent).unwrap();
         let mut hasher = hash_inst_filtered(project, inst, &mut prop_list);
-        add_children(inst, &map, &mut child_hashes, &mut hasher);
+        add_children(inst, map, &mut child_hashes, &mut hasher);
 
         map.insert(referent, hasher.finalize());
     }
-
-    map
 }
 
 /// Hashes a single Instance from the provided WeakDom, if it exists.
@@ -1,17 +1,21 @@
+mod cache;
 mod file_names;
 mod fs_snapshot;
 mod hash;
 mod property_filter;
 mod ref_properties;
+mod report;
 mod snapshot;
 
 use anyhow::Context;
+use blake3::Hash;
 use indexmap::IndexMap;
 use memofs::Vfs;
 use rbx_dom_weak::{
     types::{Ref, Variant},
-    ustr, Instance, Ustr, UstrSet, WeakDom,
+    ustr, Instance, InstanceBuilder, Ustr, UstrMap, UstrSet, WeakDom,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -22,17 +26,21 @@ use std::{
 
 use crate::{
     glob::{Glob, IgnorableGlob},
-    snapshot::{InstanceWithMeta, RojoTree},
-    snapshot_middleware::Middleware,
+    snapshot::{InstanceSnapshot, InstanceWithMeta, InstigatingSource, RojoTree},
+    snapshot_middleware::{syncback_project_node_properties, syncback_rule, Middleware},
     syncback::ref_properties::{collect_referents, link_referents},
     Project,
 };
 
-pub use file_names::{extension_for_middleware, name_for_inst, validate_file_name};
+pub use cache::SyncbackCache;
+pub use file_names::{
+    extension_for_middleware, init_name_for_middleware, name_for_inst, validate_file_name,
+};
 pub use fs_snapshot::FsSnapshot;
 pub use hash::*;
 pub use property_filter::{filter_properties, filter_properties_preallocated};
-pub use snapshot::{SyncbackData, SyncbackSnapshot};
+pub use report::{ReportAction, SkipReason, SyncbackReport};
+pub use snapshot::{inst_path, SyncbackData, SyncbackSnapshot};
 
 /// The name of an enviroment variable to use to override the behavior of
 /// syncback on model files.
@@ -48,10 +56,46 @@ const DEBUG_MODEL_FORMAT_VAR: &str = "ROJO_SYNCBACK_DEBUG";
 static GIT_IGNORE_GLOB: OnceLock<Glob> = OnceLock::new();
 
 pub fn syncback_loop(
+    vfs: &Vfs,
+    old_tree: &mut RojoTree,
+    new_tree: WeakDom,
+    project: &Project,
+) -> anyhow::Result<FsSnapshot> {
+    syncback_loop_with_report(vfs, old_tree, new_tree, project, &mut SyncbackReport::new())
+}
+
+/// Performs syncback the same as `syncback_loop`, additionally recording every
+/// file that is written or removed (or skipped because of the project's
+/// syncback rules) into `report`.
+pub fn syncback_loop_with_report(
+    vfs: &Vfs,
+    old_tree: &mut RojoTree,
+    new_tree: WeakDom,
+    project: &Project,
+    report: &mut SyncbackReport,
+) -> anyhow::Result<FsSnapshot> {
+    syncback_loop_incremental(
+        vfs,
+        old_tree,
+        new_tree,
+        project,
+        report,
+        &mut HashMap::new(),
+    )
+}
+
+/// Performs syncback the same as `syncback_loop_with_report`, reusing the
+/// hashes of any Instances in `old_tree` that are already in `old_hashes`.
+///
+/// When this returns, `old_hashes` contains the hash of every Instance in
+/// `old_tree` so that they can be cached for the next time syncback is run.
+pub fn syncback_loop_incremental(
     vfs: &Vfs,
     old_tree: &mut RojoTree,
     mut new_tree: WeakDom,
     project: &Project,
+    report: &mut SyncbackReport,
+    old_hashes: &mut HashMap<Ref, Hash>,
 ) -> anyhow::Result<FsSnapshot> {
     let ignore_patterns = project
         .syncback_rules
@@ -59,70 +103,13 @@ pub fn syncback_loop(
         .map(|rules| rules.compile_globs())
         .transpose()?;
 
-    // TODO: Add a better way to tell if the root of a project is a directory
-    let skip_pruning = if let Some(path) = &project.tree.path {
-        let middleware =
-            Middleware::middleware_for_path(vfs, &project.sync_rules, path.path()).unwrap();
-        if let Some(middleware) = middleware {
-            middleware.is_dir()
-        } else {
-            false
-        }
-    } else {
-        false
-    };
-    if !skip_pruning {
-        // Strip out any objects from the new tree that aren't in the old tree. This
-        // is necessary so that hashing the roots of each tree won't always result
-        // in different hashes. Shout out to Roblox for serializing a bunch of
-        // Services nobody cares about.
-        log::debug!("Pruning new tree");
-        strip_unknown_root_children(&mut new_tree, old_tree);
-    }
+    prune_root_children(vfs, old_tree, &mut new_tree, project);
 
     log::debug!("Collecting referents for new DOM...");
     let deferred_referents = collect_referents(&new_tree);
 
-    // Remove any properties that are manually blocked from syncback via the
-    // project file.
-    log::debug!("Pre-filtering properties on DOMs");
-    for referent in descendants(&new_tree, new_tree.root_ref()) {
-        let new_inst = new_tree.get_by_ref_mut(referent).unwrap();
-        if let Some(filter) = get_property_filter(project, new_inst) {
-            for prop in filter {
-                new_inst.properties.remove(&prop);
-            }
-        }
-    }
-    for referent in descendants(old_tree.inner(), old_tree.get_root_id()) {
-        let mut old_inst_rojo = old_tree.get_instance_mut(referent).unwrap();
-        let old_inst = old_inst_rojo.inner_mut();
-        if let Some(filter) = get_property_filter(project, old_inst) {
-            for prop in filter {
-                old_inst.properties.remove(&prop);
-            }
-        }
-    }
-
-    // Handle removing the current camera.
-    if let Some(syncback_rules) = &project.syncback_rules {
-        if !syncback_rules.sync_current_camera.unwrap_or_default() {
-            log::debug!("Removing CurrentCamera from new DOM");
-            let mut camera_ref = None;
-            for child_ref in new_tree.root().children() {
-                let inst = new_tree.get_by_ref(*child_ref).unwrap();
-                if inst.class == "Workspace" {
-                    camera_ref = inst.properties.get(&ustr("CurrentCamera"));
-                    break;
-                }
-            }
-            if let Some(Variant::Ref(camera_ref)) = camera_ref {
-                if new_tree.get_by_ref(*camera_ref).is_some() {
-                    new_tree.destroy(*camera_ref);
-                }
-            }
-        }
-    }
+    remove_ignored_properties(old_tree, &mut new_tree, project);
+    remove_current_camera(&mut new_tree, project);
 
     let ignore_referents = project
         .syncback_rules
@@ -141,8 +128,17 @@ pub fn syncback_loop(
     // different.
     new_tree.root_mut().name = old_tree.root().name().to_string();
 
-    log::debug!("Hashing project DOM");
-    let old_hashes = hash_tree(project, old_tree.inner(), old_tree.get_root_id());
+    log::debug!(
+        "Hashing project DOM ({} hashes reused from cache)",
+        old_hashes.len()
+    );
+    hash_tree_incremental(
+        project,
+        old_tree.inner(),
+        old_tree.get_root_id(),
+        old_hashes,
+    );
+    let old_hashes = &*old_hashes;
     log::debug!("Hashing file DOM");
     let new_hashes = hash_tree(project, &new_tree, new_tree.root_ref());
 
@@ -183,8 +179,18 @@ pub fn syncback_loop(
             }
         }
 
+        let middleware = get_best_middleware(&snapshot);
+
         if !is_valid_path(&ignore_patterns, project_path, &snapshot.path) {
             log::debug!("Skipping {inst_path} because its path matches ignore pattern");
+            report.push(
+                project_path,
+                ReportAction::Write,
+                &snapshot.path,
+                Some(middleware),
+                &inst_path,
+                Some(SkipReason::IgnorePaths),
+            );
             continue;
         }
         if let Some(syncback_rules) = &project.syncback_rules {
@@ -192,12 +198,33 @@ pub fn syncback_loop(
             for ignored in &syncback_rules.ignore_trees {
                 if inst_path.starts_with(ignored.as_str()) {
                     log::debug!("Tree {inst_path} is blocked by project");
+                    report.push(
+                        project_path,
+                        ReportAction::Write,
+                        &snapshot.path,
+                        Some(middleware),
+                        &inst_path,
+                        Some(SkipReason::IgnoreTrees),
+                    );
                     continue 'syncback;
                 }
             }
         }
 
-        let middleware = get_best_middleware(&snapshot);
+        if middleware == Middleware::External
+            && syncback_rule(&snapshot).is_some_and(|rule| rule.syncback_command.is_none())
+        {
+            log::debug!("Skipping {inst_path} because its sync rule has no syncbackCommand");
+            report.push(
+                project_path,
+                ReportAction::Write,
+                &snapshot.path,
+                Some(middleware),
+                &inst_path,
+                Some(SkipReason::NoSyncbackCommand),
+            );
+            continue;
+        }
 
         log::trace!(
             "Middleware for {inst_path} is {:?} (path is {})",
@@ -205,11 +232,7 @@ pub fn syncback_loop(
             snapshot.path.display()
         );
 
-        if matches!(middleware, Middleware::Json | Middleware::Toml) {
-            log::warn!("Cannot syncback {middleware:?} at {inst_path}, skipping");
-            continue;
-        }
-
+        let mut used_middleware = middleware;
         let syncback = match middleware.syncback(&snapshot) {
             Ok(syncback) => syncback,
             Err(err) if middleware == Middleware::Dir => {
@@ -226,7 +249,7 @@ pub fn syncback_loop(
                 let mut path = snapshot.path.clone();
                 path.set_file_name(format!(
                     "{file_name}.{}",
-                    extension_for_middleware(new_middleware)
+                    extension_for_middleware(new_middleware)?
                 ));
                 let new_snapshot = snapshot.with_new_path(path, snapshot.new, snapshot.old);
                 log::warn!(
@@ -240,7 +263,57 @@ pub fn syncback_loop(
                     // We need to remove the old FS representation if we're
                     // reserializing it as an rbxm.
                     fs_snapshot.remove_dir(&snapshot.path);
+                    report.push(
+                        project_path,
+                        ReportAction::Remove,
+                        &snapshot.path,
+                        Some(middleware),
+                        &inst_path,
+                        None,
+                    );
                 }
+                used_middleware = new_middleware;
+                new_syncback_result?
+            }
+            Err(err)
+                if matches!(
+                    middleware,
+                    Middleware::Json | Middleware::Toml | Middleware::Yaml
+                ) =>
+            {
+                // Data files can only be written if the ModuleScript is still
+                // just a literal, so anything else is kept as a ModuleScript.
+                let new_middleware = Middleware::ModuleScript;
+                let file_name = name_for_inst(
+                    new_middleware,
+                    snapshot.new_inst(),
+                    None,
+                    &project.sync_rules,
+                    &project.init_file_names,
+                )?;
+                let path = snapshot.path.with_file_name(file_name.as_ref());
+                let new_snapshot = snapshot.with_new_path(path, snapshot.new, snapshot.old);
+                log::warn!(
+                    "Could not syncback {inst_path} as {middleware:?} because: {err:#}.\n\
+                    It will instead be synced back as a ModuleScript."
+                );
+                let new_syncback_result = new_middleware
+                    .syncback(&new_snapshot)
+                    .with_context(|| format!("Failed to syncback {inst_path}"));
+                if new_syncback_result.is_ok() && snapshot.old_inst().is_some() {
+                    // The data file would otherwise produce a second
+                    // ModuleScript alongside the new one.
+                    fs_snapshot.remove_file(&snapshot.path);
+                    report.push(
+                        project_path,
+                        ReportAction::Remove,
+                        &snapshot.path,
+                        Some(middleware),
+                        &inst_path,
+                        None,
+                    );
+                }
+                used_middleware = new_middleware;
                 new_syncback_result?
             }
             Err(err) => anyhow::bail!("Failed to syncback {inst_path} because {err}"),
@@ -254,21 +327,46 @@ pub fn syncback_loop(
             'remove: for inst in &syncback.removed_children {
                 let path = inst.metadata().instigating_source.as_ref().unwrap().path();
                 let inst_path = snapshot.get_old_inst_path(inst.id());
+                let inst_middleware = inst.metadata().middleware;
                 if !is_valid_path(&ignore_patterns, project_path, path) {
                     log::debug!(
                         "Skipping removing {} because its matches an ignore pattern",
                         path.display()
                     );
+                    report.push(
+                        project_path,
+                        ReportAction::Remove,
+                        path,
+                        inst_middleware,
+                        &inst_path,
+                        Some(SkipReason::IgnorePaths),
+                    );
                     continue;
                 }
                 if let Some(syncback_rules) = &project.syncback_rules {
                     for ignored in &syncback_rules.ignore_trees {
                         if inst_path.starts_with(ignored.as_str()) {
                             log::debug!("Skipping removing {inst_path} because its path is blocked by project");
+                            report.push(
+                                project_path,
+                                ReportAction::Remove,
+                                path,
+                                inst_middleware,
+                                &inst_path,
+                                Some(SkipReason::IgnoreTrees),
+                            );
                             continue 'remove;
                         }
                     }
                 }
+                report.push(
+                    project_path,
+                    ReportAction::Remove,
+                    path,
+                    inst_middleware,
+                    &inst_path,
+                    None,
+                );
                 if path.is_dir() {
                     fs_snapshot.remove_dir(path)
                 } else {
@@ -279,6 +377,13 @@ pub fn syncback_loop(
 
         // TODO provide replacement snapshots for e.g. two way sync
 
+        report.push_fs_snapshot(
+            project_path,
+            &syncback.fs_snapshot,
+            used_middleware,
+            &inst_path,
+            |path| is_valid_path(&ignore_patterns, project_path, path),
+        );
         fs_snapshot.merge_with_filter(syncback.fs_snapshot, |path| {
             is_valid_path(&ignore_patterns, project_path, path)
         });
@@ -286,9 +391,322 @@ pub fn syncback_loop(
         snapshots.extend(syncback.children);
     }
 
+    // The order Instances are visited in isn't stable, so the report is
+    // sorted to make it easier to compare.
+    report.sort();
+
     Ok(fs_snapshot)
 }
 
+/// Removes everything that syncback ignores from the trees before they're
+/// compared: children of the new root that the project doesn't have, the
+/// properties in `ignoreProperties`, and the CurrentCamera unless
+/// `syncCurrentCamera` is set.
+///
+/// Subtrees matched by `ignoreTrees` and `ignorePaths` are left in, since
+/// they can only be told apart while walking the trees. See [`is_ignored`].
+pub fn prefilter_trees(
+    vfs: &Vfs,
+    old_tree: &mut RojoTree,
+    new_tree: &mut WeakDom,
+    project: &Project,
+) {
+    prune_root_children(vfs, old_tree, new_tree, project);
+    remove_ignored_properties(old_tree, new_tree, project);
+    remove_current_camera(new_tree, project);
+}
+
+/// Strips out any objects from the new tree's root that aren't in the old
+/// tree's root. This is necessary so that hashing the roots of each tree won't
+/// always result in different hashes. Shout out to Roblox for serializing a
+/// bunch of Services nobody cares about.
+fn prune_root_children(vfs: &Vfs, old_tree: &RojoTree, new_tree: &mut WeakDom, project: &Project) {
+    // TODO: Add a better way to tell if the root of a project is a directory
+    let skip_pruning = if let Some(path) = &project.tree.path {
+        let middleware = Middleware::middleware_for_path(
+            vfs,
+            &project.sync_rules,
+            &project.init_file_names,
+            path.path(),
+        )
+        .unwrap();
+        if let Some(middleware) = middleware {
+            middleware.is_dir()
+        } else {
+            false
+        }
+    } else {
+        false
+    };
+    if !skip_pruning {
+        log::debug!("Pruning new tree");
+        strip_unknown_root_children(new_tree, old_tree);
+    }
+}
+
+/// Removes any properties that are manually blocked from syncback via the
+/// project file from both trees.
+fn remove_ignored_properties(old_tree: &mut RojoTree, new_tree: &mut WeakDom, project: &Project) {
+    log::debug!("Pre-filtering properties on DOMs");
+    for referent in descendants(new_tree, new_tree.root_ref()) {
+        let new_inst = new_tree.get_by_ref_mut(referent).unwrap();
+        if let Some(filter) = get_property_filter(project, new_inst) {
+            for prop in filter {
+                new_inst.properties.remove(&prop);
+            }
+        }
+    }
+    for referent in descendants(old_tree.inner(), old_tree.get_root_id()) {
+        let mut old_inst_rojo = old_tree.get_instance_mut(referent).unwrap();
+        let old_inst = old_inst_rojo.inner_mut();
+        if let Some(filter) = get_property_filter(project, old_inst) {
+            for prop in filter {
+                old_inst.properties.remove(&prop);
+            }
+        }
+    }
+}
+
+/// Removes the Workspace's CurrentCamera from the new tree, unless the
+/// project's `syncCurrentCamera` is set.
+fn remove_current_camera(new_tree: &mut WeakDom, project: &Project) {
+    let Some(syncback_rules) = &project.syncback_rules else {
+        return;
+    };
+    if syncback_rules.sync_current_camera.unwrap_or_default() {
+        return;
+    }
+
+    log::debug!("Removing CurrentCamera from new DOM");
+    let mut camera_ref = None;
+    for child_ref in new_tree.root().children() {
+        let inst = new_tree.get_by_ref(*child_ref).unwrap();
+        if inst.class == "Workspace" {
+            camera_ref = inst.properties.get(&ustr("CurrentCamera"));
+            break;
+        }
+    }
+    if let Some(&Variant::Ref(camera_ref)) = camera_ref {
+        if new_tree.get_by_ref(camera_ref).is_some() {
+            new_tree.destroy(camera_ref);
+        }
+    }
+}
+
+/// Returns whether syncback skips the Instance at `inst_path` in the new
+/// tree, whose file would be at `path`, because of the project's
+/// `ignoreTrees` or `ignorePaths`.
+pub fn is_ignored(
+    project: &Project,
+    ignore_patterns: &Option<Vec<IgnorableGlob>>,
+    inst_path: &str,
+    path: &Path,
+) -> bool {
+    if !is_valid_path(ignore_patterns, project.folder_location(), path) {
+        return true;
+    }
+
+    project.syncback_rules.as_ref().is_some_and(|rules| {
+        rules
+            .ignore_trees
+            .iter()
+            .any(|ignored| inst_path.starts_with(ignored.as_str()))
+    })
+}
+
+/// Produces an `FsSnapshot` that creates the Instance described by `snapshot`,
+/// along with all of its descendants, inside of the directory `base_path`.
+///
+/// This is used to write Instances that were created during live sync back to
+/// the file system. Because the Instance doesn't exist in `old_tree` yet, the
+/// middleware for it and its descendants is picked the same way syncback picks
+/// it for new Instances.
+pub fn syncback_new_instance(
+    vfs: &Vfs,
+    old_tree: &RojoTree,
+    snapshot: InstanceSnapshot,
+    base_path: &Path,
+    project: &Project,
+) -> anyhow::Result<FsSnapshot> {
+    let ignore_patterns = project
+        .syncback_rules
+        .as_ref()
+        .map(|rules| rules.compile_globs())
+        .transpose()?;
+
+    // The root of this DOM stands in for the parent of the new Instance so
+    // that paths for logging are relative to it.
+    let mut new_tree = WeakDom::new(InstanceBuilder::new("Folder"));
+    let new_ref = new_tree.insert(new_tree.root_ref(), builder_from_snapshot(snapshot));
+
+    for referent in descendants(&new_tree, new_ref) {
+        let new_inst = new_tree.get_by_ref_mut(referent).unwrap();
+        if let Some(filter) = get_property_filter(project, new_inst) {
+            for prop in filter {
+                new_inst.properties.remove(&prop);
+            }
+        }
+    }
+
+    let project_path = project.folder_location();
+    let parent = SyncbackSnapshot {
+        data: SyncbackData {
+            vfs,
+            old_tree,
+            new_tree: &new_tree,
+            project,
+        },
+        old: None,
+        new: new_tree.root_ref(),
+        path: base_path.to_path_buf(),
+        middleware: None,
+    };
+
+    let mut snapshots = vec![parent.with_base_path(base_path, new_ref, None)?];
+    let mut fs_snapshot = FsSnapshot::new();
+
+    while let Some(snapshot) = snapshots.pop() {
+        let inst_path = snapshot.get_new_inst_path(snapshot.new);
+
+        if !is_valid_path(&ignore_patterns, project_path, &snapshot.path) {
+            log::debug!("Skipping {inst_path} because its path matches ignore pattern");
+            continue;
+        }
+
+        let middleware = get_best_middleware(&snapshot);
+        log::trace!(
+            "Middleware for {inst_path} is {:?} (path is {})",
+            middleware,
+            snapshot.path.display()
+        );
+
+        let syncback = middleware
+            .syncback(&snapshot)
+            .with_context(|| format!("Failed to syncback {inst_path}"))?;
+
+        fs_snapshot.merge_with_filter(syncback.fs_snapshot, |path| {
+            is_valid_path(&ignore_patterns, project_path, path)
+        });
+
+        snapshots.extend(syncback.children);
+    }
+
+    Ok(fs_snapshot)
+}
+
+/// Produces an `FsSnapshot` that persists the properties of the Instance `id`
+/// in `old_tree` once `changed_properties` have been applied to it.
+///
+/// Only the files that hold the Instance's own properties are written: its
+/// model file, meta file, the file itself, or the project node it comes from.
+pub fn syncback_changed_properties(
+    vfs: &Vfs,
+    old_tree: &RojoTree,
+    id: Ref,
+    changed_properties: &UstrMap<Option<Variant>>,
+    project: &Project,
+) -> anyhow::Result<FsSnapshot> {
+    let old_inst = old_tree
+        .get_instance(id)
+        .with_context(|| format!("Instance {id:?} does not exist"))?;
+    let metadata = old_inst.metadata();
+
+    // The root of this DOM stands in for the parent of the Instance so that
+    // paths for logging are relative to it.
+    let mut new_tree = WeakDom::new(InstanceBuilder::new("Folder"));
+    let new_ref = old_tree.inner().clone_into_external(id, &mut new_tree);
+    new_tree.transfer_within(new_ref, new_tree.root_ref());
+
+    let new_inst = new_tree.get_by_ref_mut(new_ref).unwrap();
+    for (key, value) in changed_properties {
+        match value {
+            Some(value) => new_inst.properties.insert(*key, value.clone()),
+            None => new_inst.properties.remove(key),
+        };
+    }
+
+    for referent in descendants(&new_tree, new_ref) {
+        let new_inst = new_tree.get_by_ref_mut(referent).unwrap();
+        if let Some(filter) = get_property_filter(project, new_inst) {
+            for prop in filter {
+                new_inst.properties.remove(&prop);
+            }
+        }
+    }
+
+    let instigating_source = metadata
+        .instigating_source
+        .as_ref()
+        .with_context(|| format!("{} is not backed by a file", old_inst.name()))?;
+
+    let snapshot = SyncbackSnapshot {
+        data: SyncbackData {
+            vfs,
+            old_tree,
+            new_tree: &new_tree,
+            project,
+        },
+        old: Some(id),
+        new: new_ref,
+        path: instigating_source.path().to_path_buf(),
+        middleware: metadata.middleware,
+    };
+    let changed_names = changed_properties.keys().copied();
+
+    match (instigating_source, metadata.middleware) {
+        (InstigatingSource::ProjectNode { path, .. }, _) => {
+            let node_names = project_node_names(old_tree, id, path)?;
+            syncback_project_node_properties(&snapshot, path, &node_names, changed_names)
+        }
+        (InstigatingSource::Path(path), Some(Middleware::Project)) => {
+            syncback_project_node_properties(&snapshot, path, &[], changed_names)
+        }
+        (InstigatingSource::Path(_), Some(middleware)) => middleware
+            .syncback_properties(&snapshot)
+            .with_context(|| format!("Failed to syncback {}", old_inst.name())),
+        (InstigatingSource::Path(path), None) => {
+            anyhow::bail!("{} has no middleware", path.display())
+        }
+    }
+}
+
+/// Returns the names of the project nodes leading from the root of the
+/// project at `project_path` to the node that created the Instance `id`.
+fn project_node_names(
+    tree: &RojoTree,
+    id: Ref,
+    project_path: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut current = tree.get_instance(id);
+
+    while let Some(inst) = current {
+        match &inst.metadata().instigating_source {
+            Some(InstigatingSource::ProjectNode { path, name, .. }) if path == project_path => {
+                names.push(name.clone());
+            }
+            Some(InstigatingSource::Path(path)) if path == project_path => {
+                names.reverse();
+                return Ok(names);
+            }
+            _ => break,
+        }
+        current = tree.get_instance(inst.parent());
+    }
+
+    anyhow::bail!(
+        "could not find the root of project {} for Instance {id:?}",
+        project_path.display()
+    )
+}
+
+fn builder_from_snapshot(snapshot: InstanceSnapshot) -> InstanceBuilder {
+    InstanceBuilder::new(snapshot.class_name)
+        .with_name(snapshot.name)
+        .with_properties(snapshot.properties)
+        .with_children(snapshot.children.into_iter().map(builder_from_snapshot))
+}
+
 pub struct SyncbackReturn<'sync> {
     pub fs_snapshot: FsSnapshot,
     pub children: Vec<SyncbackSnapshot<'sync>>,
@@ -339,7 +757,24 @@ pub fn get_best_middleware(snapshot: &SyncbackSnapshot) -> Middleware {
         return override_middleware;
     } else if let Some(old_middleware) = old_middleware {
         return old_middleware;
-    } else if json_model_classes.contains(inst.class.as_str()) {
+    }
+
+    let rule_middleware = snapshot
+        .project()
+        .syncback_rules
+        .as_ref()
+        .and_then(|rules| rules.middleware_for(snapshot));
+    if let Some(rule_middleware) = rule_middleware {
+        // JSON models can contain their children, so if a user asked for one
+        // explicitly we don't turn it into a directory.
+        return if inst.children().is_empty() || rule_middleware == Middleware::JsonModel {
+            rule_middleware
+        } else {
+            middleware_with_children(rule_middleware)
+        };
+    }
+
+    if json_model_classes.contains(inst.class.as_str()) {
         middleware = Middleware::JsonModel;
     } else {
         middleware = match inst.class.as_str() {
@@ -356,15 +791,7 @@ pub fn get_best_middleware(snapshot: &SyncbackSnapshot) -> Middleware {
     }
 
     if !inst.children().is_empty() {
-        middleware = match middleware {
-            Middleware::ServerScript => Middleware::ServerScriptDir,
-            Middleware::ClientScript => Middleware::ClientScriptDir,
-            Middleware::PluginScript => Middleware::PluginScriptDir,
-            Middleware::ModuleScript => Middleware::ModuleScriptDir,
-            Middleware::Csv => Middleware::CsvDir,
-            Middleware::JsonModel | Middleware::Text => Middleware::Dir,
-            _ => middleware,
-        }
+        middleware = middleware_with_children(middleware);
     }
 
     if middleware == Middleware::Rbxm {
@@ -378,7 +805,21 @@ pub fn get_best_middleware(snapshot: &SyncbackSnapshot) -> Middleware {
     middleware
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Returns the middleware that should be used in place of `middleware` for
+/// an Instance that has children.
+fn middleware_with_children(middleware: Middleware) -> Middleware {
+    match middleware {
+        Middleware::ServerScript => Middleware::ServerScriptDir,
+        Middleware::ClientScript => Middleware::ClientScriptDir,
+        Middleware::PluginScript => Middleware::PluginScriptDir,
+        Middleware::ModuleScript => Middleware::ModuleScriptDir,
+        Middleware::Csv => Middleware::CsvDir,
+        Middleware::JsonModel | Middleware::Text => Middleware::Dir,
+        _ => middleware,
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct SyncbackRules {
     /// A list of subtrees in a file that will be ignored by Syncback.
@@ -391,6 +832,7 @@ pub struct SyncbackRules {
     /// A map of classes to properties to ignore for that class when doing
     /// syncback.
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(with = "IndexMap<String, Vec<String>>")]
     ignore_properties: IndexMap<Ustr, Vec<Ustr>>,
     /// Whether or not the `CurrentCamera` of `Workspace` is included in the
     /// syncback or not. Defaults to `false`.
@@ -412,9 +854,194 @@ pub struct SyncbackRules {
     /// generally a better UX.
     #[serde(skip_serializing_if = "Option::is_none")]
     create_ignore_dir_paths: Option<bool>,
+    /// A map of classes to the middleware that new Instances of that class
+    /// will be synced back as, in place of Rojo's defaults.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    #[schemars(with = "IndexMap<String, Middleware>")]
+    class_middleware: IndexMap<Ustr, Middleware>,
+    /// A map of patterns to the middleware that new Instances will be synced
+    /// back as if their path in the input file matches the pattern. The first
+    /// matching pattern is used, and these take priority over
+    /// `class_middleware`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    path_middleware: IndexMap<Glob, Middleware>,
 }
 
 impl SyncbackRules {
+    /// Merges the rules of a project that's being extended into these ones.
+    /// Lists are combined, and these rules are kept wherever both define the
+    /// same setting.
+    pub fn merge_base(&mut self, base: &SyncbackRules) {
+        fn merge_list<T: PartialEq + Clone>(list: &mut Vec<T>, base: &[T]) {
+            for item in base {
+                if !list.contains(item) {
+                    list.push(item.clone());
+                }
+            }
+        }
+
+        merge_list(&mut self.ignore_trees, &base.ignore_trees);
+        merge_list(&mut self.ignore_paths, &base.ignore_paths);
+        for (class, properties) in &base.ignore_properties {
+            merge_list(
+                self.ignore_properties.entry(*class).or_default(),
+                properties,
+            );
+        }
+        self.sync_current_camera = self.sync_current_camera.or(base.sync_current_camera);
+        self.sync_unscriptable = self.sync_unscriptable.or(base.sync_unscriptable);
+        self.ignore_referents = self.ignore_referents.or(base.ignore_referents);
+        self.create_ignore_dir_paths = self
+            .create_ignore_dir_paths
+            .or(base.create_ignore_dir_paths);
+        for (class, middleware) in &base.class_middleware {
+            self.class_middleware.entry(*class).or_insert(*middleware);
+        }
+        // The first matching pattern is used, so these rules' patterns have to
+        // stay ahead of the base rules' patterns.
+        for (pattern, middleware) in &base.path_middleware {
+            self.path_middleware
+                .entry(pattern.clone())
+                .or_insert(*middleware);
+        }
+    }
+
+    /// Changes every pattern in `ignore_paths` from being relative to `from`
+    /// to being relative to `to`. Patterns are made absolute if `from` isn't
+    /// inside of `to`, since paths outside of the project's folder are matched
+    /// as they are.
+    pub fn rebase_ignore_paths(&mut self, from: &Path, to: &Path) {
+        let prefix = from.strip_prefix(to).unwrap_or(from);
+        if prefix.as_os_str().is_empty() {
+            return;
+        }
+        let prefix = globset::escape(&prefix.to_string_lossy().replace('\\', "/"));
+
+        for pattern in &mut self.ignore_paths {
+            *pattern = if let Some(body) = pattern.strip_prefix('!') {
+                format!("!{prefix}/{body}")
+            } else {
+                // A leading `\!` is only escaped so that it isn't a negation.
+                let body = pattern
+                    .strip_prefix('\\')
+                    .filter(|body| body.starts_with('!'));
+                let body = body.unwrap_or(pattern);
+                format!("{prefix}/{body}")
+            };
+        }
+    }
+
+    /// Returns the parts of these rules that differ from `base`, which is the
+    /// inverse of `merge_base`. Returns `None` if nothing differs.
+    pub fn without_base(&self, base: &SyncbackRules) -> Option<SyncbackRules> {
+        fn differs<T: PartialEq + Copy>(value: Option<T>, base: Option<T>) -> Option<T> {
+            if value == base {
+                None
+            } else {
+                value
+            }
+        }
+
+        let without = |list: &[String], base: &[String]| -> Vec<String> {
+            list.iter()
+                .filter(|item| !base.contains(item))
+                .cloned()
+                .collect()
+        };
+
+        let rules = SyncbackRules {
+            ignore_trees: without(&self.ignore_trees, &base.ignore_trees),
+            ignore_paths: without(&self.ignore_paths, &base.ignore_paths),
+            ignore_properties: self
+                .ignore_properties
+                .iter()
+                .filter_map(|(class, properties)| {
+                    let base_properties = base.ignore_properties.get(class);
+                    let properties: Vec<Ustr> = properties
+                        .iter()
+                        .filter(|prop| !base_properties.is_some_and(|base| base.contains(prop)))
+                        .copied()
+                        .collect();
+                    (!properties.is_empty()).then_some((*class, properties))
+                })
+                .collect(),
+            sync_current_camera: differs(self.sync_current_camera, base.sync_current_camera),
+            sync_unscriptable: differs(self.sync_unscriptable, base.sync_unscriptable),
+            ignore_referents: differs(self.ignore_referents, base.ignore_referents),
+            create_ignore_dir_paths: differs(
+                self.create_ignore_dir_paths,
+                base.create_ignore_dir_paths,
+            ),
+            class_middleware: self
+                .class_middleware
+                .iter()
+                .filter(|(class, middleware)| base.class_middleware.get(*class) != Some(middleware))
+                .map(|(class, middleware)| (*class, *middleware))
+                .collect(),
+            path_middleware: self
+                .path_middleware
+                .iter()
+                .filter(|(pattern, middleware)| {
+                    base.path_middleware.get(*pattern) != Some(middleware)
+                })
+                .map(|(pattern, middleware)| (pattern.clone(), *middleware))
+                .collect(),
+        };
+
+        (rules != SyncbackRules::default()).then_some(rules)
+    }
+
+    /// Returns the first `classMiddleware` or `pathMiddleware` entry that
+    /// can't be used, as the name of the setting, the entry's key, and the
+    /// reason it can't be used.
+    pub fn invalid_middleware(&self) -> Option<(&'static str, String, String)> {
+        let database = rbx_reflection_database::get().unwrap();
+        let middleware_name = |middleware: &Middleware| {
+            serde_json::to_value(middleware)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_owned))
+                .unwrap_or_else(|| format!("{middleware:?}"))
+        };
+        let unusable = |middleware: &Middleware| {
+            (!middleware.can_syncback_new()).then(|| {
+                format!(
+                    "uses {} middleware, which can't be used to sync back new Instances",
+                    middleware_name(middleware)
+                )
+            })
+        };
+
+        for (class, middleware) in &self.class_middleware {
+            let reason = if !database.classes.contains_key(class.as_str()) {
+                Some("is not a class in the reflection database".to_owned())
+            } else if let Some(reason) = unusable(middleware) {
+                Some(reason)
+            } else {
+                middleware
+                    .syncback_classes()
+                    .filter(|classes| !classes.contains(&class.as_str()))
+                    .map(|classes| {
+                        format!(
+                            "uses {} middleware, which can only sync back {}",
+                            middleware_name(middleware),
+                            classes.join(" or ")
+                        )
+                    })
+            };
+            if let Some(reason) = reason {
+                return Some(("classMiddleware", class.to_string(), reason));
+            }
+        }
+
+        for (pattern, middleware) in &self.path_middleware {
+            if let Some(reason) = unusable(middleware) {
+                return Some(("pathMiddleware", pattern.as_str().to_owned(), reason));
+            }
+        }
+
+        None
+    }
+
     pub fn compile_globs(&self) -> anyhow::Result<Vec<IgnorableGlob>> {
         let mut globs = Vec::with_capacity(self.ignore_paths.len());
         let dir_ignore_paths = self.create_ignore_dir_paths.unwrap_or(true);
@@ -435,6 +1062,23 @@ impl SyncbackRules {
 
         Ok(globs)
     }
+
+    /// Returns the middleware that the user asked for the new Instance in
+    /// `snapshot` to be synced back as, if there is one.
+    pub fn middleware_for(&self, snapshot: &SyncbackSnapshot) -> Option<Middleware> {
+        if !self.path_middleware.is_empty() {
+            let inst_path = snapshot.get_new_inst_path(snapshot.new);
+            for (glob, middleware) in &self.path_middleware {
+                if glob.is_match(&inst_path) {
+                    return Some(*middleware);
+                }
+            }
+        }
+
+        self.class_middleware
+            .get(&snapshot.new_inst().class)
+            .copied()
+    }
 }
 
 fn is_valid_path(globs: &Option<Vec<IgnorableGlob>>, base_path: &Path, path: &Path) -> bool {
@@ -558,6 +1202,8 @@ mod test {
             sync_unscriptable: None,
             ignore_referents: None,
             create_ignore_dir_paths,
+            class_middleware: IndexMap::new(),
+            path_middleware: IndexMap::new(),
         }
     }
 
@@ -611,4 +1257,38 @@ mod test {
             Path::new("/test/!literal.lua")
         ));
     }
+
+    #[test]
+    fn rebase_ignore_paths() {
+        let mut inside = rules(&["*.rbxm", "!keep.rbxm", r"\!literal.lua"], Some(false));
+        inside.rebase_ignore_paths(Path::new("/test/shared"), Path::new("/test"));
+        let globs = Some(inside.compile_globs().unwrap());
+        let base = Path::new("/test");
+
+        assert!(!is_valid_path(
+            &globs,
+            base,
+            Path::new("/test/shared/a.rbxm")
+        ));
+        assert!(is_valid_path(
+            &globs,
+            base,
+            Path::new("/test/shared/keep.rbxm")
+        ));
+        assert!(is_valid_path(&globs, base, Path::new("/test/a.rbxm")));
+        assert!(!is_valid_path(
+            &globs,
+            base,
+            Path::new("/test/shared/!literal.lua")
+        ));
+
+        // Paths outside of the project's folder are matched as they are, so
+        // patterns for them have to be absolute.
+        let mut outside = rules(&["*.rbxm"], Some(false));
+        outside.rebase_ignore_paths(Path::new("/shared"), Path::new("/test"));
+        let globs = Some(outside.compile_globs().unwrap());
+
+        assert!(!is_valid_path(&globs, base, Path::new("/shared/a.rbxm")));
+        assert!(is_valid_path(&globs, base, Path::new("/test/a.rbxm")));
+    }
 }
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{path_serializer, snapshot_middleware::Middleware};
+
+use super::FsSnapshot;
+
+/// A record of every change that syncback made or decided not to make, along
+/// with why. This is meant to be consumed by tools, so it's serializable.
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncbackReport {
+    pub changes: Vec<ReportEntry>,
+
+    /// Syncback can visit the same Instance more than once when navigating
+    /// projects, so this is used to only report each change once.
+    #[serde(skip)]
+    seen: HashSet<(ReportAction, PathBuf)>,
+}
+
+/// A single file or directory that syncback would write or remove.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportEntry {
+    pub action: ReportAction,
+
+    /// The path of the file or directory, relative to the project's folder.
+    #[serde(serialize_with = "path_serializer::serialize_absolute")]
+    pub path: PathBuf,
+
+    /// The middleware the Instance was (or would have been) written with.
+    pub middleware: Option<Middleware>,
+
+    /// The path to the Instance responsible for this change, as it would
+    /// appear in Roblox Studio.
+    pub instance: String,
+
+    /// If set, this change was not made because of the project's syncback
+    /// rules.
+    pub skipped: Option<SkipReason>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportAction {
+    Write,
+    Remove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SkipReason {
+    /// The path matched one of the `ignorePaths` globs.
+    IgnorePaths,
+    /// The Instance is inside one of the `ignoreTrees`.
+    IgnoreTrees,
+    /// The Instance was read by a sync rule with a command, but the rule has
+    /// no `syncbackCommand` to write it back with.
+    NoSyncbackCommand,
+}
+
+impl SyncbackReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a single change made by syncback. `path` is made relative to
+    /// `base_path` if possible.
+    pub fn push(
+        &mut self,
+        base_path: &Path,
+        action: ReportAction,
+        path: &Path,
+        middleware: Option<Middleware>,
+        instance: &str,
+        skipped: Option<SkipReason>,
+    ) {
+        let path = path.strip_prefix(base_path).unwrap_or(path).to_path_buf();
+        if !self.seen.insert((action, path.clone())) {
+            return;
+        }
+
+        self.changes.push(ReportEntry {
+            action,
+            path,
+            middleware,
+            instance: instance.to_owned(),
+            skipped,
+        })
+    }
+
+    /// Sorts the changes in this report by their path.
+    pub fn sort(&mut self) {
+        self.changes
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.action.cmp(&b.action)));
+    }
+
+    /// Records every path in `fs_snapshot` as a change made by `middleware`
+    /// for `instance`. Paths that `is_allowed` rejects are marked as skipped
+    /// because of `ignorePaths`.
+    pub fn push_fs_snapshot(
+        &mut self,
+        base_path: &Path,
+        fs_snapshot: &FsSnapshot,
+        middleware: Middleware,
+        instance: &str,
+        mut is_allowed: impl FnMut(&Path) -> bool,
+    ) {
+        let added = fs_snapshot
+            .added_paths()
+            .into_iter()
+            .map(|path| (ReportAction::Write, path));
+        let removed = fs_snapshot
+            .removed_paths()
+            .into_iter()
+            .map(|path| (ReportAction::Remove, path));
+
+        for (action, path) in added.chain(removed) {
+            let skipped = if is_allowed(path) {
+                None
+            } else {
+                Some(SkipReason::IgnorePaths)
+            };
+            self.push(base_path, action, path, Some(middleware), instance, skipped);
+        }
+    }
+}
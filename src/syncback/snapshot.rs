@@ -44,7 +44,13 @@ impl<'sync> SyncbackSnapshot<'sync> {
             middleware: None,
         };
         let middleware = get_best_middleware(&snapshot);
-        let name = name_for_inst(middleware, snapshot.new_inst(), snapshot.old_inst())?;
+        let name = name_for_inst(
+            middleware,
+            snapshot.new_inst(),
+            snapshot.old_inst(),
+            &self.data.project.sync_rules,
+            &self.data.project.init_file_names,
+        )?;
         snapshot.path = self.path.join(name.as_ref());
 
         Ok(snapshot)
@@ -70,7 +76,13 @@ impl<'sync> SyncbackSnapshot<'sync> {
             middleware: None,
         };
         let middleware = get_best_middleware(&snapshot);
-        let name = name_for_inst(middleware, snapshot.new_inst(), snapshot.old_inst())?;
+        let name = name_for_inst(
+            middleware,
+            snapshot.new_inst(),
+            snapshot.old_inst(),
+            &self.data.project.sync_rules,
+            &self.data.project.init_file_names,
+        )?;
         snapshot.path = base_path.join(name.as_ref());
 
         Ok(snapshot)
@@ -14,7 +14,7 @@ use rbx_dom_weak::{
 
 use crate::{
     serve_session::ServeSession,
-    snapshot::{InstanceWithMeta, PatchSet, PatchUpdate},
+    snapshot::{InstanceSnapshot, InstanceWithMeta, PatchAdd, PatchSet, PatchUpdate},
     web::{
         interface::{
             ErrorResponse, Instance, MessagesPacket, OpenResponse, ReadResponse,
@@ -160,6 +160,8 @@ impl ApiService {
             );
         }
 
+        let added_instances = patch_adds_from_request(request.added);
+
         let updated_instances = request
             .updated
             .into_iter()
@@ -174,8 +176,8 @@ impl ApiService {
 
         tree_mutation_sender
             .send(PatchSet {
-                removed_instances: Vec::new(),
-                added_instances: Vec::new(),
+                removed_instances: request.removed,
+                added_instances,
                 updated_instances,
             })
             .unwrap();
@@ -485,6 +487,60 @@ fn pick_script_path(instance: InstanceWithMeta<'_>) -> Option<PathBuf> {
         .map(|path| path.to_owned())
 }
 
+/// Turns the flat map of instances sent to /api/write into a list of patches,
+/// one for each added instance whose parent was not also added. Descendants are
+/// folded into the snapshot of their topmost added ancestor.
+fn patch_adds_from_request(mut added: HashMap<Ref, Instance<'static>>) -> Vec<PatchAdd> {
+    let root_ids: Vec<Ref> = added
+        .iter()
+        .filter(|(_, instance)| !added.contains_key(&instance.parent))
+        .map(|(id, _)| *id)
+        .collect();
+
+    root_ids
+        .into_iter()
+        .filter_map(|id| {
+            let parent_id = added.get(&id)?.parent;
+            let instance = snapshot_from_added(&mut added, id)?;
+
+            Some(PatchAdd {
+                parent_id,
+                instance,
+            })
+        })
+        .collect()
+}
+
+/// Builds an `InstanceSnapshot` for the added instance with the given ID and
+/// its added children. Instances are removed from `added` as they're visited,
+/// so a malformed request that contains a cycle can't recurse forever.
+fn snapshot_from_added(
+    added: &mut HashMap<Ref, Instance<'static>>,
+    id: Ref,
+) -> Option<InstanceSnapshot> {
+    let instance = added.remove(&id)?;
+
+    let children: Vec<InstanceSnapshot> = instance
+        .children
+        .iter()
+        .filter_map(|child_id| snapshot_from_added(added, *child_id))
+        .collect();
+
+    let properties: UstrMap<Variant> = instance
+        .properties
+        .into_iter()
+        .map(|(key, value)| (key, value.into_owned()))
+        .collect();
+
+    Some(
+        InstanceSnapshot::new()
+            .name(instance.name)
+            .class_name(instance.class_name)
+            .properties(properties)
+            .children(children),
+    )
+}
+
 /// Handle WebSocket connection for streaming subscription messages
 async fn handle_websocket_subscription(
     serve_session: Arc<ServeSession>,
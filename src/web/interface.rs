@@ -182,8 +182,11 @@ pub struct WriteRequest {
     pub session_id: SessionId,
     pub removed: Vec<Ref>,
 
+    /// Instances created by the client, keyed by a client-chosen ID. Added
+    /// instances are listed flatly; an instance whose `Parent` is also in this
+    /// map is a descendant of that instance rather than a new subtree root.
     #[serde(default)]
-    pub added: HashMap<Ref, ()>,
+    pub added: HashMap<Ref, Instance<'static>>,
     pub updated: Vec<InstanceUpdate>,
 }
 
@@ -0,0 +1,190 @@
+//! Workspaces list several projects in one repository, so that `rojo build`,
+//! `rojo sourcemap`, `rojo check`, and `rojo serve` can work with all of them
+//! at once instead of being run once for each project.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{json, project::DEFAULT_PROJECT_NAMES};
+
+/// The name of the workspace file that Rojo looks for in folders.
+pub const WORKSPACE_FILE_NAME: &str = "rojo.workspace.json";
+
+/// The extension that workspace files given directly need to end with.
+const WORKSPACE_EXTENSION: &str = ".workspace.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct Workspace {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    schema: Option<String>,
+
+    /// The projects in the workspace.
+    pub projects: Vec<WorkspaceProject>,
+
+    /// The path to the file that this workspace was loaded from.
+    #[serde(skip)]
+    pub file_location: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct WorkspaceProject {
+    /// The path to the project file, or to a folder with a default project
+    /// in it. Like every path in a workspace, it's relative to the folder the
+    /// workspace file is in.
+    pub path: PathBuf,
+
+    /// Where `rojo build` writes the project to. Projects without an output
+    /// aren't built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+
+    /// Where `rojo sourcemap` writes the project's sourcemap to. Projects
+    /// without one don't get a sourcemap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sourcemap: Option<PathBuf>,
+
+    /// The port that `rojo serve` serves the project on. Defaults to the
+    /// project's `servePort`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_port: Option<u16>,
+
+    /// The name of a profile from the project file to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl Workspace {
+    /// Returns the path of the workspace file that `path` refers to, if it
+    /// refers to one. That's either a `*.workspace.json` file or a folder
+    /// with a `rojo.workspace.json` file in it. Folders that also have a
+    /// default project in them are treated as projects, not workspaces.
+    pub fn locate(path: &Path) -> Option<PathBuf> {
+        if path.is_file() {
+            let file_name = path.file_name()?.to_str()?;
+            return file_name
+                .ends_with(WORKSPACE_EXTENSION)
+                .then(|| path.to_path_buf());
+        }
+
+        let workspace_path = path.join(WORKSPACE_FILE_NAME);
+        let has_default_project = DEFAULT_PROJECT_NAMES
+            .iter()
+            .any(|name| path.join(name).is_file());
+
+        (workspace_path.is_file() && !has_default_project).then_some(workspace_path)
+    }
+
+    /// Loads the workspace file at the given path, making every path in it
+    /// relative to the current directory instead of the workspace's folder.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs_err::read(path)?;
+        let mut workspace: Workspace = json::from_slice_with_context(&contents, || {
+            format!("Error loading workspace file {}", path.display())
+        })?;
+        workspace.file_location = path.to_path_buf();
+
+        let folder = workspace.folder_location().to_path_buf();
+        for project in &mut workspace.projects {
+            project.path = folder.join(&project.path);
+            for output in [&mut project.output, &mut project.sourcemap]
+                .into_iter()
+                .flatten()
+            {
+                *output = folder.join(&*output);
+            }
+        }
+
+        Ok(workspace)
+    }
+
+    pub fn folder_location(&self) -> &Path {
+        self.file_location.parent().unwrap()
+    }
+}
+
+/// Runs `run` for every project in the workspace at the same time, returning
+/// an error that names every project that failed, if any did.
+pub fn run_for_each<F>(projects: &[&WorkspaceProject], run: F) -> anyhow::Result<()>
+where
+    F: Fn(&WorkspaceProject) -> anyhow::Result<()> + Sync,
+{
+    let failures: Vec<String> = projects
+        .par_iter()
+        .filter_map(|project| {
+            run(project)
+                .with_context(|| format!("{} failed", project.path.display()))
+                .err()
+        })
+        .map(|err| format!("{err:?}"))
+        .collect();
+
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} projects failed:\n\n{}",
+            failures.len(),
+            projects.len(),
+            failures.join("\n\n")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_and_locate() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace_path = dir.path().join(WORKSPACE_FILE_NAME);
+        fs_err::write(
+            &workspace_path,
+            r#"{
+                // Comments are allowed, like in project files.
+                "projects": [
+                    { "path": "places/game", "output": "build/game.rbxl", "servePort": 34873 },
+                    { "path": "packages/utils.project.json", "sourcemap": "packages/sourcemap.json" },
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(Workspace::locate(dir.path()), Some(workspace_path.clone()));
+        assert_eq!(
+            Workspace::locate(&workspace_path),
+            Some(workspace_path.clone())
+        );
+
+        let workspace = Workspace::load(&workspace_path).unwrap();
+        assert_eq!(
+            workspace.projects,
+            [
+                WorkspaceProject {
+                    path: dir.path().join("places/game"),
+                    output: Some(dir.path().join("build/game.rbxl")),
+                    sourcemap: None,
+                    serve_port: Some(34873),
+                    profile: None,
+                },
+                WorkspaceProject {
+                    path: dir.path().join("packages/utils.project.json"),
+                    output: None,
+                    sourcemap: Some(dir.path().join("packages/sourcemap.json")),
+                    serve_port: None,
+                    profile: None,
+                },
+            ]
+        );
+
+        // Folders with a default project in them are projects, even if they
+        // have a workspace file too.
+        fs_err::write(dir.path().join("default.project.json"), "{}").unwrap();
+        assert_eq!(Workspace::locate(dir.path()), None);
+    }
+}
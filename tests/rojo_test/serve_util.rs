@@ -1,8 +1,8 @@
 use std::{
     fs,
+    net::TcpListener,
     path::{Path, PathBuf},
     process::Command,
-    sync::atomic::{AtomicUsize, Ordering},
     thread,
     time::Duration,
 };
@@ -16,7 +16,7 @@ use tempfile::{tempdir, TempDir};
 use librojo::{
     web_api::{
         ReadResponse, SerializeRequest, SerializeResponse, ServerInfoResponse, SocketPacket,
-        SocketPacketType,
+        SocketPacketType, WriteRequest, WriteResponse,
     },
     SessionId,
 };
@@ -34,11 +34,20 @@ use crate::rojo_test::io_util::{
 /// The passed in callback is where the actual test body should go. Setup and
 /// cleanup happens automatically.
 pub fn run_serve_test(test_name: &str, callback: impl FnOnce(TestServeSession, RedactionMap)) {
+    run_serve_test_with_args(test_name, &[], callback);
+}
+
+/// Like `run_serve_test`, but passes extra arguments to `rojo serve`.
+pub fn run_serve_test_with_args(
+    test_name: &str,
+    args: &[&str],
+    callback: impl FnOnce(TestServeSession, RedactionMap),
+) {
     let _ = env_logger::try_init();
 
     let mut redactions = RedactionMap::default();
 
-    let mut session = TestServeSession::new(test_name);
+    let mut session = TestServeSession::new(test_name, args);
     let info = session.wait_to_come_online();
 
     redactions.intern(info.session_id);
@@ -69,7 +78,9 @@ pub struct TestServeSession {
 }
 
 impl TestServeSession {
-    pub fn new(name: &str) -> Self {
+    /// Starts `rojo serve` on a copy of the given test project, passing it
+    /// any extra arguments.
+    pub fn new(name: &str, args: &[&str]) -> Self {
         let working_dir = get_working_dir_path();
 
         let source_path = Path::new(SERVE_TESTS_PATH).join(name);
@@ -110,6 +121,7 @@ impl TestServeSession {
                 "--port",
                 port_string.as_str(),
             ])
+            .args(args)
             .current_dir(working_dir)
             .spawn()
             .expect("Couldn't start Rojo");
@@ -244,6 +256,16 @@ impl TestServeSession {
         client.post(url).body(body).send()
     }
 
+    pub fn post_api_write(&self, request: &WriteRequest) -> Result<WriteResponse, reqwest::Error> {
+        let client = reqwest::blocking::Client::new();
+        let url = format!("http://localhost:{}/api/write", self.port);
+        let body = serialize_msgpack(request).unwrap();
+
+        let body = client.post(url).body(body).send()?.bytes()?;
+
+        Ok(deserialize_msgpack(&body).expect("Server returned malformed response"))
+    }
+
     /// Sends a GET to `/api/rojo` with the given extra request headers and
     /// returns the full response. Used to exercise the Host/Origin allowlist that
     /// guards against DNS rebinding, including asserting that a rejection reveals
@@ -297,16 +319,19 @@ pub fn deserialize_msgpack<'a, T: Deserialize<'a>>(
     T::deserialize(&mut deserializer)
 }
 
-/// Probably-okay way to generate random enough port numbers for running the
-/// Rojo live server.
+/// Finds a port that's free for the Rojo live server to listen on.
 ///
-/// If this method ends up having problems, we should add an option for Rojo to
-/// use a random port chosen by the operating system and figure out a good way
-/// to get that port back to the test CLI.
+/// The operating system picks the port, so that it can't collide with ports
+/// already in use, like the ephemeral ports of other tests' client
+/// connections. The listener is closed right away so that Rojo can bind it.
 fn get_port_number() -> usize {
-    static NEXT_PORT_NUMBER: AtomicUsize = AtomicUsize::new(35103);
+    let listener = TcpListener::bind(("127.0.0.1", 0)).expect("Couldn't find a free port for Rojo");
 
-    NEXT_PORT_NUMBER.fetch_add(1, Ordering::SeqCst)
+    listener
+        .local_addr()
+        .expect("Couldn't get the address of the listener")
+        .port()
+        .into()
 }
 
 /// Takes a SerializeResponse and creates an XML model out of the response.
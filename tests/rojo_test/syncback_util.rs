@@ -1,7 +1,11 @@
-use std::{io::Write as _, path::Path, process::Command};
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
 
 use insta::{assert_snapshot, assert_yaml_snapshot};
-use tempfile::tempdir;
+use tempfile::{tempdir, TempDir};
 
 use crate::rojo_test::io_util::SYNCBACK_TESTS_PATH;
 
@@ -23,6 +27,71 @@ pub fn run_syncback_test(name: &str, callback: impl FnOnce(&Path)) {
 
     // let working_dir = get_working_dir_path();
 
+    let (_test_dir, project_path, input_file) = copy_test_project(name);
+
+    let output = Command::new(ROJO_PATH)
+        // I don't really understand why setting the working directory breaks this, but it does.
+        // It's a bit concerning but I'm more interested in writing tests than debugging it right now.
+        // TODO: Figure out why and fix it.
+        // .current_dir(working_dir)
+        .args([
+            "--color",
+            "never",
+            "syncback",
+            project_path.to_str().unwrap(),
+            "--input",
+            input_file.to_str().unwrap(),
+            "--non-interactive",
+            "--list",
+        ])
+        .output()
+        .expect("Couldn't spawn syncback process");
+
+    exit_on_failure(&output);
+
+    let settings = snapshot_settings();
+
+    settings.bind(|| {
+        assert_snapshot!(
+            format!("{name}-stdout"),
+            String::from_utf8_lossy(&output.stdout)
+        )
+    });
+
+    settings.bind(|| callback(project_path.as_path()))
+}
+
+/// Runs `rojo syncback --dry-run --format json` on a test project from the
+/// `syncback-tests` folder and snapshots the report it emits.
+pub fn snapshot_syncback_report(name: &str) {
+    let _ = env_logger::try_init();
+
+    let (_test_dir, project_path, input_file) = copy_test_project(name);
+
+    let output = Command::new(ROJO_PATH)
+        .args([
+            "syncback",
+            project_path.to_str().unwrap(),
+            "--input",
+            input_file.to_str().unwrap(),
+            "--dry-run",
+            "--format",
+            "json",
+        ])
+        .output()
+        .expect("Couldn't spawn syncback process");
+
+    exit_on_failure(&output);
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("syncback report should be valid JSON");
+
+    snapshot_settings().bind(|| assert_yaml_snapshot!(format!("{name}-report"), report));
+}
+
+/// Copies a test project into a temporary directory, returning the
+/// directory, the path to the copied project, and the path to the input file.
+fn copy_test_project(name: &str) -> (TempDir, PathBuf, PathBuf) {
     let source_path = Path::new(SYNCBACK_TESTS_PATH)
         .join(name)
         .join(INPUT_FILE_PROJECT);
@@ -55,24 +124,10 @@ pub fn run_syncback_test(name: &str, callback: impl FnOnce(&Path)) {
             .expect("Couldn't copy project to temporary directory");
     };
 
-    let output = Command::new(ROJO_PATH)
-        // I don't really understand why setting the working directory breaks this, but it does.
-        // It's a bit concerning but I'm more interested in writing tests than debugging it right now.
-        // TODO: Figure out why and fix it.
-        // .current_dir(working_dir)
-        .args([
-            "--color",
-            "never",
-            "syncback",
-            project_path.to_str().unwrap(),
-            "--input",
-            input_file.to_str().unwrap(),
-            "--non-interactive",
-            "--list",
-        ])
-        .output()
-        .expect("Couldn't spawn syncback process");
+    (test_dir, project_path, input_file)
+}
 
+fn exit_on_failure(output: &Output) {
     if !output.status.success() {
         let mut lock = std::io::stderr().lock();
         writeln!(
@@ -88,7 +143,9 @@ pub fn run_syncback_test(name: &str, callback: impl FnOnce(&Path)) {
 
         std::process::exit(1)
     }
+}
 
+fn snapshot_settings() -> insta::Settings {
     let mut settings = insta::Settings::new();
     let snapshot_path = Path::new(SYNCBACK_TESTS_PATH)
         .parent()
@@ -97,14 +154,7 @@ pub fn run_syncback_test(name: &str, callback: impl FnOnce(&Path)) {
     settings.set_snapshot_path(snapshot_path);
     settings.set_sort_maps(true);
 
-    settings.bind(|| {
-        assert_snapshot!(
-            format!("{name}-stdout"),
-            String::from_utf8_lossy(&output.stdout)
-        )
-    });
-
-    settings.bind(|| callback(project_path.as_path()))
+    settings
 }
 
 pub fn snapshot_rbxm(name: &str, input: Vec<u8>, file_name: &str) {
@@ -22,6 +22,7 @@ macro_rules! gen_build_tests {
 
 gen_build_tests! {
     init_csv_with_children,
+    init_file_names,
     attributes,
     client_in_folder,
     client_init,
@@ -48,7 +49,9 @@ gen_build_tests! {
     optional,
     project_composed_default,
     project_composed_file,
+    project_extends,
     project_root_name,
+    project_variables,
     rbxm_in_folder,
     rbxmx_in_folder,
     rbxmx_ref,
@@ -59,16 +62,259 @@ gen_build_tests! {
     txt_in_folder,
     unresolved_values,
     weldconstraint,
+    yaml_toml_files,
     sync_rule_alone,
     sync_rule_complex,
     sync_rule_nested_projects,
+    sync_rule_directories,
+    strip_sources,
+    strip_sources_init,
     no_name_default_project,
     no_name_project,
     no_name_top_level_project,
     plugin_init,
+    profile,
+    preprocess,
+}
+
+#[test]
+fn build_profile_release() {
+    let _ = env_logger::try_init();
+
+    run_build_test_with_args("profile", "profile_release", &["--profile", "release"]);
+}
+
+#[test]
+fn build_preprocess_release() {
+    let _ = env_logger::try_init();
+
+    run_build_test_with_args(
+        "preprocess",
+        "preprocess_release",
+        &["--profile", "release"],
+    );
+}
+
+#[test]
+fn build_preprocess_define() {
+    let _ = env_logger::try_init();
+
+    run_build_test_with_args(
+        "preprocess",
+        "preprocess_define",
+        &["--define", "PLATFORM=console", "--define", "DEBUG=false"],
+    );
+}
+
+#[test]
+fn build_strip_sources_profile() {
+    let _ = env_logger::try_init();
+
+    run_build_test_with_args(
+        "strip_sources",
+        "strip_sources_profile",
+        &["--profile", "readable"],
+    );
+}
+
+#[test]
+fn build_source_mapping_init() {
+    let _ = env_logger::try_init();
+
+    let input_path = Path::new(BUILD_TESTS_PATH).join("strip_sources_init");
+    let output_dir = tempdir().expect("couldn't create temporary directory");
+    let output_path = output_dir.path().join("strip_sources_init.rbxmx");
+    let mapping_path = output_dir.path().join("mapping.json");
+
+    let output = Command::new(ROJO_PATH)
+        .args([
+            "build",
+            input_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+            "--source-mapping",
+            mapping_path.to_str().unwrap(),
+        ])
+        .env("RUST_LOG", "error")
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(output.status.success(), "Rojo did not exit successfully");
+
+    // Init scripts map back to their init files, not their directories.
+    let mapping = fs::read_to_string(&mapping_path).expect("Couldn't read source mapping");
+    let mut settings = insta::Settings::new();
+    settings.set_snapshot_path(
+        Path::new(BUILD_TESTS_PATH)
+            .parent()
+            .unwrap()
+            .join("build-test-snapshots"),
+    );
+    settings.bind(|| {
+        assert_snapshot!("source_mapping_init", mapping);
+    });
+}
+
+#[test]
+fn build_base() {
+    let _ = env_logger::try_init();
+
+    let base_path = Path::new(BUILD_TESTS_PATH).join("base/base.rbxmx");
+    run_build_test_with_args("base", "base", &["--base", base_path.to_str().unwrap()]);
+}
+
+#[cfg(unix)]
+#[test]
+fn build_on_build() {
+    let _ = env_logger::try_init();
+
+    let input_path = Path::new(BUILD_TESTS_PATH).join("txt");
+    let output_dir = tempdir().expect("couldn't create temporary directory");
+    let output_path = output_dir.path().join("txt.rbxmx");
+
+    let output = Command::new(ROJO_PATH)
+        .args([
+            "build",
+            input_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+            "--on-build",
+            "sh -c 'cp \"$ROJO_OUTPUT_PATH\" copy.rbxmx'",
+        ])
+        .env("RUST_LOG", "error")
+        .current_dir(output_dir.path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(output.status.success(), "Rojo did not exit successfully");
+
+    let contents = fs::read(&output_path).expect("Couldn't read output file");
+    let copied = fs::read(output_dir.path().join("copy.rbxmx"))
+        .expect("The on-build command didn't copy the output file");
+    assert_eq!(contents, copied);
+}
+
+#[cfg(unix)]
+#[test]
+fn build_external_middleware() {
+    let _ = env_logger::try_init();
+
+    run_build_test_with_args(
+        "external_middleware",
+        "external_middleware",
+        &["--allow-external-commands"],
+    );
+}
+
+#[test]
+fn build_external_middleware_not_allowed() {
+    let _ = env_logger::try_init();
+
+    let input_path = Path::new(BUILD_TESTS_PATH).join("external_middleware");
+    let output_dir = tempdir().expect("couldn't create temporary directory");
+    let output_path = output_dir.path().join("external_middleware.rbxmx");
+
+    let output = Command::new(ROJO_PATH)
+        .args([
+            "build",
+            input_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+        ])
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "Rojo ran a command without being allowed to"
+    );
+    assert!(stderr.contains("--allow-external-commands"), "{stderr}");
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn build_over_budget() {
+    let _ = env_logger::try_init();
+
+    let input_path = Path::new(BUILD_TESTS_PATH).join("over_budget");
+    let output_dir = tempdir().expect("couldn't create temporary directory");
+    let output_path = output_dir.path().join("over_budget.rbxmx");
+
+    let output = Command::new(ROJO_PATH)
+        .args([
+            "build",
+            input_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+        ])
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success(),
+        "Rojo built a project over its budget"
+    );
+    assert!(
+        stderr.contains("The budget for `Shared` allows 100 bytes"),
+        "{stderr}"
+    );
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn build_workspace() {
+    let _ = env_logger::try_init();
+
+    let workspace_dir = tempdir().expect("couldn't create temporary directory");
+    let projects = ["txt", "module_init", "preprocess"].map(|name| {
+        serde_json::json!({
+            "path": Path::new(BUILD_TESTS_PATH).join(name),
+            "output": format!("{name}.rbxmx"),
+        })
+    });
+    fs::write(
+        workspace_dir.path().join("rojo.workspace.json"),
+        serde_json::to_string(&serde_json::json!({ "projects": projects })).unwrap(),
+    )
+    .expect("Couldn't write workspace file");
+
+    let output = Command::new(ROJO_PATH)
+        .args(["build", workspace_dir.path().to_str().unwrap()])
+        .env("RUST_LOG", "error")
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    assert!(output.status.success(), "Rojo did not exit successfully");
+
+    // Projects are built at the same time, so each line names its project.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for name in ["txt", "module_init", "preprocess"] {
+        let output_path = workspace_dir.path().join(format!("{name}.rbxmx"));
+        assert!(output_path.is_file(), "{name} wasn't built");
+        assert!(stdout.contains(&format!("[{name}] Built project to {name}.rbxmx")));
+    }
 }
 
 fn run_build_test(test_name: &str) {
+    run_build_test_with_args(test_name, test_name, &[]);
+}
+
+fn run_build_test_with_args(test_name: &str, snapshot_name: &str, args: &[&str]) {
     let working_dir = get_working_dir_path();
 
     let input_path = Path::new(BUILD_TESTS_PATH).join(test_name);
@@ -83,6 +329,7 @@ fn run_build_test(test_name: &str) {
             "-o",
             output_path.to_str().unwrap(),
         ])
+        .args(args)
         .env("RUST_LOG", "error")
         .current_dir(working_dir)
         .output()
@@ -105,6 +352,6 @@ fn run_build_test(test_name: &str) {
     settings.set_snapshot_path(snapshot_path);
 
     settings.bind(|| {
-        assert_snapshot!(test_name, contents);
+        assert_snapshot!(snapshot_name, contents);
     });
 }
@@ -1,3 +1,4 @@
 mod build;
 mod serve;
 mod syncback;
+mod workspace;
@@ -1,16 +1,23 @@
-use std::fs;
+use std::{borrow::Cow, collections::HashMap, fs, thread, time::Duration};
 
 use insta::{assert_snapshot, assert_yaml_snapshot, with_settings};
-use rbx_dom_weak::types::Ref;
+use rbx_dom_weak::{
+    types::{Ref, Variant},
+    ustr, UstrMap,
+};
 use reqwest::StatusCode;
 use tempfile::tempdir;
 
 use crate::rojo_test::{
     internable::InternAndRedact,
-    serve_util::{deserialize_msgpack, run_serve_test, serialize_to_xml_model},
+    serve_util::{
+        deserialize_msgpack, run_serve_test, run_serve_test_with_args, serialize_to_xml_model,
+    },
 };
 
-use librojo::web_api::{SerializeResponse, SocketPacketType};
+use librojo::web_api::{
+    Instance, InstanceUpdate, ReadResponse, SerializeResponse, SocketPacketType, WriteRequest,
+};
 
 #[test]
 fn rejects_dns_rebinding_requests() {
@@ -196,6 +203,281 @@ fn remove_file() {
     });
 }
 
+#[test]
+fn write_removed_and_added() {
+    run_serve_test("scripts", |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        let root_id = info.root_instance_id;
+
+        let read_response = session.get_api_read(root_id).unwrap();
+        let foo_id = find_instance_id(&read_response, "foo");
+
+        let mut properties = UstrMap::default();
+        properties.insert(
+            ustr("Source"),
+            Cow::Owned(Variant::String("-- Hello, from baz!".to_owned())),
+        );
+
+        let added_id = Ref::new();
+        let mut added = HashMap::new();
+        added.insert(
+            added_id,
+            Instance {
+                id: added_id,
+                parent: root_id,
+                name: Cow::Borrowed("baz"),
+                class_name: ustr("ModuleScript"),
+                properties,
+                children: Cow::Borrowed(&[]),
+                metadata: None,
+            },
+        );
+
+        session
+            .post_api_write(&WriteRequest {
+                session_id: info.session_id,
+                removed: vec![foo_id],
+                added,
+                updated: Vec::new(),
+            })
+            .unwrap();
+
+        let src = session.path().join("src");
+        // The file can exist before its contents are written, so wait on those
+        // instead.
+        wait_for(|| {
+            !src.join("foo.lua").exists()
+                && fs::read_to_string(src.join("baz.luau")).ok().as_deref()
+                    == Some("-- Hello, from baz!")
+        });
+
+        assert!(src.join("bar.server.lua").exists());
+    });
+}
+
+#[test]
+fn write_updated_properties() {
+    run_serve_test("write_properties", |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        let root_id = info.root_instance_id;
+
+        let read_response = session.get_api_read(root_id).unwrap();
+        session
+            .post_api_write(&WriteRequest {
+                session_id: info.session_id,
+                removed: Vec::new(),
+                added: HashMap::new(),
+                updated: vec![
+                    instance_update(
+                        find_instance_id(&read_response, "node"),
+                        [("Value", Variant::String("edited".to_owned()))],
+                    ),
+                    instance_update(
+                        find_instance_id(&read_response, "model"),
+                        [("Value", Variant::Int64(5))],
+                    ),
+                    instance_update(
+                        find_instance_id(&read_response, "text"),
+                        [("Value", Variant::String("world".to_owned()))],
+                    ),
+                ],
+            })
+            .unwrap();
+
+        let project_path = session.path().join("default.project.json");
+        let model_path = session.path().join("src/model.model.json");
+        let text_path = session.path().join("src/text.txt");
+        let read_model = || {
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&model_path).unwrap())
+                .ok()
+        };
+        wait_for(|| {
+            fs::read_to_string(&project_path)
+                .unwrap()
+                .contains("edited")
+                && read_model().is_some_and(|model| model["properties"]["Value"] == 5.0)
+                && fs::read_to_string(&text_path).unwrap() == "world"
+        });
+
+        assert_eq!(read_model().unwrap()["className"], "IntValue");
+    });
+}
+
+#[test]
+fn write_unchanged_project_properties() {
+    run_serve_test("write_properties", |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        let root_id = info.root_instance_id;
+
+        let read_response = session.get_api_read(root_id).unwrap();
+        let project_path = session.path().join("default.project.json");
+        let project_contents = fs::read_to_string(&project_path).unwrap();
+
+        session
+            .post_api_write(&WriteRequest {
+                session_id: info.session_id,
+                removed: Vec::new(),
+                added: HashMap::new(),
+                updated: vec![
+                    instance_update(
+                        find_instance_id(&read_response, "node"),
+                        [("Value", Variant::String("node".to_owned()))],
+                    ),
+                    instance_update(
+                        find_instance_id(&read_response, "text"),
+                        [("Value", Variant::String("world".to_owned()))],
+                    ),
+                ],
+            })
+            .unwrap();
+
+        // Updates are applied in order, so once the text file is written the
+        // project would have been rewritten too.
+        let text_path = session.path().join("src/text.txt");
+        wait_for(|| fs::read_to_string(&text_path).unwrap() == "world");
+
+        assert_eq!(fs::read_to_string(&project_path).unwrap(), project_contents);
+    });
+}
+
+#[test]
+fn write_profile_properties() {
+    run_serve_test_with_args(
+        "write_profile",
+        &["--profile", "dev"],
+        |session, _redactions| {
+            let info = session.get_api_rojo().unwrap();
+            let root_id = info.root_instance_id;
+
+            let read_response = session.get_api_read(root_id).unwrap();
+
+            session
+                .post_api_write(&WriteRequest {
+                    session_id: info.session_id,
+                    removed: Vec::new(),
+                    added: HashMap::new(),
+                    updated: vec![
+                        instance_update(
+                            find_instance_id(&read_response, "node"),
+                            [("Value", Variant::String("edited".to_owned()))],
+                        ),
+                        instance_update(
+                            find_instance_id(&read_response, "added"),
+                            [("Value", Variant::String("edited".to_owned()))],
+                        ),
+                        instance_update(
+                            find_instance_id(&read_response, "plain"),
+                            [("Value", Variant::String("edited".to_owned()))],
+                        ),
+                    ],
+                })
+                .unwrap();
+
+            // Updates are applied in order, so once the plain node is written
+            // the others would have been written too. Neither the node that
+            // the profile changes nor the one it adds can be written without
+            // changing the project for every profile.
+            let project_path = session.path().join("default.project.json");
+            let read_project = || {
+                serde_json::from_str::<serde_json::Value>(
+                    &fs::read_to_string(&project_path).unwrap(),
+                )
+                .ok()
+            };
+            wait_for(|| {
+                read_project().is_some_and(|project| {
+                    project["tree"]["plain"]["$properties"]["Value"] == "edited"
+                })
+            });
+
+            let project = read_project().unwrap();
+            assert_eq!(project["tree"]["node"]["$properties"]["Value"], "node");
+            assert!(project["tree"].get("added").is_none());
+            let profile_tree = &project["profiles"]["dev"]["tree"];
+            assert_eq!(profile_tree["node"]["$properties"]["Value"], "dev");
+            assert_eq!(profile_tree["added"]["$properties"]["Value"], "added");
+        },
+    );
+}
+
+#[test]
+fn write_preprocessed_source() {
+    run_serve_test("write_preprocessed", |session, _redactions| {
+        let info = session.get_api_rojo().unwrap();
+        let root_id = info.root_instance_id;
+
+        let read_response = session.get_api_read(root_id).unwrap();
+        let debug_path = session.path().join("src/Debug.luau");
+        let debug_contents = fs::read_to_string(&debug_path).unwrap();
+
+        session
+            .post_api_write(&WriteRequest {
+                session_id: info.session_id,
+                removed: Vec::new(),
+                added: HashMap::new(),
+                updated: vec![
+                    instance_update(
+                        find_instance_id(&read_response, "Debug"),
+                        [("Source", Variant::String("return 3".to_owned()))],
+                    ),
+                    instance_update(
+                        find_instance_id(&read_response, "Plain"),
+                        [("Source", Variant::String("return 4".to_owned()))],
+                    ),
+                ],
+            })
+            .unwrap();
+
+        // Updates are applied in order, so once the plain script is written
+        // the preprocessed one would have been overwritten too.
+        let plain_path = session.path().join("src/Plain.luau");
+        wait_for(|| fs::read_to_string(&plain_path).unwrap() == "return 4");
+
+        assert_eq!(fs::read_to_string(&debug_path).unwrap(), debug_contents);
+    });
+}
+
+/// Returns the ID of the first instance named `name` in the response.
+fn find_instance_id(read_response: &ReadResponse, name: &str) -> Ref {
+    read_response
+        .instances
+        .values()
+        .find(|instance| instance.name == name)
+        .map(|instance| instance.id)
+        .unwrap_or_else(|| panic!("{name} should exist in the tree"))
+}
+
+/// Builds an update that sets the given properties of the instance with the
+/// given ID, leaving everything else about it as it is.
+fn instance_update<'a>(
+    id: Ref,
+    properties: impl IntoIterator<Item = (&'a str, Variant)>,
+) -> InstanceUpdate {
+    InstanceUpdate {
+        id,
+        changed_name: None,
+        changed_class_name: None,
+        changed_properties: properties
+            .into_iter()
+            .map(|(key, value)| (ustr(key), Some(value)))
+            .collect(),
+        changed_metadata: None,
+    }
+}
+
+/// Polls `condition` until it holds, panicking if it doesn't within a few
+/// seconds. Writes through `/api/write` are applied asynchronously.
+fn wait_for(condition: impl Fn() -> bool) {
+    for _ in 0..50 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    panic!("condition was not met in time");
+}
+
 #[test]
 fn edit_init() {
     run_serve_test("edit_init", |session, mut redactions| {
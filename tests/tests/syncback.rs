@@ -1,8 +1,21 @@
-use std::ffi::OsStr;
+use std::{
+    ffi::OsStr,
+    fs,
+    io::Write,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
 
 use insta::assert_snapshot;
+use rbx_dom_weak::{InstanceBuilder, WeakDom};
+use tempfile::tempdir;
 
-use crate::rojo_test::syncback_util::{run_syncback_test, snapshot_rbxm};
+use crate::rojo_test::{
+    io_util::ROJO_PATH,
+    syncback_util::{run_syncback_test, snapshot_rbxm, snapshot_syncback_report},
+};
 
 macro_rules! syncback_tests {
     ($($test_name:ident => $list:expr$(,)?),*) => {$(
@@ -34,9 +47,17 @@ syncback_tests! {
     child_but_not => ["OnlyOneCopy/child_of_one.luau", "ReplicatedStorage/child_replicated_storage.luau"],
     // Ensures that syncback works with CSVs
     csv => ["src/csv_init/init.csv", "src/csv.csv"],
+    // Ensures that JSON, TOML, and YAML modules are rewritten from their
+    // Source, and fall back to ModuleScripts when they aren't literals
+    data_modules => ["src/config.json", "src/data.yaml", "src/scripted.luau", "src/settings.toml"],
     // Ensures that if a RojoId is duplicated somewhere in the project, it's
     // rewritten rather than synced back as a conflict
     duplicate_rojo_id => ["container.model.json"],
+    // Ensures that init files are written with the project's init file names
+    init_file_names => ["src/Module/_index.luau", "src/NewModule/_index.luau", "src/NewModule/Helper.luau", "src/Strings/_strings.csv"],
+    // Ensures that directories matching sync rules for directories keep
+    // their suffix and don't get a className in init.meta.json
+    sync_rule_directories => ["src/Sword.tool/Swing.server.luau", "src/Axe.tool/Chop.server.luau"],
     // Ensures that the `ignorePaths` setting works for additions
     ignore_paths_adding => ["src/int_value.model.json", "src/subfolder/string_value.txt"],
     // Ensures that the `ignorePaths` setting works for `init` files
@@ -49,6 +70,9 @@ syncback_tests! {
     ignore_trees_removing => [],
     // Ensures that all of the JSON middlewares are handled as expected
     json_middlewares => ["src/dir_with_meta/init.meta.json", "src/model_json.model.json", "src/project_json.project.json"],
+    // Ensures that existing meta files are written back in their own format,
+    // and new ones are written as JSON
+    meta_file_formats => ["src/Script.meta.yaml", "src/Folder/init.meta.toml", "src/NewModule.meta.json"],
     // Ensures projects that refer to other projects work as expected
     nested_projects => ["nested.project.json", "string_value.txt"],
     // Ensures files that are ignored by nested projects are picked up if
@@ -74,6 +98,12 @@ syncback_tests! {
     // Ensures that ref properties that point to nothing after the prune both
     // do not leave any trace of themselves
     ref_properties_pruned => ["src/Pointer1.model.json", "src/Pointer2.model.json", "src/Pointer3.model.json"],
+    // Ensures that `classMiddleware` and `pathMiddleware` are respected for
+    // new Instances
+    middleware_rules => ["src/Settings.model.json", "src/Stuff/Thing.rbxm"],
+    // Ensures that plugin, legacy, and RunContext scripts round-trip with
+    // the right file names and RunContext
+    script_middlewares => ["src/Client.client.luau", "src/Legacy.legacy.luau", "src/LegacyClient.local.luau", "src/Odd.legacy.luau", "src/Odd.meta.json", "src/Plugin.plugin.luau", "src/Server.server.luau"],
     // Ensures that the old middleware is respected during syncback
     respect_old_middleware => ["default.project.json", "src/model_json.model.json", "src/rbxm.rbxm", "src/rbxmx.rbxmx"],
     // Ensures that the `$schema` field roundtrips with syncback
@@ -90,3 +120,183 @@ syncback_tests! {
     // than leaving an incorrect value.
     project_default_properties_remove => ["default.project.json"],
 }
+
+#[test]
+fn report_ignore_paths() {
+    snapshot_syncback_report("ignore_paths_adding");
+}
+
+#[test]
+fn report_ignore_trees() {
+    snapshot_syncback_report("ignore_trees_removing");
+}
+
+#[test]
+fn watch() {
+    run_watch_test(&[]);
+}
+
+#[test]
+fn watch_incremental() {
+    run_watch_test(&["--incremental"]);
+}
+
+#[test]
+fn incremental_cache_written_with_files() {
+    let _ = env_logger::try_init();
+
+    let test_dir = tempdir().expect("couldn't create temporary directory");
+    let project_path = test_dir.path().join("project");
+    fs::create_dir_all(project_path.join("src")).unwrap();
+    fs::write(
+        project_path.join("default.project.json"),
+        r#"{ "name": "cache", "tree": { "$path": "src" } }"#,
+    )
+    .unwrap();
+
+    let input_path = test_dir.path().join("input.rbxm");
+    write_model(&input_path, &[("Module", "return 1")]);
+
+    let cache_path = project_path.join(".rojo-syncback-cache");
+    let module_path = project_path.join("src/Module.luau");
+    let syncback = |args: &[&str], stdin: &[u8]| {
+        let mut rojo = Command::new(ROJO_PATH)
+            .args([
+                "syncback",
+                project_path.to_str().unwrap(),
+                "--input",
+                input_path.to_str().unwrap(),
+                "--incremental",
+            ])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Couldn't spawn syncback process");
+        rojo.stdin.take().unwrap().write_all(stdin).unwrap();
+        assert!(
+            rojo.wait().unwrap().success(),
+            "Rojo did not exit successfully"
+        );
+    };
+
+    // Neither a dry run nor a declined prompt writes anything, and that
+    // includes the cache.
+    syncback(&["--dry-run"], b"");
+    assert!(!cache_path.exists(), "--dry-run wrote the cache");
+    syncback(&[], b"n\n");
+    assert!(!cache_path.exists(), "a declined prompt wrote the cache");
+    assert!(!module_path.exists());
+
+    syncback(&["--non-interactive"], b"");
+    assert!(cache_path.exists(), "the cache wasn't written");
+    assert_eq!(read(&module_path), "return 1");
+}
+
+/// Runs `rojo syncback --watch` with the given extra arguments, changing the
+/// input file a few times and checking that each change is synced back.
+fn run_watch_test(args: &[&str]) {
+    let _ = env_logger::try_init();
+
+    let test_dir = tempdir().expect("couldn't create temporary directory");
+    let project_path = test_dir.path().join("project");
+    let src_path = project_path.join("src");
+    fs::create_dir_all(&src_path).unwrap();
+    fs::write(
+        project_path.join("default.project.json"),
+        r#"{ "name": "watch", "tree": { "$path": "src" } }"#,
+    )
+    .unwrap();
+
+    let input_folder = test_dir.path().join("input");
+    fs::create_dir(&input_folder).unwrap();
+    let input_path = input_folder.join("input.rbxm");
+    write_model(
+        &input_path,
+        &[("Module", "return 1"), ("Other", "return 'other'")],
+    );
+
+    let mut rojo = KillOnDrop(
+        Command::new(ROJO_PATH)
+            .args([
+                "syncback",
+                project_path.to_str().unwrap(),
+                "--input",
+                input_path.to_str().unwrap(),
+                "--non-interactive",
+                "--watch",
+            ])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Couldn't spawn syncback process"),
+    );
+
+    let module_path = src_path.join("Module.luau");
+    let other_path = src_path.join("Other.luau");
+    wait_for(&mut rojo, || {
+        read(&module_path) == "return 1" && read(&other_path) == "return 'other'"
+    });
+
+    // Each syncback has to see the files written by the one before it, or
+    // it won't know to change or remove them.
+    write_model(&input_path, &[("Module", "return 2")]);
+    wait_for(&mut rojo, || {
+        read(&module_path) == "return 2" && !other_path.exists()
+    });
+
+    write_model(
+        &input_path,
+        &[("Module", "return 3"), ("New", "return 'new'")],
+    );
+    wait_for(&mut rojo, || {
+        read(&module_path) == "return 3" && read(&src_path.join("New.luau")) == "return 'new'"
+    });
+}
+
+/// Writes a model of a Folder containing ModuleScripts with the given names
+/// and sources.
+fn write_model(path: &Path, modules: &[(&str, &str)]) {
+    let mut folder = InstanceBuilder::new("Folder").with_name("watch");
+    for (name, source) in modules {
+        folder = folder.with_child(
+            InstanceBuilder::new("ModuleScript")
+                .with_name(*name)
+                .with_property("Source", *source),
+        );
+    }
+    let dom = WeakDom::new(InstanceBuilder::new("DataModel").with_child(folder));
+
+    let mut contents = Vec::new();
+    rbx_binary::to_writer(&mut contents, &dom, dom.root().children()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+/// Polls `condition` until it holds, panicking if it doesn't within a few
+/// seconds or if Rojo exits.
+fn wait_for(rojo: &mut KillOnDrop, condition: impl Fn() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        if let Some(status) = rojo.0.try_wait().unwrap() {
+            panic!("Rojo exited early with {status}");
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    panic!("condition was not met in time");
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+use serde_json::{json, Value};
+use tempfile::tempdir;
+
+use crate::rojo_test::io_util::{get_working_dir_path, BUILD_TESTS_PATH, ROJO_PATH};
+
+#[test]
+fn sourcemap_workspace() {
+    let _ = env_logger::try_init();
+
+    let workspace_dir = tempdir().expect("couldn't create temporary directory");
+    write_workspace(
+        workspace_dir.path(),
+        json!([
+            {
+                "path": Path::new(BUILD_TESTS_PATH).join("txt"),
+                "sourcemap": "txt.sourcemap.json",
+            },
+            {
+                "path": Path::new(BUILD_TESTS_PATH).join("module_init"),
+                "sourcemap": "module_init.sourcemap.json",
+            },
+            // Projects without a sourcemap path are skipped.
+            { "path": Path::new(BUILD_TESTS_PATH).join("preprocess") },
+        ]),
+    );
+
+    let output = run_rojo(&[
+        "sourcemap",
+        workspace_dir.path().to_str().unwrap(),
+        "--include-non-scripts",
+    ]);
+    assert!(output.status.success(), "Rojo did not exit successfully");
+
+    for name in ["txt", "module_init"] {
+        let sourcemap_path = workspace_dir.path().join(format!("{name}.sourcemap.json"));
+        let contents = fs::read(&sourcemap_path)
+            .unwrap_or_else(|_| panic!("{name}'s sourcemap wasn't written"));
+        let sourcemap: Value = serde_json::from_slice(&contents).unwrap();
+        assert_eq!(sourcemap["name"], name, "{name}'s sourcemap is wrong");
+    }
+
+    let entries = fs::read_dir(workspace_dir.path()).unwrap().count();
+    assert_eq!(
+        entries, 3,
+        "only the workspace file and two sourcemaps should exist"
+    );
+}
+
+#[test]
+fn check_workspace() {
+    let _ = env_logger::try_init();
+
+    let workspace_dir = tempdir().expect("couldn't create temporary directory");
+    write_project(
+        &workspace_dir.path().join("good"),
+        json!({ "name": "good", "tree": { "$className": "Folder" } }),
+    );
+    write_project(
+        &workspace_dir.path().join("bad"),
+        json!({
+            "name": "bad",
+            "tree": {
+                "$className": "Folder",
+                "Unknown": { "$className": "NotARealClass" },
+            },
+        }),
+    );
+    write_workspace(
+        workspace_dir.path(),
+        json!([{ "path": "good" }, { "path": "bad" }]),
+    );
+
+    let output = run_rojo(&[
+        "check",
+        workspace_dir.path().to_str().unwrap(),
+        "--format",
+        "json",
+    ]);
+    assert!(
+        !output.status.success(),
+        "Rojo should fail when a project in the workspace has errors"
+    );
+
+    // Problems from every project are listed together, with paths relative
+    // to the workspace's folder.
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let problems = report["problems"].as_array().unwrap();
+    assert_eq!(problems.len(), 1, "expected one problem, got {problems:#?}");
+    assert_eq!(problems[0]["kind"], "unknownClass");
+    assert_eq!(problems[0]["instance"], "Unknown");
+    assert_eq!(problems[0]["path"], "bad/default.project.json");
+}
+
+#[test]
+fn serve_workspace_duplicate_port() {
+    let _ = env_logger::try_init();
+
+    let workspace_dir = tempdir().expect("couldn't create temporary directory");
+    write_workspace(
+        workspace_dir.path(),
+        json!([
+            {
+                "path": Path::new(BUILD_TESTS_PATH).join("txt"),
+                "servePort": 34871,
+            },
+            {
+                "path": Path::new(BUILD_TESTS_PATH).join("module_init"),
+                "servePort": 34871,
+            },
+        ]),
+    );
+
+    let output = run_rojo(&["serve", workspace_dir.path().to_str().unwrap()]);
+    assert!(!output.status.success(), "Rojo should refuse to serve");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("More than one project in the workspace uses port 34871"),
+        "unexpected error: {stderr}"
+    );
+}
+
+#[test]
+fn serve_workspace_missing_port() {
+    let _ = env_logger::try_init();
+
+    let workspace_dir = tempdir().expect("couldn't create temporary directory");
+    write_workspace(
+        workspace_dir.path(),
+        json!([
+            {
+                "path": Path::new(BUILD_TESTS_PATH).join("txt"),
+                "servePort": 34872,
+            },
+            { "path": Path::new(BUILD_TESTS_PATH).join("module_init") },
+        ]),
+    );
+
+    let output = run_rojo(&["serve", workspace_dir.path().to_str().unwrap()]);
+    assert!(!output.status.success(), "Rojo should refuse to serve");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("module_init needs a servePort"),
+        "unexpected error: {stderr}"
+    );
+}
+
+fn write_workspace(folder: &Path, projects: Value) {
+    fs::write(
+        folder.join("rojo.workspace.json"),
+        serde_json::to_string(&json!({ "projects": projects })).unwrap(),
+    )
+    .expect("Couldn't write workspace file");
+}
+
+fn write_project(folder: &Path, project: Value) {
+    fs::create_dir(folder).expect("Couldn't create project folder");
+    fs::write(
+        folder.join("default.project.json"),
+        serde_json::to_string(&project).unwrap(),
+    )
+    .expect("Couldn't write project file");
+}
+
+fn run_rojo(args: &[&str]) -> Output {
+    let output = Command::new(ROJO_PATH)
+        .args(args)
+        .env("RUST_LOG", "error")
+        .current_dir(get_working_dir_path())
+        .output()
+        .expect("Couldn't start Rojo");
+
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    output
+}